@@ -50,10 +50,9 @@ async fn main() -> anyhow::Result<()> {
     // Search for content
     info!("\nSearching for 'test'...");
     let search_request = SearchRequest {
-        query: "test".to_string(),
-        ancestor_id: None,
-        space_id: None,
         limit: Some(10),
+        source: "quick_find_input_change".to_string(),
+        ..SearchRequest::in_ancestor(page_id, "test")
     };
 
     let result = client.post(paths::SEARCH, &search_request).await;
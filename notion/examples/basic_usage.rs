@@ -7,8 +7,12 @@
 //! 4. Copy the value of `token_v2` cookie
 //!
 //! Run with: cargo run --example basic_usage -- YOUR_TOKEN
+//!
+//! Without an argument the token comes from `NOTION_TOKEN` or the config
+//! file profile (`NOTION_PROFILE` picks one).
 
 use notion_re::api::{NotionClient, paths};
+use notion_re::config::Profile;
 use serde_json::json;
 use tracing::info;
 
@@ -18,15 +22,11 @@ async fn main() -> anyhow::Result<()> {
         .with_max_level(tracing::Level::INFO)
         .init();
 
-    // Get token from command line or environment variable
-    let token = std::env::var("NOTION_TOKEN")
-        .or_else(|_| {
-            std::env::args()
-                .nth(1)
-                .ok_or_else(|| anyhow::anyhow!("No token provided. Pass token as argument or set NOTION_TOKEN env var"))
-        })?;
-
-    let client = NotionClient::from_token(token);
+    // Get token from command line, else the environment or config profile
+    let client = match std::env::args().nth(1) {
+        Some(token) => NotionClient::from_token(token),
+        None => Profile::load(None)?.client()?,
+    };
 
     // Fetch user info
     info!("Fetching user info...");
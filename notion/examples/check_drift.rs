@@ -0,0 +1,46 @@
+//! Check captured responses against the models
+//!
+//! Usage:
+//!   cargo run --example check_drift -- FIXTURE_DIR [REPORT.json]
+//!
+//! Prints a summary per model type and optionally writes the machine-readable
+//! report. Exits with an error if any drift was found, so it can run in CI.
+
+use std::collections::BTreeMap;
+
+use notion_re::drift::{check_fixture, DriftReport};
+use notion_re::fixture;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 {
+        anyhow::bail!("Usage: {} <FIXTURE_DIR> [REPORT.json]", args[0]);
+    }
+
+    let mut reports: BTreeMap<String, DriftReport> = BTreeMap::new();
+    for fixture in fixture::load_dir(&args[1])? {
+        if let Some(report) = check_fixture(&fixture) {
+            match reports.get_mut(&fixture.endpoint) {
+                Some(existing) => existing.merge(report),
+                None => {
+                    reports.insert(fixture.endpoint.clone(), report);
+                }
+            }
+        }
+    }
+
+    for (endpoint, report) in &reports {
+        println!("[{}] {}", endpoint, report);
+    }
+
+    if let Some(path) = args.get(2) {
+        std::fs::write(path, serde_json::to_string_pretty(&reports)?)?;
+        println!("Report written to {}", path);
+    }
+
+    let drifted = reports.values().filter(|r| !r.is_clean()).count();
+    if drifted > 0 {
+        anyhow::bail!("{} endpoint(s) drifted from the models", drifted);
+    }
+    Ok(())
+}
@@ -0,0 +1,52 @@
+//! Find `/api/v3` endpoints in saved Notion web-app bundles
+//!
+//! Usage:
+//!   cargo run --example discover_endpoints -- BUNDLE_DIR [PREVIOUS_REPORT] [OUTPUT]
+//!
+//! Save the app's JavaScript from DevTools (Sources > Save as) into BUNDLE_DIR.
+//! The report is written to OUTPUT (default: endpoints.json); pass the report
+//! of the previous release as PREVIOUS_REPORT to see what changed.
+
+use notion_re::discovery::DiscoveryReport;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 {
+        anyhow::bail!("Usage: {} <BUNDLE_DIR> [PREVIOUS_REPORT] [OUTPUT]", args[0]);
+    }
+
+    let report = DiscoveryReport::scan_path(&args[1])?;
+    println!("Found {} endpoints", report.endpoints.len());
+
+    println!("\nMissing from paths:");
+    for name in report.missing_from_paths() {
+        let info = &report.endpoints[&name];
+        let keys: Vec<&str> = info.argument_keys.iter().map(String::as_str).collect();
+        println!("  {} ({})", name, keys.join(", "));
+    }
+
+    println!("\nIn paths but not in the bundles:");
+    for name in report.unseen_paths() {
+        println!("  {}", name);
+    }
+
+    if let Some(previous) = args.get(2) {
+        let diff = report.diff(&DiscoveryReport::load(previous)?);
+        println!("\nSince {}:", previous);
+        for name in &diff.added {
+            println!("  + {}", name);
+        }
+        for name in &diff.removed {
+            println!("  - {}", name);
+        }
+        for (name, added, removed) in &diff.changed {
+            println!("  ~ {} (+[{}] -[{}])", name, added.join(", "), removed.join(", "));
+        }
+    }
+
+    let output = args.get(3).map(String::as_str).unwrap_or("endpoints.json");
+    report.save(output)?;
+    println!("\nReport written to {}", output);
+
+    Ok(())
+}
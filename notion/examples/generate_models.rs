@@ -0,0 +1,31 @@
+//! Infer a schema from captured responses and print serde structs for it
+//!
+//! Usage:
+//!   cargo run --example generate_models -- FIXTURE_DIR ENDPOINT [STRUCT_NAME]
+//!
+//! Example:
+//!   cargo run --example generate_models -- fixtures loadPageChunk > load_page_chunk.rs
+
+use notion_re::fixture;
+use notion_re::schema::{generate_rust, infer_endpoint};
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        anyhow::bail!("Usage: {} <FIXTURE_DIR> <ENDPOINT> [STRUCT_NAME]", args[0]);
+    }
+
+    let fixtures = fixture::load_dir(&args[1])?;
+    let endpoint = &args[2];
+    let samples = fixtures.iter().filter(|f| &f.endpoint == endpoint).count();
+    if samples == 0 {
+        anyhow::bail!("No fixtures for {} in {}", endpoint, args[1]);
+    }
+    eprintln!("Inferring {} from {} responses", endpoint, samples);
+
+    let default_name = format!("{}Response", endpoint);
+    let name = args.get(3).unwrap_or(&default_name);
+    print!("{}", generate_rust(name, &infer_endpoint(&fixtures, endpoint)));
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+//! Turn a DevTools HAR capture into fixtures
+//!
+//! Usage:
+//!   cargo run --example import_har -- CAPTURE.har [FIXTURE_DIR]
+//!
+//! In DevTools, open the Network tab, use Notion for a while, then right click
+//! and choose "Save all as HAR". Cookies and auth headers are never written.
+
+use notion_re::har::HarImport;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 {
+        anyhow::bail!("Usage: {} <CAPTURE.har> [FIXTURE_DIR]", args[0]);
+    }
+
+    let import = HarImport::from_file(&args[1])?;
+    let dir = args.get(2).map(String::as_str).unwrap_or("fixtures");
+    let written = import.write_fixtures(dir)?;
+    println!("Wrote {} fixtures to {}", written.len(), dir);
+
+    let coverage = import.coverage();
+    println!("\nCaptured:");
+    for (name, count) in &coverage.captured {
+        println!("  {} ({} exchanges)", name, count);
+    }
+    println!("\nNot captured yet:");
+    for name in &coverage.unseen {
+        println!("  {}", name);
+    }
+    println!("\nCaptured but missing from paths:");
+    for endpoint in &coverage.unknown {
+        println!("  {}", endpoint);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+//! Print version changes of blocks as they happen
+//!
+//! Usage:
+//!   NOTION_PROFILE=work cargo run --example realtime -- BLOCK_ID [BLOCK_ID...]
+//!
+//! Keeps the message-store socket open and reconnects on its own; stop with Ctrl-C.
+
+use futures::StreamExt;
+use notion_re::api::RecordPointer;
+use notion_re::config::Profile;
+use notion_re::realtime::RealtimeClient;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    let headers = Profile::load(None)?.headers()?;
+    let pointers: Vec<RecordPointer> = std::env::args().skip(1).map(|id| RecordPointer::block(&id)).collect();
+    if pointers.is_empty() {
+        anyhow::bail!("Usage: realtime <BLOCK_ID> [BLOCK_ID...]");
+    }
+
+    let mut changes = RealtimeClient::new(headers).subscribe(&pointers);
+    while let Some(change) = changes.next().await {
+        let change = change?;
+        println!("{} -> version {}", change.pointer, change.version);
+    }
+
+    Ok(())
+}
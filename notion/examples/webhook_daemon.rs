@@ -0,0 +1,39 @@
+//! Deliver webhooks for changes in watched pages and databases
+//!
+//! Usage:
+//!   NOTION_PROFILE=work cargo run --example webhook_daemon -- webhooks.json
+//!
+//! Example config:
+//!
+//! ```json
+//! {
+//!   "interval_secs": 30,
+//!   "state_dir": "webhook-state",
+//!   "dead_letter_file": "webhook-dead-letters.jsonl",
+//!   "hooks": [{
+//!     "name": "task-status",
+//!     "url": "https://example.com/notion-hook",
+//!     "secret": "change-me",
+//!     "databases": ["<collection id>"],
+//!     "filter": { "properties": ["Status"] }
+//!   }]
+//! }
+//! ```
+
+use notion_re::config::Profile;
+use notion_re::webhook::{Dispatcher, WebhookConfig};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| anyhow::anyhow!("Usage: webhook_daemon <CONFIG.json>"))?;
+    let client = Profile::load(None)?.client()?;
+
+    let config = WebhookConfig::load(&path)?;
+    Dispatcher::new(client, config)?.run().await
+}
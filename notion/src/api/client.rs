@@ -1,15 +1,88 @@
-use anyhow::{Context, Result};
-use reqwest::{Client, RequestBuilder};
-use serde::Serialize;
-use tracing::{debug, trace};
+use std::sync::Arc;
+use std::time::Duration;
 
+use anyhow::{bail, Context, Result};
+use reqwest::{header, Body, Client, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{debug, trace, warn};
+
+use super::endpoints::{
+    paths, GetRecordValuesRequest, Operation, RecordPointer, SubmitTransactionRequest,
+    SyncRecordRequest, SyncRecordValuesRequest, Transaction,
+};
+use super::transport::{self, Transport};
 use super::NotionHeaders;
+use crate::drift::{self, DriftHook};
+use crate::edit::{Journal, JournalEntry};
+use crate::models::{GetRecordValuesResponse, LoadPageChunkResponse, Record, RecordMap, SyncRecordValuesResponse};
+use crate::utils::to_uuid_format;
+
+/// Blocks asked for per `loadPageChunk` round trip
+const PAGE_CHUNK_LIMIT: u32 = 100;
+
+/// First wait after a 429 without `Retry-After`, doubled for each further retry
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest wait before a retry, whatever the attempt or `Retry-After` says
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Client-side limits on how hard the API is hit
+///
+/// The default sends requests as fast as they come and gives up on the
+/// first 429.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimit {
+    /// Most requests started per second, across clones of the client
+    pub requests_per_second: Option<f64>,
+    /// Times a request answered with 429 is sent again
+    pub max_retries: u32,
+}
+
+impl RateLimit {
+    /// Reject rates that are not positive or too small to wait for
+    pub fn validate(&self) -> Result<()> {
+        if let Some(rps) = self.requests_per_second {
+            if !(rps.is_finite() && rps > 0.0) {
+                bail!("requests_per_second must be a positive number, not {}", rps);
+            }
+            if Duration::try_from_secs_f64(1.0 / rps).is_err() {
+                bail!("requests_per_second {} is too small", rps);
+            }
+        }
+        Ok(())
+    }
+
+    /// Time between request starts; rates rejected by `validate` give none
+    fn interval(&self) -> Option<Duration> {
+        self.requests_per_second
+            .filter(|rps| *rps > 0.0)
+            .and_then(|rps| Duration::try_from_secs_f64(1.0 / rps).ok())
+    }
+
+    /// Wait before retry number `attempt + 1` when the server gives no `Retry-After`
+    fn backoff(attempt: u32) -> Duration {
+        RETRY_BASE_DELAY
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(RETRY_MAX_DELAY)
+    }
+}
 
 /// Notion API Client for reverse engineering
 #[derive(Debug, Clone)]
 pub struct NotionClient {
     client: Client,
     headers: NotionHeaders,
+    base_url: String,
+    drift_hook: Option<DriftHook>,
+    transport: Transport,
+    rate_limit: RateLimit,
+    /// Earliest time the next request may start, shared between clones
+    next_slot: Arc<Mutex<Instant>>,
+    journal: Option<Journal>,
 }
 
 impl NotionClient {
@@ -20,9 +93,66 @@ impl NotionClient {
                 .build()
                 .unwrap(),
             headers,
+            base_url: super::NOTION_API_BASE.to_string(),
+            drift_hook: None,
+            transport: Transport::Live,
+            rate_limit: RateLimit::default(),
+            next_slot: Arc::new(Mutex::new(Instant::now())),
+            journal: None,
         }
     }
 
+    /// Send requests to another API root, e.g. a local stand-in during tests
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Check typed responses against their models and report drift to `hook`
+    pub fn with_drift_hook(mut self, hook: DriftHook) -> Self {
+        self.drift_hook = Some(hook);
+        self
+    }
+
+    /// Record exchanges to, or replay them from, a fixture directory
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Space requests out and retry rate-limited ones
+    pub fn with_rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Record every submitted transaction and its inverse in `journal`
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    pub fn rate_limit(&self) -> &RateLimit {
+        &self.rate_limit
+    }
+
+    pub fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    pub fn headers(&self) -> &NotionHeaders {
+        &self.headers
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+
     /// Get authentication token from browser cookies
     ///
     /// Token is stored in `token_v2` cookie
@@ -43,9 +173,50 @@ impl NotionClient {
         req
     }
 
+    /// Send a request within the rate limit, retrying on 429 as configured
+    async fn send(&self, req: RequestBuilder) -> Result<(StatusCode, String)> {
+        let mut attempt = 0;
+        loop {
+            self.wait_for_slot().await;
+            let attempt_req = req.try_clone().context("Request body cannot be resent")?;
+            let resp = attempt_req.send().await.context("Failed to send request")?;
+            let status = resp.status();
+            if status == StatusCode::TOO_MANY_REQUESTS && attempt < self.rate_limit.max_retries {
+                let delay = retry_after(&resp)
+                    .unwrap_or_else(|| RateLimit::backoff(attempt))
+                    .min(RETRY_MAX_DELAY);
+                attempt += 1;
+                warn!("Rate limited, retry {}/{} in {:?}", attempt, self.rate_limit.max_retries, delay);
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+            let body = resp.text().await.context("Failed to read response")?;
+            return Ok((status, body));
+        }
+    }
+
+    /// Wait until the rate limit allows another request
+    async fn wait_for_slot(&self) {
+        let Some(interval) = self.rate_limit.interval() else {
+            return;
+        };
+        let start = {
+            let mut next = self.next_slot.lock().await;
+            let start = (*next).max(Instant::now());
+            *next = start + interval;
+            start
+        };
+        tokio::time::sleep_until(start).await;
+    }
+
     /// Make a GET request to Notion API
     pub async fn get<T: Serialize>(&self, path: &str, query: Option<&T>) -> Result<String> {
-        let url = format!("{}{}", super::NOTION_API_BASE, path);
+        let recorded_body = serde_json::to_value(query)?;
+        if let Transport::Replay(dir) = &self.transport {
+            return transport::replay(dir, path, &recorded_body);
+        }
+
+        let url = format!("{}{}", self.base_url, path);
         debug!("GET {}", url);
 
         let mut req = self.client.get(&url);
@@ -55,13 +226,15 @@ impl NotionClient {
             req = req.query(q);
         }
 
-        let resp = req.send().await.context("Failed to send request")?;
-        let status = resp.status();
-        let body = resp.text().await.context("Failed to read response")?;
+        let (status, body) = self.send(req).await?;
 
         trace!("Response status: {}", status);
         trace!("Response body: {}", body);
 
+        if let Transport::Record(dir) = &self.transport {
+            transport::record(dir, path, &recorded_body, status.as_u16(), &body, &self.headers.token)?;
+        }
+
         if !status.is_success() {
             anyhow::bail!("Request failed with status {}: {}", status, body);
         }
@@ -71,27 +244,198 @@ impl NotionClient {
 
     /// Make a POST request to Notion API
     pub async fn post<T: Serialize>(&self, path: &str, body: &T) -> Result<String> {
-        let url = format!("{}{}", super::NOTION_API_BASE, path);
+        let (status, resp_body) = self.post_raw(path, body).await?;
+        if !status.is_success() {
+            anyhow::bail!("Request failed with status {}: {}", status, resp_body);
+        }
+        Ok(resp_body)
+    }
+
+    /// Make a POST request, returning the status and body even when the status is an error
+    pub async fn post_raw<T: Serialize>(&self, path: &str, body: &T) -> Result<(StatusCode, String)> {
+        if let Transport::Replay(dir) = &self.transport {
+            let (status, resp_body) = transport::replay_exchange(dir, path, &serde_json::to_value(body)?)?;
+            return Ok((StatusCode::from_u16(status)?, resp_body));
+        }
+
+        let url = format!("{}{}", self.base_url, path);
         debug!("POST {}", url);
 
         let req = self.client.post(&url);
         let req = self.build_request(req).json(body);
 
-        let resp = req.send().await.context("Failed to send request")?;
-        let status = resp.status();
-        let resp_body = resp.text().await.context("Failed to read response")?;
+        let (status, resp_body) = self.send(req).await?;
 
         trace!("Response status: {}", status);
         trace!("Response body: {}", resp_body);
 
+        if let Transport::Record(dir) = &self.transport {
+            let recorded_body = serde_json::to_value(body)?;
+            transport::record(dir, path, &recorded_body, status.as_u16(), &resp_body, &self.headers.token)?;
+        }
+
+        Ok((status, resp_body))
+    }
+
+    /// PUT a file to a signed storage URL, through the transport like API calls
+    ///
+    /// Recordings are keyed by the URL without its query string, which holds
+    /// the signature.
+    pub(crate) async fn put_signed(&self, url: &str, content_type: &str, body: Body, len: u64) -> Result<()> {
+        let key = serde_json::json!({
+            "url": url.split('?').next().unwrap_or(url),
+            "contentType": content_type,
+            "length": len,
+        });
+        if let Transport::Replay(dir) = &self.transport {
+            transport::replay(dir, transport::SIGNED_UPLOAD, &key)?;
+            return Ok(());
+        }
+
+        debug!("PUT {} bytes to signed URL", len);
+        let resp = self
+            .client
+            .put(url)
+            .header("Content-Type", content_type)
+            .header("Content-Length", len)
+            .body(body)
+            .send()
+            .await
+            .context("Failed to upload file")?;
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+
+        if let Transport::Record(dir) = &self.transport {
+            transport::record(dir, transport::SIGNED_UPLOAD, &key, status.as_u16(), &text, &self.headers.token)?;
+        }
         if !status.is_success() {
-            anyhow::bail!("Request failed with status {}: {}", status, resp_body);
+            bail!("Upload failed with status {}: {}", status, text);
         }
+        Ok(())
+    }
 
-        Ok(resp_body)
+    /// Make a POST request and deserialize the JSON response
+    pub async fn post_json<T: Serialize, R: DeserializeOwned + Serialize>(&self, path: &str, body: &T) -> Result<R> {
+        let resp = self.post(path, body).await?;
+        if let Some(hook) = &self.drift_hook {
+            if let Ok(value) = serde_json::from_str(&resp) {
+                let report = drift::check::<R>(&value);
+                if !report.is_clean() {
+                    hook.call(path, &report);
+                }
+            }
+        }
+        serde_json::from_str(&resp).with_context(|| format!("Failed to parse response of {}", path))
+    }
+
+    /// Fetch the current value of each record, in the order requested
+    pub async fn get_record_values(&self, pointers: &[RecordPointer]) -> Result<Vec<Record>> {
+        let request = GetRecordValuesRequest {
+            requests: pointers.to_vec(),
+        };
+        let response: GetRecordValuesResponse = self.post_json(paths::GET_RECORD_VALUES, &request).await?;
+        Ok(response.results)
+    }
+
+    /// Fetch the records that changed since the given versions
+    pub async fn sync_record_values(&self, requests: &[SyncRecordRequest]) -> Result<RecordMap> {
+        let request = SyncRecordValuesRequest {
+            requests: requests.to_vec(),
+        };
+        let response: SyncRecordValuesResponse = self.post_json(paths::SYNC_RECORD_VALUES, &request).await?;
+        Ok(response.record_map)
+    }
+
+    /// Spaces, users and their top-level pages, merged across signed-in users
+    ///
+    /// `getSpaces` answers with one record map per user id.
+    pub async fn get_spaces(&self) -> Result<RecordMap> {
+        let response: serde_json::Value = self.post_json(paths::GET_SPACES, &serde_json::json!({})).await?;
+        let mut records = RecordMap::default();
+        for user_records in response.as_object().into_iter().flat_map(|o| o.values()) {
+            records.merge(serde_json::from_value(user_records.clone()).context("Unexpected getSpaces response")?);
+        }
+        Ok(records)
+    }
+
+    /// Load a page and its content, following the chunk cursor to the end
+    pub async fn load_page(&self, page_id: &str) -> Result<RecordMap> {
+        let page_id = to_uuid_format(page_id).unwrap_or_else(|| page_id.to_string());
+        let mut records = RecordMap::default();
+        let mut cursor = serde_json::json!({ "stack": [] });
+        for chunk_number in 0.. {
+            let body = serde_json::json!({
+                "pageId": page_id,
+                "limit": PAGE_CHUNK_LIMIT,
+                "cursor": cursor,
+                "chunkNumber": chunk_number,
+                "verticalColumns": false,
+            });
+            let response: LoadPageChunkResponse = self.post_json(paths::LOAD_PAGE_CHUNK, &body).await?;
+            records.merge(response.record_map);
+            match response.cursor {
+                Some(next) if !next.stack.is_empty() => cursor = serde_json::to_value(next)?,
+                _ => break,
+            }
+        }
+        Ok(records)
+    }
+
+    /// Submit operations as a single transaction
+    ///
+    /// With a journal the touched records are fetched first, so the entry
+    /// can undo the transaction.
+    pub async fn submit_transaction(&self, operations: Vec<Operation>) -> Result<String> {
+        if self.journal.is_none() {
+            return self.submit_with_prior(operations, &RecordMap::default()).await;
+        }
+        let mut pointers: Vec<RecordPointer> = operations.iter().map(Operation::pointer).collect();
+        pointers.sort();
+        pointers.dedup();
+        let mut prior = RecordMap::default();
+        for (pointer, record) in pointers.iter().zip(self.get_record_values(&pointers).await?) {
+            prior.table_mut(&pointer.table).insert(pointer.id.clone(), record);
+        }
+        self.submit_with_prior(operations, &prior).await
+    }
+
+    /// Submit operations, journaling them against records already fetched
+    ///
+    /// `prior` must hold the server state of every touched record; it is
+    /// ignored without a journal. A journal that cannot be written is only
+    /// logged, since the transaction has gone through by then.
+    pub async fn submit_with_prior(&self, operations: Vec<Operation>, prior: &RecordMap) -> Result<String> {
+        // Computed before submitting so an uninvertible batch is never sent
+        let entry = match &self.journal {
+            Some(_) => Some(JournalEntry::new(prior, operations.clone())?),
+            None => None,
+        };
+
+        let request = SubmitTransactionRequest {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            transactions: vec![Transaction {
+                id: uuid::Uuid::new_v4().to_string(),
+                operations,
+            }],
+        };
+        let response = self.post(paths::SUBMIT_TRANSACTION, &request).await?;
+
+        if let (Some(journal), Some(entry)) = (&self.journal, entry) {
+            match journal.append(&entry) {
+                Ok(()) => debug!("Journaled transaction as {}", entry.id),
+                Err(e) => warn!("Transaction {} was submitted but not journaled: {:#}", entry.id, e),
+            }
+        }
+        Ok(response)
     }
 }
 
+/// Delay asked for by a `Retry-After` header given in seconds
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let value = resp.headers().get(header::RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -141,4 +485,114 @@ mod tests {
         assert_eq!(headers.token, "token");
         assert_eq!(headers.user_id.as_deref(), Some("user"));
     }
+
+    #[tokio::test]
+    async fn test_load_page_follows_cursor() {
+        use axum::{routing::post, Json, Router};
+        use serde_json::{json, Value};
+
+        async fn chunk(Json(body): Json<Value>) -> Json<Value> {
+            let n = body["chunkNumber"].as_u64().unwrap();
+            let stack = if n == 0 { json!([[{ "table": "block", "id": "b1", "index": 1 }]]) } else { json!([]) };
+            Json(json!({
+                "recordMap": { "block": { format!("b{}", n): { "value": { "id": n } } } },
+                "cursor": { "stack": stack },
+            }))
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/api", listener.local_addr().unwrap());
+        let app = Router::new().route("/api/v3/loadPageChunk", post(chunk));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = NotionClient::from_token("token".to_string()).with_base_url(&base);
+        let records = client.load_page("page").await.unwrap();
+        let mut ids: Vec<&String> = records.block.keys().collect();
+        ids.sort();
+        assert_eq!(ids, vec!["b0", "b1"]);
+    }
+
+    #[test]
+    fn test_rate_limit_bounds() {
+        assert_eq!(RateLimit::backoff(0), RETRY_BASE_DELAY);
+        assert_eq!(RateLimit::backoff(2), RETRY_BASE_DELAY * 4);
+        assert_eq!(RateLimit::backoff(40), RETRY_MAX_DELAY);
+
+        let limit = |rps| RateLimit {
+            requests_per_second: Some(rps),
+            max_retries: 0,
+        };
+        assert_eq!(limit(4.0).interval(), Some(Duration::from_millis(250)));
+        assert!(limit(4.0).validate().is_ok());
+        for rps in [0.0, -1.0, f64::NAN, f64::INFINITY, 1e-300] {
+            assert!(limit(rps).validate().is_err(), "{} accepted", rps);
+        }
+        assert_eq!(limit(1e-300).interval(), None);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_spaces_and_retries() {
+        use crate::emulator::{Emulator, Fault};
+        use serde_json::json;
+
+        let emulator = Emulator::new("secret")
+            .with_fault(Fault::status(429).on(paths::LOAD_PAGE_CHUNK).times(1))
+            .start()
+            .await
+            .unwrap();
+        let body = json!({ "pageId": "p", "limit": 1 });
+
+        let client = emulator.client();
+        assert!(client.post(paths::LOAD_PAGE_CHUNK, &body).await.unwrap_err().to_string().contains("429"));
+
+        emulator.inject(Fault::status(429).on(paths::LOAD_PAGE_CHUNK).times(1));
+        let limited = client.with_rate_limit(RateLimit {
+            requests_per_second: Some(20.0),
+            max_retries: 1,
+        });
+        let started = Instant::now();
+        limited.post(paths::LOAD_PAGE_CHUNK, &body).await.unwrap();
+        let clone = limited.clone();
+        let (a, b) = tokio::join!(limited.post(paths::LOAD_PAGE_CHUNK, &body), clone.post(paths::LOAD_PAGE_CHUNK, &body));
+        a.unwrap();
+        b.unwrap();
+        // One retry after the base delay, then four requests 50ms apart
+        assert!(started.elapsed() >= RETRY_BASE_DELAY + Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_journaled_submit_rolls_back() {
+        use crate::edit::{rollback, Journal};
+        use crate::emulator::Emulator;
+        use serde_json::json;
+
+        let prior = json!({ "id": "a", "version": 1, "alive": true, "properties": { "title": [["A"]] } });
+        let mut records = RecordMap::default();
+        records.block.insert("a".to_string(), Record::new(prior.clone()));
+        let emulator = Emulator::new("secret").with_records(records).start().await.unwrap();
+        let tmp = tempfile::tempdir().unwrap();
+        let journal = Journal::new(tmp.path().join("journal.jsonl"));
+        let client = emulator.client().with_journal(journal.clone());
+
+        client
+            .submit_transaction(vec![
+                Operation::update("block", "a", &[], json!({ "alive": false, "type": "text" })),
+                Operation::set("block", "a", &["format", "page_icon"], json!("x")),
+                Operation::set_parent("block", "a", "p", "block"),
+                Operation::set("block", "b", &[], json!({ "id": "b", "alive": true })),
+            ])
+            .await
+            .unwrap();
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+
+        rollback(&client, &entries[0]).await.unwrap();
+        let after = emulator.records();
+        let mut restored = after.get("block", "a").unwrap().clone();
+        // Rolling back is itself a change, so only the version moves on
+        restored["version"] = prior["version"].clone();
+        assert_eq!(restored, prior);
+        assert_eq!(after.get("block", "b").unwrap()["alive"], json!(false));
+        assert_eq!(journal.entries().unwrap().len(), 2);
+    }
 }
@@ -1,7 +1,7 @@
-/// Known Notion API endpoints for reverse engineering
-///
-/// Notion uses a REST-ish API with various endpoints for different operations.
-/// Many endpoints are undocumented and may change without notice.
+//! Known Notion API endpoints for reverse engineering
+//!
+//! Notion uses a REST-ish API with various endpoints for different operations.
+//! Many endpoints are undocumented and may change without notice.
 
 pub mod paths {
     // Authentication & User
@@ -18,6 +18,7 @@ pub mod paths {
     pub const GET_BLOCK: &str = "/v3/getBlock";
     pub const GET_BLOCKS: &str = "/v3/getBlocks";
     pub const GET_RECORD_VALUES: &str = "/v3/getRecordValues";
+    pub const SYNC_RECORD_VALUES: &str = "/v3/syncRecordValues";
     pub const QUERY_COLLECTION: &str = "/v3/queryCollection";
     pub const QUERY_COLLECTION_VIEW: &str = "/v3/queryCollectionView";
 
@@ -40,12 +41,62 @@ pub mod paths {
     // Upload
     pub const UPLOAD_FILE: &str = "/v3/uploadFile";
     pub const GET_SIGNED_URLS: &str = "/v3/getSignedUrls";
+    pub const GET_UPLOAD_FILE_URL: &str = "/v3/getUploadFileUrl";
+
+    // History
+    pub const GET_SNAPSHOTS_LIST: &str = "/v3/getSnapshotsList";
+    pub const GET_SNAPSHOT_CONTENTS: &str = "/v3/getSnapshotContents";
+    pub const GET_ACTIVITY_LOG: &str = "/v3/getActivityLog";
 
     // Analytics & Telemetry
     pub const SEND_EVENT: &str = "/v3/sendEvent";
+
+    /// Every known endpoint as `(constant name, path)`
+    pub const ALL: &[(&str, &str)] = &[
+        ("GET_USER", GET_USER),
+        ("GET_USERS", GET_USERS),
+        ("GET_USER_EMAIL", GET_USER_EMAIL),
+        ("GET_SPACES", GET_SPACES),
+        ("CREATE_SPACE", CREATE_SPACE),
+        ("LOAD_PAGE_CHUNK", LOAD_PAGE_CHUNK),
+        ("GET_BLOCK", GET_BLOCK),
+        ("GET_BLOCKS", GET_BLOCKS),
+        ("GET_RECORD_VALUES", GET_RECORD_VALUES),
+        ("SYNC_RECORD_VALUES", SYNC_RECORD_VALUES),
+        ("QUERY_COLLECTION", QUERY_COLLECTION),
+        ("QUERY_COLLECTION_VIEW", QUERY_COLLECTION_VIEW),
+        ("SUBMIT_TRANSACTION", SUBMIT_TRANSACTION),
+        ("UPDATE_BLOCK", UPDATE_BLOCK),
+        ("SEARCH", SEARCH),
+        ("SEARCH_BLOCKS", SEARCH_BLOCKS),
+        ("GET_COLLECTION", GET_COLLECTION),
+        ("GET_COLLECTION_VIEW", GET_COLLECTION_VIEW),
+        ("EXPORT_PAGE", EXPORT_PAGE),
+        ("EXPORT_MATH", EXPORT_MATH),
+        ("UPLOAD_FILE", UPLOAD_FILE),
+        ("GET_SIGNED_URLS", GET_SIGNED_URLS),
+        ("GET_UPLOAD_FILE_URL", GET_UPLOAD_FILE_URL),
+        ("GET_SNAPSHOTS_LIST", GET_SNAPSHOTS_LIST),
+        ("GET_SNAPSHOT_CONTENTS", GET_SNAPSHOT_CONTENTS),
+        ("GET_ACTIVITY_LOG", GET_ACTIVITY_LOG),
+        ("SEND_EVENT", SEND_EVENT),
+    ];
+
+    /// Path for `getSpaces`, `GET_SPACES` or `/v3/getSpaces`
+    ///
+    /// Names not in [`ALL`] are assumed to be v3 endpoints.
+    pub fn resolve(endpoint: &str) -> String {
+        if endpoint.starts_with('/') {
+            return endpoint.to_string();
+        }
+        ALL.iter()
+            .find(|(name, path)| name.eq_ignore_ascii_case(endpoint) || path.strip_prefix("/v3/") == Some(endpoint))
+            .map(|(_, path)| path.to_string())
+            .unwrap_or_else(|| format!("/v3/{}", endpoint))
+    }
 }
 
-/// Request body structures for various endpoints
+// Request body structures for various endpoints
 
 #[derive(Debug, serde::Serialize)]
 pub struct LoadPageChunkRequest {
@@ -60,12 +111,138 @@ pub struct Cursor {
     pub stack: Vec<serde_json::Value>,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SearchRequest {
+    #[serde(rename = "type")]
+    pub search_type: SearchType,
     pub query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub ancestor_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub space_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub limit: Option<u32>,
+    pub filters: SearchFilters,
+    pub sort: SearchSort,
+    /// UI surface the search came from, e.g. `quick_find_input_change`
+    pub source: String,
+}
+
+impl SearchRequest {
+    /// Search a whole space
+    pub fn in_space(space_id: &str, query: &str) -> Self {
+        Self {
+            space_id: Some(space_id.to_string()),
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Search below a page
+    pub fn in_ancestor(ancestor_id: &str, query: &str) -> Self {
+        Self {
+            search_type: SearchType::BlocksInAncestor,
+            ancestor_id: Some(ancestor_id.to_string()),
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub enum SearchType {
+    #[default]
+    BlocksInSpace,
+    BlocksInAncestor,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchFilters {
+    pub is_deleted_only: bool,
+    pub exclude_templates: bool,
+    pub navigable_block_content_only: bool,
+    pub require_edit_permissions: bool,
+    pub ancestors: Vec<String>,
+    pub created_by: Vec<String>,
+    pub edited_by: Vec<String>,
+    pub last_edited_time: DateRangeFilter,
+    pub created_time: DateRangeFilter,
+    pub in_teams: Vec<String>,
+}
+
+impl Default for SearchFilters {
+    fn default() -> Self {
+        Self {
+            is_deleted_only: false,
+            exclude_templates: false,
+            navigable_block_content_only: true,
+            require_edit_permissions: false,
+            ancestors: Vec::new(),
+            created_by: Vec::new(),
+            edited_by: Vec::new(),
+            last_edited_time: DateRangeFilter::default(),
+            created_time: DateRangeFilter::default(),
+            in_teams: Vec::new(),
+        }
+    }
+}
+
+/// Serializes to `{}` when unbounded, as the web app sends it
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct DateRangeFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starting: Option<DateBound>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ending: Option<DateBound>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DateBound {
+    #[serde(rename = "type")]
+    pub bound_type: String,
+    /// `YYYY-MM-DD`
+    pub start_date: String,
+}
+
+impl DateBound {
+    pub fn date(start_date: &str) -> Self {
+        Self {
+            bound_type: "date".to_string(),
+            start_date: start_date.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SearchSort {
+    /// `relevance`, `lastEdited`, `created` or `title`
+    pub field: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction: Option<String>,
+}
+
+impl SearchSort {
+    pub fn relevance() -> Self {
+        Self {
+            field: "relevance".to_string(),
+            direction: None,
+        }
+    }
+
+    pub fn by(field: &str, descending: bool) -> Self {
+        Self {
+            field: field.to_string(),
+            direction: Some(if descending { "desc" } else { "asc" }.to_string()),
+        }
+    }
+}
+
+impl Default for SearchSort {
+    fn default() -> Self {
+        Self::relevance()
+    }
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -79,18 +256,123 @@ pub struct GetBlocksRequest {
 }
 
 #[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetUploadFileUrlRequest {
+    pub bucket: String,
+    pub name: String,
+    pub content_type: String,
+    pub record: Option<UploadRecord>,
+}
+
+/// Record an upload will be attached to
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadRecord {
+    pub table: String,
+    pub id: String,
+    pub space_id: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct GetRecordValuesRequest {
+    pub requests: Vec<RecordPointer>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SyncRecordValuesRequest {
+    pub requests: Vec<SyncRecordRequest>,
+}
+
+/// A record plus the version we already have (`-1` for none)
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SyncRecordRequest {
+    pub pointer: RecordPointer,
+    pub version: i64,
+}
+
+impl SyncRecordRequest {
+    pub fn new(pointer: RecordPointer, known_version: Option<u64>) -> Self {
+        Self {
+            pointer,
+            version: known_version.map_or(-1, |v| v as i64),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSnapshotsListRequest {
+    pub block_id: String,
+    pub size: u32,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct GetSnapshotContentsRequest {
+    pub snapshot: SnapshotPointer,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotPointer {
+    pub id: String,
+    pub space_id: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetActivityLogRequest {
+    pub space_id: String,
+    /// Restrict the log to one page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub navigable_block: Option<NavigableBlock>,
+    pub limit: u32,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct NavigableBlock {
+    pub id: String,
+}
+
+/// Identifies a record by table and id
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct RecordPointer {
+    pub table: String,
+    pub id: String,
+}
+
+impl RecordPointer {
+    pub fn new(table: &str, id: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            id: id.to_string(),
+        }
+    }
+
+    pub fn block(id: &str) -> Self {
+        Self::new("block", id)
+    }
+}
+
+impl std::fmt::Display for RecordPointer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.table, self.id)
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SubmitTransactionRequest {
     pub request_id: String,
     pub transactions: Vec<Transaction>,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Transaction {
     pub id: String,
     pub operations: Vec<Operation>,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Operation {
     pub id: String,
     pub table: String,
@@ -98,3 +380,77 @@ pub struct Operation {
     pub command: String,
     pub args: serde_json::Value,
 }
+
+impl Operation {
+    /// The record this operation touches
+    pub fn pointer(&self) -> RecordPointer {
+        RecordPointer::new(&self.table, &self.id)
+    }
+
+    pub fn new(table: &str, id: &str, path: &[&str], command: &str, args: serde_json::Value) -> Self {
+        Self {
+            id: id.to_string(),
+            table: table.to_string(),
+            path: path.iter().map(|p| p.to_string()).collect(),
+            command: command.to_string(),
+            args,
+        }
+    }
+
+    /// Replace the value at `path` (the whole record when `path` is empty)
+    pub fn set(table: &str, id: &str, path: &[&str], args: serde_json::Value) -> Self {
+        Self::new(table, id, path, "set", args)
+    }
+
+    /// Shallow-merge the keys of `args` into the object at `path`
+    pub fn update(table: &str, id: &str, path: &[&str], args: serde_json::Value) -> Self {
+        Self::new(table, id, path, "update", args)
+    }
+
+    /// Insert `item` into the list at `path` after `after`, or at the end
+    pub fn list_after(table: &str, id: &str, path: &[&str], item: &str, after: Option<&str>) -> Self {
+        let mut args = serde_json::json!({ "id": item });
+        if let Some(after) = after {
+            args["after"] = after.into();
+        }
+        Self::new(table, id, path, "listAfter", args)
+    }
+
+    /// Insert `item` into the list at `path` before `before`, or at the start
+    pub fn list_before(table: &str, id: &str, path: &[&str], item: &str, before: Option<&str>) -> Self {
+        let mut args = serde_json::json!({ "id": item });
+        if let Some(before) = before {
+            args["before"] = before.into();
+        }
+        Self::new(table, id, path, "listBefore", args)
+    }
+
+    /// Remove `item` from the list at `path`
+    pub fn list_remove(table: &str, id: &str, path: &[&str], item: &str) -> Self {
+        Self::new(table, id, path, "listRemove", serde_json::json!({ "id": item }))
+    }
+
+    /// Point the record at a new parent
+    pub fn set_parent(table: &str, id: &str, parent_id: &str, parent_table: &str) -> Self {
+        Self::new(
+            table,
+            id,
+            &[],
+            "setParent",
+            serde_json::json!({ "parentId": parent_id, "parentTable": parent_table }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_paths() {
+        assert_eq!(paths::resolve("GET_SPACES"), paths::GET_SPACES);
+        assert_eq!(paths::resolve("getSpaces"), paths::GET_SPACES);
+        assert_eq!(paths::resolve("/v3/getSpaces"), paths::GET_SPACES);
+        assert_eq!(paths::resolve("someNewEndpoint"), "/v3/someNewEndpoint");
+    }
+}
@@ -1,8 +1,10 @@
 mod client;
 pub mod endpoints;
+mod transport;
 
-pub use client::NotionClient;
+pub use client::{NotionClient, RateLimit};
 pub use endpoints::*;
+pub use transport::{normalize_request, scrub_response, Transport};
 
 /// Notion API base URL
 pub const NOTION_API_BASE: &str = "https://www.notion.so/api";
@@ -0,0 +1,288 @@
+//! Record/replay transport for offline tests
+//!
+//! Recorded exchanges are ordinary fixtures (see `crate::fixture`), keyed by
+//! the request body with volatile fields removed.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use tracing::debug;
+
+use crate::fixture::{endpoint_name, Fixture, REDACTED};
+use crate::utils::to_uuid_format;
+
+/// Request fields that change on every call and must not affect matching
+const VOLATILE_KEYS: [&str; 4] = [
+    "requestId",
+    "request_id",
+    "searchSessionId",
+    "searchSessionFlowNumber",
+];
+
+/// Fixture endpoint for file uploads to signed storage URLs, outside the API
+pub(crate) const SIGNED_UPLOAD: &str = "signedUpload";
+
+/// Response fields holding credentials
+const SECRET_KEYS: [&str; 3] = ["token", "token_v2", "file_token"];
+
+/// How `NotionClient` talks to the API
+///
+/// `Record` behaves like `Live` but also stores every exchange as a fixture
+/// in the given directory. `Replay` never touches the network: it answers
+/// from those fixtures and fails on any request it has no recording for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Transport {
+    #[default]
+    Live,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+/// Request body with volatile fields removed, used as the fixture key
+///
+/// Record ids in transactions are often freshly generated, so each distinct
+/// UUID used as an operation's `id` or `args.id` is replaced, wherever it
+/// appears in the operations, by a placeholder numbered in order of first
+/// appearance.
+pub fn normalize_request(body: &Value) -> Value {
+    let mut body = body.clone();
+    scrub(&mut body, &|key, value| {
+        if VOLATILE_KEYS.contains(&key) {
+            *value = Value::Null;
+        }
+    });
+    // Transaction ids are fresh UUIDs on every submit
+    if let Some(transactions) = body.get_mut("transactions").and_then(Value::as_array_mut) {
+        for tx in transactions {
+            if let Some(id) = tx.get_mut("id") {
+                *id = Value::Null;
+            }
+            if let Some(operations) = tx.get_mut("operations") {
+                number_record_ids(operations);
+            }
+        }
+    }
+    // Older clients send the operations directly
+    if let Some(operations) = body.get_mut("operations") {
+        number_record_ids(operations);
+    }
+    body
+}
+
+fn number_record_ids(operations: &mut Value) {
+    let mut numbers: HashMap<String, String> = HashMap::new();
+    for op in operations.as_array().into_iter().flatten() {
+        let ids = [op.get("id"), op.get("args").and_then(|a| a.get("id"))];
+        for id in ids.into_iter().flatten().filter_map(Value::as_str) {
+            if to_uuid_format(id).is_some() && !numbers.contains_key(id) {
+                let placeholder = format!("<id {}>", numbers.len() + 1);
+                numbers.insert(id.to_string(), placeholder);
+            }
+        }
+    }
+    if !numbers.is_empty() {
+        replace_ids(operations, &numbers);
+    }
+}
+
+fn replace_ids(value: &mut Value, numbers: &HashMap<String, String>) {
+    match value {
+        Value::String(s) => {
+            if let Some(placeholder) = numbers.get(s.as_str()) {
+                *s = placeholder.clone();
+            }
+        }
+        Value::Object(map) => map.values_mut().for_each(|v| replace_ids(v, numbers)),
+        Value::Array(items) => items.iter_mut().for_each(|v| replace_ids(v, numbers)),
+        _ => {}
+    }
+}
+
+/// Remove credentials from a response, including any copy of our own token
+pub fn scrub_response(body: &mut Value, token: &str) {
+    scrub(body, &|key, value| {
+        if SECRET_KEYS.contains(&key) && !value.is_null() {
+            *value = Value::String(REDACTED.to_string());
+        }
+    });
+    if !token.is_empty() {
+        replace_strings(body, token);
+    }
+}
+
+fn scrub(value: &mut Value, f: &dyn Fn(&str, &mut Value)) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                f(key, child);
+                scrub(child, f);
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| scrub(v, f)),
+        _ => {}
+    }
+}
+
+fn replace_strings(value: &mut Value, secret: &str) {
+    match value {
+        Value::String(s) if s.contains(secret) => *s = s.replace(secret, REDACTED),
+        Value::Object(map) => map.values_mut().for_each(|v| replace_strings(v, secret)),
+        Value::Array(items) => items.iter_mut().for_each(|v| replace_strings(v, secret)),
+        _ => {}
+    }
+}
+
+fn fixture_endpoint(path: &str) -> Result<&str> {
+    if path == SIGNED_UPLOAD {
+        return Ok(SIGNED_UPLOAD);
+    }
+    endpoint_name(path).with_context(|| format!("Cannot record non-v3 path {}", path))
+}
+
+/// Store one exchange; returns the fixture file written
+pub(crate) fn record(
+    dir: &Path,
+    path: &str,
+    body: &Value,
+    status: u16,
+    response: &str,
+    token: &str,
+) -> Result<PathBuf> {
+    let mut response =
+        serde_json::from_str(response).unwrap_or_else(|_| Value::String(response.to_string()));
+    scrub_response(&mut response, token);
+
+    let fixture = Fixture {
+        endpoint: fixture_endpoint(path)?.to_string(),
+        status,
+        request: normalize_request(body),
+        response,
+    };
+    let file = fixture.save(dir)?;
+    debug!("Recorded {} to {}", path, file.display());
+    Ok(file)
+}
+
+/// Answer a request from recorded fixtures
+pub(crate) fn replay(dir: &Path, path: &str, body: &Value) -> Result<String> {
+    let (status, body) = replay_exchange(dir, path, body)?;
+    if !(200..300).contains(&status) {
+        bail!("Request failed with status {}: {}", status, body);
+    }
+    Ok(body)
+}
+
+/// Look up a recorded exchange, returning its status and body whatever the status
+pub(crate) fn replay_exchange(dir: &Path, path: &str, body: &Value) -> Result<(u16, String)> {
+    let probe = Fixture {
+        endpoint: fixture_endpoint(path)?.to_string(),
+        status: 0,
+        request: normalize_request(body),
+        response: Value::Null,
+    };
+    let file = probe.path_in(dir);
+    let data = std::fs::read(&file).with_context(|| {
+        format!(
+            "No recording for {} with body {} (expected {})",
+            path,
+            serde_json::to_string(&probe.request).unwrap_or_default(),
+            file.display()
+        )
+    })?;
+    let fixture: Fixture = serde_json::from_slice(&data)
+        .with_context(|| format!("Invalid fixture {}", file.display()))?;
+    debug!("Replayed {} from {}", path, file.display());
+
+    let body = match fixture.response {
+        Value::String(s) => s,
+        other => serde_json::to_string(&other)?,
+    };
+    Ok((fixture.status, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_normalize_ignores_volatile_fields() {
+        let a = json!({ "requestId": "1", "transactions": [{ "id": "x", "operations": [] }] });
+        let b = json!({ "requestId": "2", "transactions": [{ "id": "y", "operations": [] }] });
+        assert_eq!(normalize_request(&a), normalize_request(&b));
+    }
+
+    #[test]
+    fn test_normalize_numbers_record_ids() {
+        let submit = |new: &str| {
+            json!({ "transactions": [{ "id": new, "operations": [
+                { "table": "block", "id": new, "path": [], "command": "set", "args": { "id": new, "type": "text" } },
+                { "table": "block", "id": "page", "path": ["content"], "command": "listAfter", "args": { "id": new } },
+            ] }] })
+        };
+        let a = normalize_request(&submit("11111111-1111-1111-1111-111111111111"));
+        let b = normalize_request(&submit("22222222-2222-2222-2222-222222222222"));
+        assert_eq!(a, b);
+        let ops = &a["transactions"][0]["operations"];
+        assert_eq!(ops[0]["id"], json!("<id 1>"));
+        assert_eq!(ops[1]["id"], json!("page"));
+        assert_eq!(ops[1]["args"]["id"], json!("<id 1>"));
+    }
+
+    #[test]
+    fn test_scrub_response() {
+        let mut body =
+            json!({ "user": { "token": "abc" }, "url": "https://x/?t=my-secret", "n": 1 });
+        scrub_response(&mut body, "my-secret");
+        assert_eq!(body["user"]["token"], json!(REDACTED));
+        assert_eq!(body["url"], json!("https://x/?t=[REDACTED]"));
+        assert_eq!(body["n"], json!(1));
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay_offline() {
+        use crate::api::{paths, NotionClient};
+        use axum::{routing::post, Json, Router};
+
+        async fn load(Json(body): Json<Value>) -> Json<Value> {
+            Json(json!({ "pageId": body["pageId"], "owner": { "token_v2": "live-token", "note": "sent live-token" } }))
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/api", listener.local_addr().unwrap());
+        let app = Router::new().route("/api/v3/loadPageChunk", post(load));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let recorder = NotionClient::from_token("live-token".to_string())
+            .with_base_url(&base)
+            .with_transport(Transport::Record(dir.to_path_buf()));
+        let live = recorder
+            .post(paths::LOAD_PAGE_CHUNK, &json!({ "pageId": "p1", "requestId": "a" }))
+            .await
+            .unwrap();
+        assert!(live.contains("live-token"));
+
+        // Unroutable base URL proves replay never hits the network
+        let replayer = NotionClient::from_token("other".to_string())
+            .with_base_url("http://127.0.0.1:1/api")
+            .with_transport(Transport::Replay(dir.to_path_buf()));
+        let replayed = replayer
+            .post(paths::LOAD_PAGE_CHUNK, &json!({ "pageId": "p1", "requestId": "b" }))
+            .await
+            .unwrap();
+        assert!(!replayed.contains("live-token"));
+        let replayed: Value = serde_json::from_str(&replayed).unwrap();
+        assert_eq!(replayed["pageId"], json!("p1"));
+        assert_eq!(replayed["owner"]["note"], json!("sent [REDACTED]"));
+
+        let missing = replayer
+            .post(paths::LOAD_PAGE_CHUNK, &json!({ "pageId": "p2" }))
+            .await
+            .unwrap_err();
+        assert!(missing.to_string().contains("No recording"));
+    }
+}
@@ -0,0 +1,169 @@
+//! Local copies of loaded pages
+//!
+//! Each page is stored as `<dir>/pages/<page-id>.json` holding its full
+//! record map, so commands can show a page again without a token or a
+//! network connection.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{plain_text, RecordMap};
+use crate::utils::to_uuid_format;
+
+/// A cached page with its record map
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedPage {
+    pub page_id: String,
+    pub title: String,
+    pub cached_at: DateTime<Utc>,
+    pub record_map: RecordMap,
+}
+
+/// What `list` reports for each cached page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub page_id: String,
+    pub title: String,
+    pub cached_at: DateTime<Utc>,
+    pub blocks: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PageCache {
+    dir: PathBuf,
+}
+
+impl PageCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// `notion-re` in the platform cache directory (`~/.cache` on Linux)
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|d| d.join("notion-re"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn page_path(&self, page_id: &str) -> PathBuf {
+        let id = to_uuid_format(page_id).unwrap_or_else(|| page_id.to_string());
+        self.dir.join("pages").join(format!("{}.json", id))
+    }
+
+    /// Store a page, replacing any previous copy
+    pub fn put(&self, page_id: &str, record_map: &RecordMap) -> Result<PathBuf> {
+        let page_id = to_uuid_format(page_id).unwrap_or_else(|| page_id.to_string());
+        let title = record_map
+            .get("block", &page_id)
+            .and_then(|b| b.pointer("/properties/title"))
+            .map(plain_text)
+            .unwrap_or_default();
+        let page = CachedPage {
+            page_id: page_id.clone(),
+            title,
+            cached_at: Utc::now(),
+            record_map: record_map.clone(),
+        };
+        let path = self.page_path(&page_id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        fs::write(&path, serde_json::to_vec(&page)?).with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    pub fn get(&self, page_id: &str) -> Result<Option<CachedPage>> {
+        let path = self.page_path(page_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let page = serde_json::from_slice(&data).with_context(|| format!("Invalid cache file {}", path.display()))?;
+        Ok(Some(page))
+    }
+
+    /// Every cached page, most recently cached first
+    pub fn list(&self) -> Result<Vec<CacheEntry>> {
+        let dir = self.dir.join("pages");
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if let Some(page) = self.get(id)? {
+                entries.push(CacheEntry {
+                    page_id: page.page_id,
+                    title: page.title,
+                    cached_at: page.cached_at,
+                    blocks: page.record_map.block.len(),
+                });
+            }
+        }
+        entries.sort_by_key(|e| std::cmp::Reverse(e.cached_at));
+        Ok(entries)
+    }
+
+    /// Drop one page; returns whether it was cached
+    pub fn remove(&self, page_id: &str) -> Result<bool> {
+        let path = self.page_path(page_id);
+        if !path.exists() {
+            return Ok(false);
+        }
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        Ok(true)
+    }
+
+    /// Drop every page; returns how many there were
+    pub fn clear(&self) -> Result<usize> {
+        let entries = self.list()?;
+        for entry in &entries {
+            self.remove(&entry.page_id)?;
+        }
+        Ok(entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Record;
+    use serde_json::json;
+
+    const PAGE: &str = "12345678-90ab-cdef-1234-567890abcdef";
+
+    #[test]
+    fn test_round_trip_by_either_id_form() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let cache = PageCache::new(dir);
+        let mut records = RecordMap::default();
+        records
+            .block
+            .insert(PAGE.to_string(), Record::new(json!({ "type": "page", "properties": { "title": [["Notes"]] } })));
+
+        cache.put("1234567890abcdef1234567890abcdef", &records).unwrap();
+        let page = cache.get(PAGE).unwrap().unwrap();
+        assert_eq!(page.title, "Notes");
+        assert_eq!(page.record_map.block.len(), 1);
+
+        let entries = cache.list().unwrap();
+        assert_eq!((entries[0].page_id.as_str(), entries[0].blocks), (PAGE, 1));
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert!(cache.get(PAGE).unwrap().is_none());
+    }
+}
@@ -0,0 +1,535 @@
+//! Database (collection) helpers
+//!
+//! Notion stores database rows as `page` blocks whose `properties` are keyed by
+//! opaque schema ids and encoded as rich text. `Collection` resolves property
+//! names through the schema and produces the operations the web app would send.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::api::endpoints::Operation;
+use crate::models::{plain_text, RecordMap};
+use crate::utils::to_uuid_format;
+
+mod query;
+
+pub use query::{Aggregation, Aggregator, Condition, Filter, QueryBuilder, QueryResult, Row, Sort};
+
+/// One entry of a collection `schema`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PropertySchema {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub property_type: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<SelectOption>,
+    /// Target collection of a `relation` property
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SelectOption {
+    pub id: String,
+    #[serde(default)]
+    pub color: String,
+    pub value: String,
+}
+
+/// A property value before it is encoded for the schema
+///
+/// Serializes untagged, e.g. `"Done"`, `3.5` or `{"start": "2024-01-01", "end": null}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum PropertyValue {
+    Text(String),
+    Number(f64),
+    Checkbox(bool),
+    Select(String),
+    MultiSelect(Vec<String>),
+    /// `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM`, with an optional end of the range
+    Date { start: String, end: Option<String> },
+    /// Page ids of rows in the related collection
+    Relation(Vec<String>),
+    /// User ids
+    Person(Vec<String>),
+}
+
+/// Operations for a row change, plus the id of the row they touch
+#[derive(Debug, Clone)]
+pub struct RowOperations {
+    pub row_id: String,
+    pub operations: Vec<Operation>,
+}
+
+/// A database with its schema, ready to build row operations
+#[derive(Debug, Clone)]
+pub struct Collection {
+    pub id: String,
+    pub space_id: Option<String>,
+    pub schema: BTreeMap<String, PropertySchema>,
+    create_missing_options: bool,
+    known_rows: BTreeMap<String, Option<String>>,
+}
+
+impl Collection {
+    /// Read the collection and its schema from loaded records
+    ///
+    /// Row blocks found in `records` are remembered so relation targets can be
+    /// checked against the collection they should belong to.
+    pub fn from_record_map(records: &RecordMap, collection_id: &str) -> Result<Self> {
+        let value = records
+            .get("collection", collection_id)
+            .with_context(|| format!("Collection {} is not in the record map", collection_id))?;
+        let schema = serde_json::from_value(value.get("schema").cloned().unwrap_or(json!({})))
+            .context("Failed to parse collection schema")?;
+
+        let known_rows = records
+            .block
+            .iter()
+            .map(|(id, r)| {
+                let parent = r.value.get("parent_id").and_then(Value::as_str).map(str::to_string);
+                (to_uuid_format(id).unwrap_or_else(|| id.clone()), parent)
+            })
+            .collect();
+
+        Ok(Self {
+            id: collection_id.to_string(),
+            space_id: value.get("space_id").and_then(Value::as_str).map(str::to_string),
+            schema,
+            create_missing_options: false,
+            known_rows,
+        })
+    }
+
+    /// Add unknown select options to the schema instead of rejecting them
+    pub fn create_missing_options(mut self, create: bool) -> Self {
+        self.create_missing_options = create;
+        self
+    }
+
+    /// Resolve a property name (or raw schema id) to its schema id
+    pub fn property_id(&self, name: &str) -> Option<&str> {
+        self.schema
+            .iter()
+            .find(|(_, p)| p.name == name)
+            .or_else(|| self.schema.get_key_value(name))
+            .map(|(id, _)| id.as_str())
+    }
+
+    /// Decode a stored property back into a `PropertyValue`
+    pub fn decode(&self, prop_id: &str, raw: &Value) -> Option<PropertyValue> {
+        let schema = self.schema.get(prop_id)?;
+        let text = plain_text(raw);
+        let annotations = |kind: &str| -> Vec<&Value> {
+            raw.as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|seg| seg.get(1)?.as_array())
+                .flatten()
+                .filter(|a| a.get(0).and_then(Value::as_str) == Some(kind))
+                .collect()
+        };
+        let mention_ids = |kind: &str| -> Vec<String> {
+            annotations(kind)
+                .into_iter()
+                .filter_map(|a| a.get(1)?.as_str().map(str::to_string))
+                .collect()
+        };
+
+        let value = match schema.property_type.as_str() {
+            "number" => PropertyValue::Number(text.trim().parse().ok()?),
+            "checkbox" => PropertyValue::Checkbox(text == "Yes"),
+            "select" => PropertyValue::Select(text),
+            "multi_select" => PropertyValue::MultiSelect(
+                text.split(',').filter(|s| !s.is_empty()).map(str::to_string).collect(),
+            ),
+            "date" => {
+                let date = annotations("d").into_iter().next()?.get(1)?;
+                let part = |date_key: &str, time_key: &str| {
+                    let day = date.get(date_key)?.as_str()?;
+                    Some(match date.get(time_key).and_then(Value::as_str) {
+                        Some(time) => format!("{}T{}", day, time),
+                        None => day.to_string(),
+                    })
+                };
+                PropertyValue::Date {
+                    start: part("start_date", "start_time")?,
+                    end: part("end_date", "end_time"),
+                }
+            }
+            "relation" => PropertyValue::Relation(mention_ids("p")),
+            "person" => PropertyValue::Person(mention_ids("u")),
+            _ => PropertyValue::Text(text),
+        };
+
+        Some(value)
+    }
+
+    /// Build the operations that create a new row
+    pub fn insert_row(&mut self, values: &BTreeMap<String, PropertyValue>) -> Result<RowOperations> {
+        let row_id = uuid::Uuid::new_v4().to_string();
+        let (properties, mut operations) = self.encode_properties(values)?;
+        let now = chrono::Utc::now().timestamp_millis();
+
+        let mut row = json!({
+            "id": row_id,
+            "type": "page",
+            "version": 1,
+            "alive": true,
+            "parent_id": self.id,
+            "parent_table": "collection",
+            "properties": properties,
+            "created_time": now,
+            "last_edited_time": now,
+        });
+        if let Some(space_id) = &self.space_id {
+            row["space_id"] = json!(space_id);
+        }
+
+        operations.push(Operation::set("block", &row_id, &[], row));
+        Ok(RowOperations { row_id, operations })
+    }
+
+    /// Build the operations that change properties of an existing row
+    pub fn update_row(&mut self, row_id: &str, values: &BTreeMap<String, PropertyValue>) -> Result<RowOperations> {
+        let (properties, mut operations) = self.encode_properties(values)?;
+        for (prop_id, encoded) in properties {
+            operations.push(Operation::set("block", row_id, &["properties", &prop_id], encoded));
+        }
+        operations.push(Operation::update(
+            "block",
+            row_id,
+            &[],
+            json!({ "last_edited_time": chrono::Utc::now().timestamp_millis() }),
+        ));
+
+        Ok(RowOperations {
+            row_id: row_id.to_string(),
+            operations,
+        })
+    }
+
+    /// Encode values by schema id; returned operations create missing options
+    ///
+    /// The whole row is validated before any option is added to the schema,
+    /// so a failed row leaves the collection as it was.
+    fn encode_properties(
+        &mut self,
+        values: &BTreeMap<String, PropertyValue>,
+    ) -> Result<(BTreeMap<String, Value>, Vec<Operation>)> {
+        let mut properties = BTreeMap::new();
+        let mut new_options = Vec::new();
+
+        for (name, value) in values {
+            let prop_id = self
+                .property_id(name)
+                .with_context(|| format!("Unknown property '{}' in collection {}", name, self.id))?
+                .to_string();
+            let encoded = self
+                .encode(&prop_id, value)
+                .with_context(|| format!("Invalid value for property '{}'", name))?;
+            let missing = self.missing_options(&prop_id, value)?;
+            if !missing.is_empty() {
+                new_options.push((prop_id.clone(), missing));
+            }
+            properties.insert(prop_id, encoded);
+        }
+
+        let operations = new_options
+            .into_iter()
+            .map(|(prop_id, names)| self.add_options(&prop_id, names))
+            .collect();
+        Ok((properties, operations))
+    }
+
+    /// Select options the value uses that the schema lacks, if creating them is allowed
+    fn missing_options(&self, prop_id: &str, value: &PropertyValue) -> Result<Vec<String>> {
+        let names: Vec<&String> = match value {
+            PropertyValue::Select(name) => vec![name],
+            PropertyValue::MultiSelect(names) => names.iter().collect(),
+            _ => return Ok(Vec::new()),
+        };
+
+        let schema = &self.schema[prop_id];
+        let mut missing: Vec<String> = Vec::new();
+        for name in names {
+            if name.contains(',') {
+                bail!("Select option '{}' must not contain a comma", name);
+            }
+            if schema.options.iter().any(|o| &o.value == name) || missing.contains(name) {
+                continue;
+            }
+            if !self.create_missing_options {
+                bail!("'{}' is not an option of property '{}'", name, schema.name);
+            }
+            missing.push(name.clone());
+        }
+        Ok(missing)
+    }
+
+    /// Add options to the schema and build the operation that saves them
+    fn add_options(&mut self, prop_id: &str, names: Vec<String>) -> Operation {
+        let schema = self.schema.get_mut(prop_id).expect("property id was resolved from the schema");
+        schema.options.extend(names.into_iter().map(|value| SelectOption {
+            id: uuid::Uuid::new_v4().to_string(),
+            color: "default".to_string(),
+            value,
+        }));
+        Operation::set(
+            "collection",
+            &self.id,
+            &["schema", prop_id, "options"],
+            serde_json::to_value(&schema.options).expect("options serialize"),
+        )
+    }
+
+    fn encode(&self, prop_id: &str, value: &PropertyValue) -> Result<Value> {
+        let schema = &self.schema[prop_id];
+        let ty = schema.property_type.as_str();
+
+        let encoded = match (ty, value) {
+            ("title" | "text" | "email" | "phone_number", PropertyValue::Text(s)) => json!([[s]]),
+            ("url", PropertyValue::Text(s)) => json!([[s, [["a", s]]]]),
+            ("number", PropertyValue::Number(n)) if !n.is_finite() => bail!("{} is not a finite number", n),
+            ("number", PropertyValue::Number(n)) => json!([[n.to_string()]]),
+            ("checkbox", PropertyValue::Checkbox(b)) => json!([[if *b { "Yes" } else { "No" }]]),
+            ("select", PropertyValue::Select(s)) => json!([[s]]),
+            ("multi_select", PropertyValue::MultiSelect(names)) => json!([[names.join(",")]]),
+            ("date", PropertyValue::Date { start, end }) => encode_date(start, end.as_deref())?,
+            ("relation", PropertyValue::Relation(ids)) => {
+                for id in ids {
+                    self.check_relation_target(schema, id)?;
+                }
+                let space_id = self.space_id.clone().unwrap_or_default();
+                mention_list(ids.iter().map(|id| json!(["‣", [["p", id, space_id]]])))
+            }
+            ("person", PropertyValue::Person(ids)) => mention_list(ids.iter().map(|id| json!(["‣", [["u", id]]]))),
+            (ty, value) => bail!("{:?} cannot be stored in a '{}' property", value, ty),
+        };
+
+        Ok(encoded)
+    }
+
+    fn check_relation_target(&self, schema: &PropertySchema, id: &str) -> Result<()> {
+        let normalized = to_uuid_format(id).with_context(|| format!("'{}' is not a valid page id", id))?;
+        if let (Some(target), Some(Some(parent))) = (&schema.collection_id, self.known_rows.get(&normalized)) {
+            if to_uuid_format(parent).as_deref().unwrap_or(parent) != to_uuid_format(target).as_deref().unwrap_or(target) {
+                bail!("Page {} belongs to collection {}, not {}", id, parent, target);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Join mention segments with the `,` separators Notion puts between them
+fn mention_list(items: impl Iterator<Item = Value>) -> Value {
+    let mut segments = Vec::new();
+    for item in items {
+        if !segments.is_empty() {
+            segments.push(json!([","]));
+        }
+        segments.push(item);
+    }
+    Value::Array(segments)
+}
+
+fn encode_date(start: &str, end: Option<&str>) -> Result<Value> {
+    let (start_date, start_time) = parse_date(start)?;
+    let mut date = json!({ "start_date": start_date });
+    if let Some(time) = &start_time {
+        date["start_time"] = json!(time);
+    }
+
+    let kind = match end {
+        Some(end) => {
+            let (end_date, end_time) = parse_date(end)?;
+            if end_date < start_date {
+                bail!("Date range ends before it starts");
+            }
+            date["end_date"] = json!(end_date);
+            if let Some(time) = end_time {
+                date["end_time"] = json!(time);
+            }
+            if start_time.is_some() { "datetimerange" } else { "daterange" }
+        }
+        None if start_time.is_some() => "datetime",
+        None => "date",
+    };
+    date["type"] = json!(kind);
+
+    Ok(json!([["‣", [["d", date]]]]))
+}
+
+/// Split an ISO date or date-time into Notion's `YYYY-MM-DD` and `HH:MM` parts
+fn parse_date(input: &str) -> Result<(String, Option<String>)> {
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Ok((date.format("%Y-%m-%d").to_string(), None));
+    }
+    for format in ["%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(input, format) {
+            return Ok((dt.format("%Y-%m-%d").to_string(), Some(dt.format("%H:%M").to_string())));
+        }
+    }
+    bail!("'{}' is not a date (expected YYYY-MM-DD or YYYY-MM-DDTHH:MM)", input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Record;
+
+    const TARGET: &str = "aaaaaaaa-aaaa-aaaa-aaaa-aaaaaaaaaaaa";
+    const OTHER: &str = "bbbbbbbb-bbbb-bbbb-bbbb-bbbbbbbbbbbb";
+
+    fn collection() -> Collection {
+        let mut records = RecordMap::default();
+        records.collection.insert(
+            "coll".to_string(),
+            Record::new(json!({
+                "id": "coll",
+                "space_id": "space",
+                "schema": {
+                    "title": { "name": "Name", "type": "title" },
+                    "a1b2": { "name": "Status", "type": "select",
+                              "options": [{ "id": "o1", "color": "red", "value": "Done" }] },
+                    "c3d4": { "name": "Due", "type": "date" },
+                    "e5f6": { "name": "Tasks", "type": "relation", "collection_id": "target-coll" },
+                    "g7h8": { "name": "Points", "type": "number" },
+                }
+            })),
+        );
+        records.block.insert(TARGET.to_string(), Record::new(json!({ "parent_id": "target-coll" })));
+        records.block.insert(OTHER.to_string(), Record::new(json!({ "parent_id": "elsewhere" })));
+        Collection::from_record_map(&records, "coll").unwrap()
+    }
+
+    fn values(pairs: Vec<(&str, PropertyValue)>) -> BTreeMap<String, PropertyValue> {
+        pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+    }
+
+    #[test]
+    fn test_insert_row_encodes_by_schema_id() {
+        let mut coll = collection();
+        let row = coll
+            .insert_row(&values(vec![
+                ("Name", PropertyValue::Text("Write docs".into())),
+                ("Status", PropertyValue::Select("Done".into())),
+                ("Points", PropertyValue::Number(3.0)),
+                ("Due", PropertyValue::Date { start: "2024-05-01".into(), end: None }),
+            ]))
+            .unwrap();
+
+        assert_eq!(row.operations.len(), 1);
+        let args = &row.operations[0].args;
+        assert_eq!(args["parent_id"], json!("coll"));
+        assert_eq!(args["parent_table"], json!("collection"));
+        assert_eq!(args["space_id"], json!("space"));
+        assert_eq!(args["properties"]["title"], json!([["Write docs"]]));
+        assert_eq!(args["properties"]["a1b2"], json!([["Done"]]));
+        assert_eq!(args["properties"]["g7h8"], json!([["3"]]));
+        assert_eq!(
+            args["properties"]["c3d4"],
+            json!([["‣", [["d", { "type": "date", "start_date": "2024-05-01" }]]]])
+        );
+    }
+
+    #[test]
+    fn test_unknown_select_option() {
+        let mut coll = collection();
+        let input = values(vec![("Status", PropertyValue::Select("Blocked".into()))]);
+        assert!(coll.insert_row(&input).is_err());
+
+        let mut coll = coll.create_missing_options(true);
+        let row = coll.update_row("row", &input).unwrap();
+        assert_eq!(row.operations[0].table, "collection");
+        assert_eq!(row.operations[0].path, vec!["schema", "a1b2", "options"]);
+        assert_eq!(row.operations[0].args.as_array().unwrap().len(), 2);
+        assert_eq!(row.operations[1], Operation::set("block", "row", &["properties", "a1b2"], json!([["Blocked"]])));
+    }
+
+    #[test]
+    fn test_failed_row_leaves_schema_unchanged() {
+        let mut coll = collection().create_missing_options(true);
+        let bad = values(vec![
+            ("Status", PropertyValue::Select("Blocked".into())),
+            ("Tasks", PropertyValue::Relation(vec![OTHER.into()])),
+        ]);
+        assert!(coll.insert_row(&bad).is_err());
+        assert_eq!(coll.schema["a1b2"].options.len(), 1);
+
+        let good = values(vec![("Status", PropertyValue::Select("Blocked".into()))]);
+        let row = coll.update_row("row", &good).unwrap();
+        assert_eq!(row.operations[0].path, vec!["schema", "a1b2", "options"]);
+        assert_eq!(coll.schema["a1b2"].options.len(), 2);
+    }
+
+    #[test]
+    fn test_non_finite_number_rejected() {
+        let mut coll = collection();
+        for n in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(coll.update_row("row", &values(vec![("Points", PropertyValue::Number(n))])).is_err());
+        }
+    }
+
+    #[test]
+    fn test_date_validation() {
+        let mut coll = collection();
+        let bad = values(vec![("Due", PropertyValue::Date { start: "05/01/2024".into(), end: None })]);
+        assert!(coll.insert_row(&bad).is_err());
+
+        let range = values(vec![(
+            "Due",
+            PropertyValue::Date { start: "2024-05-01T09:30".into(), end: Some("2024-05-02T10:00".into()) },
+        )]);
+        let row = coll.update_row("row", &range).unwrap();
+        let date = &row.operations[0].args[0][1][0][1];
+        assert_eq!(date["type"], json!("datetimerange"));
+        assert_eq!(date["start_time"], json!("09:30"));
+        assert_eq!(date["end_date"], json!("2024-05-02"));
+    }
+
+    #[test]
+    fn test_relation_targets() {
+        let mut coll = collection();
+        let ok = values(vec![("Tasks", PropertyValue::Relation(vec![TARGET.into()]))]);
+        let row = coll.update_row("row", &ok).unwrap();
+        assert_eq!(row.operations[0].args, json!([["‣", [["p", TARGET, "space"]]]]));
+
+        let wrong = values(vec![("Tasks", PropertyValue::Relation(vec![OTHER.into()]))]);
+        assert!(coll.update_row("row", &wrong).is_err());
+        let compact = values(vec![("Tasks", PropertyValue::Relation(vec![OTHER.replace('-', "")]))]);
+        assert!(coll.update_row("row", &compact).is_err());
+        let invalid = values(vec![("Tasks", PropertyValue::Relation(vec!["nope".into()]))]);
+        assert!(coll.update_row("row", &invalid).is_err());
+    }
+
+    #[test]
+    fn test_decode_round_trips() {
+        let mut coll = collection();
+        let input = values(vec![
+            ("Name", PropertyValue::Text("Row".into())),
+            ("Points", PropertyValue::Number(2.5)),
+            ("Due", PropertyValue::Date { start: "2024-05-01T09:30".into(), end: None }),
+            ("Tasks", PropertyValue::Relation(vec![TARGET.into()])),
+        ]);
+        let row = coll.insert_row(&input).unwrap();
+        let properties = &row.operations[0].args["properties"];
+
+        for (name, value) in &input {
+            let id = coll.property_id(name).unwrap();
+            assert_eq!(coll.decode(id, &properties[id]).as_ref(), Some(value), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_type_mismatch_and_unknown_property() {
+        let mut coll = collection();
+        assert!(coll.insert_row(&values(vec![("Points", PropertyValue::Text("x".into()))])).is_err());
+        assert!(coll.insert_row(&values(vec![("Missing", PropertyValue::Checkbox(true))])).is_err());
+    }
+}
@@ -0,0 +1,529 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use super::{Collection, PropertyValue};
+use crate::api::endpoints::paths;
+use crate::api::NotionClient;
+use crate::models::{QueryCollectionResponse, RecordMap};
+
+const RESULTS_REDUCER: &str = "collection_group_results";
+
+/// A filter tree; leaves refer to properties by name
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Property { property: String, condition: Condition },
+}
+
+impl Filter {
+    pub fn property(property: &str, condition: Condition) -> Self {
+        Filter::Property {
+            property: property.to_string(),
+            condition,
+        }
+    }
+}
+
+/// Comparison applied to a single property
+///
+/// Which conditions are allowed depends on the property type; the builder
+/// rejects combinations Notion would not accept.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Is(String),
+    IsNot(String),
+    Contains(String),
+    DoesNotContain(String),
+    StartsWith(String),
+    EndsWith(String),
+    IsEmpty,
+    IsNotEmpty,
+    NumberEquals(f64),
+    GreaterThan(f64),
+    LessThan(f64),
+    Checked(bool),
+    /// Dates as `YYYY-MM-DD`
+    DateIs(String),
+    Before(String),
+    After(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sort {
+    pub property: String,
+    pub descending: bool,
+}
+
+impl Sort {
+    pub fn ascending(property: &str) -> Self {
+        Self {
+            property: property.to_string(),
+            descending: false,
+        }
+    }
+
+    pub fn descending(property: &str) -> Self {
+        Self {
+            property: property.to_string(),
+            descending: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregator {
+    Count,
+    CountValues,
+    Unique,
+    Empty,
+    NotEmpty,
+    PercentEmpty,
+    PercentNotEmpty,
+    Sum,
+    Average,
+    Median,
+    Min,
+    Max,
+    Range,
+}
+
+impl Aggregator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Aggregator::Count => "count",
+            Aggregator::CountValues => "count_values",
+            Aggregator::Unique => "unique",
+            Aggregator::Empty => "empty",
+            Aggregator::NotEmpty => "not_empty",
+            Aggregator::PercentEmpty => "percent_empty",
+            Aggregator::PercentNotEmpty => "percent_not_empty",
+            Aggregator::Sum => "sum",
+            Aggregator::Average => "average",
+            Aggregator::Median => "median",
+            Aggregator::Min => "min",
+            Aggregator::Max => "max",
+            Aggregator::Range => "range",
+        }
+    }
+
+    fn numeric_only(&self) -> bool {
+        matches!(
+            self,
+            Aggregator::Sum | Aggregator::Average | Aggregator::Median | Aggregator::Min | Aggregator::Max | Aggregator::Range
+        )
+    }
+}
+
+/// A named aggregation; the name becomes the reducer key in the request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregation {
+    pub name: String,
+    pub property: String,
+    pub aggregator: Aggregator,
+}
+
+/// A row with its properties decoded and keyed by property name
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Row {
+    pub id: String,
+    pub properties: BTreeMap<String, PropertyValue>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    pub rows: Vec<Row>,
+    /// Aggregation values keyed by aggregation name
+    pub aggregations: BTreeMap<String, Value>,
+    pub record_map: RecordMap,
+}
+
+/// Builds `queryCollection` requests validated against the collection schema
+#[derive(Debug, Clone)]
+pub struct QueryBuilder<'a> {
+    collection: &'a Collection,
+    view_id: Option<String>,
+    filter: Option<Filter>,
+    sorts: Vec<Sort>,
+    aggregations: Vec<Aggregation>,
+    search: String,
+    time_zone: String,
+    page_size: u32,
+    max_rows: Option<usize>,
+}
+
+impl<'a> QueryBuilder<'a> {
+    pub fn new(collection: &'a Collection) -> Self {
+        Self {
+            collection,
+            view_id: None,
+            filter: None,
+            sorts: Vec::new(),
+            aggregations: Vec::new(),
+            search: String::new(),
+            time_zone: "UTC".to_string(),
+            page_size: 100,
+            max_rows: None,
+        }
+    }
+
+    pub fn view(mut self, view_id: &str) -> Self {
+        self.view_id = Some(view_id.to_string());
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn sort(mut self, sort: Sort) -> Self {
+        self.sorts.push(sort);
+        self
+    }
+
+    pub fn aggregate(mut self, name: &str, property: &str, aggregator: Aggregator) -> Self {
+        self.aggregations.push(Aggregation {
+            name: name.to_string(),
+            property: property.to_string(),
+            aggregator,
+        });
+        self
+    }
+
+    pub fn search(mut self, query: &str) -> Self {
+        self.search = query.to_string();
+        self
+    }
+
+    pub fn time_zone(mut self, tz: &str) -> Self {
+        self.time_zone = tz.to_string();
+        self
+    }
+
+    pub fn page_size(mut self, size: u32) -> Self {
+        self.page_size = size.max(1);
+        self
+    }
+
+    /// Stop paging once this many rows have been collected
+    pub fn max_rows(mut self, max: usize) -> Self {
+        self.max_rows = Some(max);
+        self
+    }
+
+    /// Request body asking for up to `limit` result rows
+    pub fn build_request(&self, limit: u32) -> Result<Value> {
+        let mut reducers = serde_json::Map::new();
+        reducers.insert(RESULTS_REDUCER.to_string(), json!({ "type": "results", "limit": limit }));
+        for agg in &self.aggregations {
+            if agg.name == RESULTS_REDUCER {
+                bail!("Aggregation name '{}' is reserved", RESULTS_REDUCER);
+            }
+            let (prop_id, ty) = self.resolve(&agg.property)?;
+            if agg.aggregator.numeric_only() && ty != "number" {
+                bail!("Aggregator '{}' needs a number property, '{}' is {}", agg.aggregator.as_str(), agg.property, ty);
+            }
+            reducers.insert(
+                agg.name.clone(),
+                json!({
+                    "type": "aggregation",
+                    "aggregation": { "property": prop_id, "aggregator": agg.aggregator.as_str() },
+                }),
+            );
+        }
+
+        let sort = self
+            .sorts
+            .iter()
+            .map(|s| {
+                let (prop_id, _) = self.resolve(&s.property)?;
+                Ok(json!({
+                    "property": prop_id,
+                    "direction": if s.descending { "descending" } else { "ascending" },
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut loader = json!({
+            "type": "reducer",
+            "reducers": reducers,
+            "sort": sort,
+            "searchQuery": self.search,
+            "userTimeZone": self.time_zone,
+        });
+        if let Some(filter) = &self.filter {
+            loader["filter"] = match self.encode_filter(filter)? {
+                group @ Value::Object(_) if group.get("filters").is_some() => group,
+                leaf => json!({ "operator": "and", "filters": [leaf] }),
+            };
+        }
+
+        let space_id = self.collection.space_id.clone().unwrap_or_default();
+        let mut body = json!({
+            "collection": { "id": self.collection.id, "spaceId": space_id },
+            "loader": loader,
+        });
+        if let Some(view_id) = &self.view_id {
+            body["collectionView"] = json!({ "id": view_id, "spaceId": space_id });
+        }
+        Ok(body)
+    }
+
+    /// Run the query, paging until every row (or `max_rows`) is loaded
+    ///
+    /// Notion has no cursor for collection queries; the web app simply asks
+    /// again with a larger `limit`, and so do we.
+    pub async fn run(&self, client: &NotionClient) -> Result<QueryResult> {
+        // Validate before the first round trip
+        self.build_request(self.page_size)?;
+
+        let mut limit = self.page_size;
+        let mut record_map = RecordMap::default();
+        let (ids, aggregations) = loop {
+            let body = self.build_request(limit)?;
+            let response: QueryCollectionResponse = client.post_json(paths::QUERY_COLLECTION, &body).await?;
+            record_map.merge(response.record_map);
+
+            let results = response.result.reducer_results;
+            let group = results.get(RESULTS_REDUCER).cloned().unwrap_or(Value::Null);
+            let ids: Vec<String> = group
+                .get("blockIds")
+                .and_then(Value::as_array)
+                .map(|ids| ids.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+                .unwrap_or_default();
+            let has_more = group.get("hasMore").and_then(Value::as_bool).unwrap_or(false);
+            debug!("queryCollection returned {} rows (limit {}, more: {})", ids.len(), limit, has_more);
+
+            let enough = self.max_rows.is_some_and(|max| ids.len() >= max);
+            if !has_more || enough || (ids.len() as u32) < limit {
+                break (ids, results);
+            }
+            limit = limit.saturating_add(self.page_size);
+        };
+
+        let aggregations = self
+            .aggregations
+            .iter()
+            .filter_map(|agg| {
+                let result = aggregations.get(&agg.name)?;
+                let value = result.get("aggregationResult").and_then(|r| r.get("value"))?;
+                Some((agg.name.clone(), value.clone()))
+            })
+            .collect();
+
+        let mut seen = HashSet::new();
+        let rows = ids
+            .into_iter()
+            .filter(|id| seen.insert(id.clone()))
+            .take(self.max_rows.unwrap_or(usize::MAX))
+            .map(|id| self.decode_row(&record_map, id))
+            .collect();
+
+        Ok(QueryResult {
+            rows,
+            aggregations,
+            record_map,
+        })
+    }
+
+    fn decode_row(&self, records: &RecordMap, id: String) -> Row {
+        let raw = records
+            .get("block", &id)
+            .and_then(|v| v.get("properties"))
+            .and_then(Value::as_object);
+        let properties = raw
+            .into_iter()
+            .flatten()
+            .filter_map(|(prop_id, value)| {
+                let name = self.collection.schema.get(prop_id)?.name.clone();
+                Some((name, self.collection.decode(prop_id, value)?))
+            })
+            .collect();
+        Row { id, properties }
+    }
+
+    fn resolve(&self, name: &str) -> Result<(String, &'a str)> {
+        let collection: &'a Collection = self.collection;
+        let prop_id = collection
+            .property_id(name)
+            .with_context(|| format!("Unknown property '{}' in collection {}", name, collection.id))?;
+        Ok((prop_id.to_string(), collection.schema[prop_id].property_type.as_str()))
+    }
+
+    fn encode_filter(&self, filter: &Filter) -> Result<Value> {
+        match filter {
+            Filter::And(children) | Filter::Or(children) => {
+                let operator = if matches!(filter, Filter::And(_)) { "and" } else { "or" };
+                let filters = children.iter().map(|f| self.encode_filter(f)).collect::<Result<Vec<_>>>()?;
+                Ok(json!({ "operator": operator, "filters": filters }))
+            }
+            Filter::Property { property, condition } => {
+                let (prop_id, ty) = self.resolve(property)?;
+                let (operator, value) = condition_for(ty, condition)
+                    .with_context(|| format!("Filter {:?} is not valid for '{}' ({})", condition, property, ty))?;
+                let mut inner = json!({ "operator": operator });
+                if let Some(value) = value {
+                    inner["value"] = json!({ "type": "exact", "value": value });
+                }
+                Ok(json!({ "property": prop_id, "filter": inner }))
+            }
+        }
+    }
+}
+
+/// Map a condition to Notion's operator name and value for a property type
+fn condition_for(ty: &str, condition: &Condition) -> Option<(&'static str, Option<Value>)> {
+    use Condition::*;
+
+    let textual = matches!(ty, "title" | "text" | "url" | "email" | "phone_number");
+    let date = |d: &String| json!({ "type": "date", "start_date": d });
+
+    let mapped = match (ty, condition) {
+        (_, IsEmpty) => ("is_empty", None),
+        (_, IsNotEmpty) => ("is_not_empty", None),
+        (_, Is(v)) if textual => ("string_is", Some(json!(v))),
+        (_, IsNot(v)) if textual => ("string_is_not", Some(json!(v))),
+        (_, Contains(v)) if textual => ("string_contains", Some(json!(v))),
+        (_, DoesNotContain(v)) if textual => ("string_does_not_contain", Some(json!(v))),
+        (_, StartsWith(v)) if textual => ("string_starts_with", Some(json!(v))),
+        (_, EndsWith(v)) if textual => ("string_ends_with", Some(json!(v))),
+        ("select", Is(v)) => ("enum_is", Some(json!(v))),
+        ("select", IsNot(v)) => ("enum_is_not", Some(json!(v))),
+        ("multi_select", Contains(v)) => ("enum_contains", Some(json!(v))),
+        ("multi_select", DoesNotContain(v)) => ("enum_does_not_contain", Some(json!(v))),
+        ("relation" | "person", Contains(v)) => ("relation_contains", Some(json!(v))),
+        ("relation" | "person", DoesNotContain(v)) => ("relation_does_not_contain", Some(json!(v))),
+        ("number", NumberEquals(n)) => ("number_equals", Some(json!(n))),
+        ("number", GreaterThan(n)) => ("number_greater_than", Some(json!(n))),
+        ("number", LessThan(n)) => ("number_less_than", Some(json!(n))),
+        ("checkbox", Checked(b)) => ("checkbox_is", Some(json!(b))),
+        ("date" | "created_time" | "last_edited_time", DateIs(d)) => ("date_is", Some(date(d))),
+        ("date" | "created_time" | "last_edited_time", Before(d)) => ("date_is_before", Some(date(d))),
+        ("date" | "created_time" | "last_edited_time", After(d)) => ("date_is_after", Some(date(d))),
+        _ => return None,
+    };
+
+    Some(mapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Record;
+
+    fn collection() -> Collection {
+        let mut records = RecordMap::default();
+        records.collection.insert(
+            "coll".to_string(),
+            Record::new(json!({
+                "id": "coll",
+                "space_id": "space",
+                "schema": {
+                    "title": { "name": "Name", "type": "title" },
+                    "stat": { "name": "Status", "type": "select", "options": [] },
+                    "pts": { "name": "Points", "type": "number" },
+                }
+            })),
+        );
+        Collection::from_record_map(&records, "coll").unwrap()
+    }
+
+    #[test]
+    fn test_build_request() {
+        let coll = collection();
+        let body = QueryBuilder::new(&coll)
+            .filter(Filter::Or(vec![
+                Filter::property("Status", Condition::Is("Done".into())),
+                Filter::property("Points", Condition::GreaterThan(3.0)),
+            ]))
+            .sort(Sort::descending("Points"))
+            .aggregate("total", "Points", Aggregator::Sum)
+            .search("docs")
+            .time_zone("Europe/Berlin")
+            .build_request(25)
+            .unwrap();
+
+        let loader = &body["loader"];
+        assert_eq!(body["collection"], json!({ "id": "coll", "spaceId": "space" }));
+        assert_eq!(loader["reducers"]["collection_group_results"]["limit"], json!(25));
+        assert_eq!(loader["reducers"]["total"]["aggregation"], json!({ "property": "pts", "aggregator": "sum" }));
+        assert_eq!(loader["sort"], json!([{ "property": "pts", "direction": "descending" }]));
+        assert_eq!(loader["filter"]["operator"], json!("or"));
+        assert_eq!(
+            loader["filter"]["filters"][0],
+            json!({ "property": "stat", "filter": { "operator": "enum_is", "value": { "type": "exact", "value": "Done" } } })
+        );
+        assert_eq!(loader["searchQuery"], json!("docs"));
+        assert_eq!(loader["userTimeZone"], json!("Europe/Berlin"));
+    }
+
+    #[test]
+    fn test_single_leaf_is_wrapped_in_group() {
+        let coll = collection();
+        let body = QueryBuilder::new(&coll)
+            .filter(Filter::property("Name", Condition::IsEmpty))
+            .build_request(10)
+            .unwrap();
+        assert_eq!(
+            body["loader"]["filter"],
+            json!({ "operator": "and", "filters": [{ "property": "title", "filter": { "operator": "is_empty" } }] })
+        );
+    }
+
+    #[test]
+    fn test_validation_against_schema() {
+        let coll = collection();
+        let bad_condition = QueryBuilder::new(&coll).filter(Filter::property("Points", Condition::StartsWith("1".into())));
+        assert!(bad_condition.build_request(10).is_err());
+
+        let bad_aggregation = QueryBuilder::new(&coll).aggregate("s", "Status", Aggregator::Sum);
+        assert!(bad_aggregation.build_request(10).is_err());
+
+        let unknown = QueryBuilder::new(&coll).sort(Sort::ascending("Nope"));
+        assert!(unknown.build_request(10).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_pages_and_decodes_rows() {
+        use axum::{routing::post, Json, Router};
+
+        async fn query(Json(body): Json<Value>) -> Json<Value> {
+            let limit = body["loader"]["reducers"]["collection_group_results"]["limit"].as_u64().unwrap() as usize;
+            let ids: Vec<String> = (0..5.min(limit)).map(|i| format!("row{}", i)).collect();
+            let blocks: serde_json::Map<String, Value> = ids
+                .iter()
+                .enumerate()
+                .map(|(i, id)| (id.clone(), json!({ "value": { "properties": { "pts": [[i.to_string()]] } } })))
+                .collect();
+            Json(json!({
+                "result": { "reducerResults": {
+                    "collection_group_results": { "blockIds": ids, "hasMore": limit < 5 },
+                    "total": { "aggregationResult": { "type": "number", "value": 10 } },
+                } },
+                "recordMap": { "block": blocks },
+            }))
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/api", listener.local_addr().unwrap());
+        let app = Router::new().route("/api/v3/queryCollection", post(query));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let coll = collection();
+        let client = NotionClient::from_token("token".to_string()).with_base_url(&base);
+        let result = QueryBuilder::new(&coll)
+            .page_size(2)
+            .aggregate("total", "Points", Aggregator::Sum)
+            .run(&client)
+            .await
+            .unwrap();
+
+        assert_eq!(result.rows.len(), 5);
+        assert_eq!(result.rows[4].properties["Points"], PropertyValue::Number(4.0));
+        assert_eq!(result.aggregations["total"], json!(10));
+    }
+}
@@ -0,0 +1,302 @@
+//! User configuration file and named profiles
+//!
+//! Read from `notion-re/config.toml` in the platform config directory
+//! (`~/.config` on Linux). Every key is optional:
+//!
+//! ```toml
+//! token = "v02%3Auser_token_or_cookies..."
+//! default_profile = "work"
+//!
+//! [profiles.work]
+//! token = "v02%3A..."
+//! user_id = "5a1b..."
+//! space_id = "f3c2..."
+//! base_url = "https://www.notion.so/api"
+//! cache_dir = "/home/me/.cache/notion-re/work"
+//! rate_limit = { requests_per_second = 3.0, max_retries = 2 }
+//!
+//! [profiles.personal]
+//! token = "v02%3A..."
+//! ```
+//!
+//! A profile is chosen by name, then `NOTION_PROFILE`, then `default_profile`.
+//! With none of those the top-level `token` is used on its own. `NOTION_*`
+//! environment variables override the fields of whichever profile is chosen.
+//! A profile left without a token uses the one stored for it in the
+//! [`credentials`](crate::credentials) store.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::api::{NotionClient, NotionHeaders, RateLimit};
+use crate::credentials::{CredentialStore, DEFAULT_PROFILE};
+
+/// Environment variable naming the profile to use
+pub const PROFILE_ENV: &str = "NOTION_PROFILE";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    /// `token_v2` cookie used when none is given on the command line or in `NOTION_TOKEN`
+    pub token: Option<String>,
+    /// Profile used when none is named
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// One account's settings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub token: Option<String>,
+    /// Active user, sent as `x-notion-active-user-header` when signed in to several accounts
+    pub user_id: Option<String>,
+    /// Space searched and listed when none is given
+    pub space_id: Option<String>,
+    pub base_url: Option<String>,
+    pub cache_dir: Option<PathBuf>,
+    pub rate_limit: Option<RateLimit>,
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("notion-re").join("config.toml"))
+    }
+
+    /// Parse a config file; a missing file gives the defaults
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let config: Self = toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))?;
+        for (name, profile) in &config.profiles {
+            profile
+                .validate()
+                .with_context(|| format!("Invalid profile {:?} in {}", name, path.display()))?;
+        }
+        Ok(config)
+    }
+
+    /// Load from the default location, if there is one
+    pub fn load_default() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) => Self::load(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Name of the profile to use: `name`, else `NOTION_PROFILE`, else `default_profile`
+    pub fn profile_name(&self, name: Option<&str>) -> Option<String> {
+        name.map(str::to_string)
+            .or_else(|| std::env::var(PROFILE_ENV).ok().filter(|n| !n.is_empty()))
+            .or_else(|| self.default_profile.clone())
+    }
+
+    /// The named profile as written in the file
+    ///
+    /// Without a name this is just the top-level `token`. A profile without
+    /// its own token also falls back to the top-level one.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        let mut profile = match name {
+            Some(name) => match self.profiles.get(name) {
+                Some(profile) => profile.clone(),
+                None => {
+                    let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+                    bail!("No profile named {:?} (known: {})", name, known.join(", "));
+                }
+            },
+            None => Profile::default(),
+        };
+        if profile.token.is_none() {
+            profile.token = self.token.clone();
+        }
+        Ok(profile)
+    }
+}
+
+impl Profile {
+    /// Resolve a profile from the default config file and the environment
+    ///
+    /// See the module docs for how the profile is picked.
+    pub fn load(name: Option<&str>) -> Result<Self> {
+        let config = Config::load_default()?;
+        let name = config.profile_name(name);
+        let profile = Self::from_config(&config, name.as_deref())?;
+        match CredentialStore::available() {
+            Some(store) if profile.token.is_none() => {
+                profile.with_stored_credential(&store, name.as_deref().unwrap_or(DEFAULT_PROFILE))
+            }
+            _ => Ok(profile),
+        }
+    }
+
+    /// Like [`Profile::load`], but leaves the credentials store alone
+    ///
+    /// Unlocking the store can mean a prompt and a slow key derivation, so
+    /// callers that may not need a token resolve the profile this way first.
+    pub fn resolve(name: Option<&str>) -> Result<Self> {
+        let config = Config::load_default()?;
+        Self::from_config(&config, config.profile_name(name).as_deref())
+    }
+
+    fn from_config(config: &Config, name: Option<&str>) -> Result<Self> {
+        let profile = config.profile(name)?.with_env()?;
+        profile.validate().context("Invalid NOTION_* environment variable")?;
+        Ok(profile)
+    }
+
+    /// Reject settings the client cannot use
+    pub fn validate(&self) -> Result<()> {
+        match &self.rate_limit {
+            Some(rate_limit) => rate_limit.validate().context("Invalid rate_limit"),
+            None => Ok(()),
+        }
+    }
+
+    /// Fill in the token (and user id, if unset) stored under `name`
+    pub fn with_stored_credential(mut self, store: &CredentialStore, name: &str) -> Result<Self> {
+        if let Some(credential) = store.get(name)? {
+            self.token = Some(credential.token);
+            if self.user_id.is_none() {
+                self.user_id = credential.user_id;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Override fields from `NOTION_TOKEN`, `NOTION_USER_ID`, `NOTION_SPACE_ID`,
+    /// `NOTION_BASE_URL`, `NOTION_CACHE_DIR` and `NOTION_RATE_LIMIT`
+    pub fn with_env(self) -> Result<Self> {
+        self.with_vars(|key| std::env::var(key).ok())
+    }
+
+    /// Like [`Profile::with_env`], reading variables through `var`; empty values are ignored
+    pub fn with_vars<F: Fn(&str) -> Option<String>>(mut self, var: F) -> Result<Self> {
+        let var = |key: &str| var(key).filter(|v| !v.is_empty());
+        if let Some(token) = var("NOTION_TOKEN") {
+            self.token = Some(token);
+        }
+        if let Some(user_id) = var("NOTION_USER_ID") {
+            self.user_id = Some(user_id);
+        }
+        if let Some(space_id) = var("NOTION_SPACE_ID") {
+            self.space_id = Some(space_id);
+        }
+        if let Some(base_url) = var("NOTION_BASE_URL") {
+            self.base_url = Some(base_url);
+        }
+        if let Some(cache_dir) = var("NOTION_CACHE_DIR") {
+            self.cache_dir = Some(PathBuf::from(cache_dir));
+        }
+        if let Some(rps) = var("NOTION_RATE_LIMIT") {
+            let rps = rps
+                .parse()
+                .with_context(|| format!("NOTION_RATE_LIMIT must be requests per second, not '{}'", rps))?;
+            let limit = self.rate_limit.get_or_insert_with(RateLimit::default);
+            limit.requests_per_second = Some(rps);
+        }
+        Ok(self)
+    }
+
+    /// Request headers for this profile's token and active user
+    pub fn headers(&self) -> Result<NotionHeaders> {
+        let token = self.token.clone().filter(|t| !t.is_empty()).context("Profile has no token")?;
+        let headers = NotionHeaders::new(token);
+        Ok(match &self.user_id {
+            Some(user_id) => headers.with_user_id(user_id.clone()),
+            None => headers,
+        })
+    }
+
+    /// A client with this profile's headers, base URL and rate limit
+    pub fn client(&self) -> Result<NotionClient> {
+        let mut client = NotionClient::new(self.headers()?);
+        if let Some(base_url) = &self.base_url {
+            client = client.with_base_url(base_url);
+        }
+        if let Some(rate_limit) = self.rate_limit {
+            client = client.with_rate_limit(rate_limit);
+        }
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+token = "fallback"
+default_profile = "work"
+
+[profiles.work]
+token = "work-token"
+user_id = "u1"
+space_id = "sp"
+rate_limit = { requests_per_second = 2.5 }
+
+[profiles.side]
+base_url = "http://localhost:9000/api"
+"#;
+
+    #[test]
+    fn test_profiles_resolve_with_fallback_token() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        let work = config.profile(Some("work")).unwrap();
+        assert_eq!(work.token.as_deref(), Some("work-token"));
+        assert_eq!(work.rate_limit.unwrap().requests_per_second, Some(2.5));
+        assert_eq!(work.headers().unwrap().user_id.as_deref(), Some("u1"));
+
+        let side = config.profile(Some("side")).unwrap();
+        assert_eq!(side.token.as_deref(), Some("fallback"));
+        assert_eq!(side.client().unwrap().base_url(), "http://localhost:9000/api");
+
+        assert_eq!(config.profile(None).unwrap().token.as_deref(), Some("fallback"));
+        let err = config.profile(Some("home")).unwrap_err().to_string();
+        assert!(err.contains("known: side, work"), "{}", err);
+    }
+
+    #[test]
+    fn test_env_overrides_profile() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        let vars = |key: &str| match key {
+            "NOTION_TOKEN" => Some("env-token".to_string()),
+            "NOTION_RATE_LIMIT" => Some("5".to_string()),
+            "NOTION_SPACE_ID" => Some(String::new()),
+            _ => None,
+        };
+        let profile = config.profile(Some("work")).unwrap().with_vars(vars).unwrap();
+        assert_eq!(profile.token.as_deref(), Some("env-token"));
+        assert_eq!(profile.space_id.as_deref(), Some("sp"));
+        assert_eq!(profile.rate_limit.unwrap().requests_per_second, Some(5.0));
+        assert_eq!(profile.user_id.as_deref(), Some("u1"));
+
+        let tiny = config
+            .profile(Some("work"))
+            .unwrap()
+            .with_vars(|key| (key == "NOTION_RATE_LIMIT").then(|| "1e-300".to_string()))
+            .unwrap();
+        assert!(tiny.validate().is_err());
+
+        let garbled = config
+            .profile(Some("work"))
+            .unwrap()
+            .with_vars(|key| (key == "NOTION_RATE_LIMIT").then(|| "fast".to_string()));
+        assert!(garbled.unwrap_err().to_string().contains("'fast'"));
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_rate_limit() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.toml");
+        fs::write(&path, "[profiles.work]\nrate_limit = { requests_per_second = -2.0 }\n").unwrap();
+        let err = format!("{:#}", Config::load(&path).unwrap_err());
+        assert!(err.contains("Invalid profile \"work\""), "{}", err);
+        assert!(err.contains("positive"), "{}", err);
+    }
+}
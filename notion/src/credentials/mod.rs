@@ -0,0 +1,365 @@
+//! Encrypted storage for `token_v2` credentials
+//!
+//! Credentials are kept per profile name in one of two places:
+//!
+//! - an encrypted file, `notion-re/credentials.enc` in the platform config
+//!   directory by default. The key is derived from a passphrase with
+//!   Argon2id and the contents are sealed with ChaCha20-Poly1305. This needs
+//!   no desktop session, so it works on servers and in CI.
+//! - the OS keyring (Secret Service/keyutils, Keychain or Credential
+//!   Manager), with the `keyring` cargo feature.
+//!
+//! The passphrase for the file can be given directly or read from
+//! `NOTION_PASSPHRASE`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+use crate::api::NotionHeaders;
+
+/// Environment variable holding the passphrase of the credentials file
+pub const PASSPHRASE_ENV: &str = "NOTION_PASSPHRASE";
+/// Keyring service name entries are stored under
+pub const KEYRING_SERVICE: &str = "notion-re";
+/// Name credentials are stored under when no profile is chosen
+pub const DEFAULT_PROFILE: &str = "default";
+
+const FILE_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+/// A stored session
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Credential {
+    pub token: String,
+    pub user_id: Option<String>,
+}
+
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credential")
+            .field("token", &"<redacted>")
+            .field("user_id", &self.user_id)
+            .finish()
+    }
+}
+
+impl Credential {
+    pub fn new(token: String) -> Self {
+        Self { token, user_id: None }
+    }
+
+    pub fn headers(&self) -> NotionHeaders {
+        let headers = NotionHeaders::new(self.token.clone());
+        match &self.user_id {
+            Some(user_id) => headers.with_user_id(user_id.clone()),
+            None => headers,
+        }
+    }
+}
+
+/// Argon2id cost, stored in the file so it can be raised later
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memory in KiB
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    /// The argon2 crate's recommended defaults (19 MiB, 2 passes, 1 lane)
+    fn default() -> Self {
+        Self {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// On-disk layout of the credentials file
+#[derive(Debug, Serialize, Deserialize)]
+struct SealedFile {
+    version: u32,
+    kdf: KdfParams,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Where credentials are kept
+#[derive(Clone)]
+pub enum CredentialStore {
+    /// A passphrase-encrypted file
+    File {
+        path: PathBuf,
+        passphrase: String,
+        kdf: KdfParams,
+    },
+    /// The OS keyring, one entry per profile
+    #[cfg(feature = "keyring")]
+    Keyring,
+}
+
+impl fmt::Debug for CredentialStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialStore::File { path, kdf, .. } => f
+                .debug_struct("File")
+                .field("path", path)
+                .field("passphrase", &"<redacted>")
+                .field("kdf", kdf)
+                .finish(),
+            #[cfg(feature = "keyring")]
+            CredentialStore::Keyring => f.write_str("Keyring"),
+        }
+    }
+}
+
+impl CredentialStore {
+    /// An encrypted file at `path`, created on the first `set`
+    pub fn file<P: AsRef<Path>>(path: P, passphrase: &str) -> Self {
+        CredentialStore::File {
+            path: path.as_ref().to_path_buf(),
+            passphrase: passphrase.to_string(),
+            kdf: KdfParams::default(),
+        }
+    }
+
+    /// The default file, with the passphrase from `NOTION_PASSPHRASE`
+    pub fn file_from_env() -> Result<Self> {
+        let path = Self::default_path().context("No config directory for the credentials file")?;
+        let passphrase = std::env::var(PASSPHRASE_ENV)
+            .ok()
+            .filter(|p| !p.is_empty())
+            .with_context(|| format!("Set {} to unlock {}", PASSPHRASE_ENV, path.display()))?;
+        Ok(Self::file(path, &passphrase))
+    }
+
+    /// The store to read without asking: the file if it exists and
+    /// `NOTION_PASSPHRASE` is set, else the keyring when built with it
+    pub fn available() -> Option<Self> {
+        let file = Self::default_path().filter(|p| p.exists()).and(Self::file_from_env().ok());
+        #[cfg(feature = "keyring")]
+        {
+            file.or(Some(CredentialStore::Keyring))
+        }
+        #[cfg(not(feature = "keyring"))]
+        {
+            file
+        }
+    }
+
+    #[cfg(feature = "keyring")]
+    pub fn keyring() -> Self {
+        CredentialStore::Keyring
+    }
+
+    /// `notion-re/credentials.enc` in the platform config directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("notion-re").join("credentials.enc"))
+    }
+
+    /// Cost used when the file is next written; existing files keep theirs until then
+    // Only `File` exists without the keyring feature
+    #[allow(irrefutable_let_patterns)]
+    pub fn with_kdf_params(mut self, params: KdfParams) -> Self {
+        if let CredentialStore::File { kdf, .. } = &mut self {
+            *kdf = params;
+        }
+        self
+    }
+
+    pub fn get(&self, profile: &str) -> Result<Option<Credential>> {
+        match self {
+            CredentialStore::File { path, passphrase, .. } => Ok(open(path, passphrase)?.remove(profile)),
+            #[cfg(feature = "keyring")]
+            CredentialStore::Keyring => match keyring_entry(profile)?.get_password() {
+                Ok(secret) => Ok(Some(serde_json::from_str(&secret).context("Invalid keyring entry")?)),
+                Err(keyring::Error::NoEntry) => Ok(None),
+                Err(e) => Err(e).context("Failed to read from the keyring"),
+            },
+        }
+    }
+
+    /// Store a credential, replacing any previous one for `profile`
+    pub fn set(&self, profile: &str, credential: &Credential) -> Result<()> {
+        match self {
+            CredentialStore::File { path, passphrase, kdf } => {
+                let mut credentials = open(path, passphrase)?;
+                credentials.insert(profile.to_string(), credential.clone());
+                seal(path, passphrase, *kdf, &credentials)
+            }
+            #[cfg(feature = "keyring")]
+            CredentialStore::Keyring => keyring_entry(profile)?
+                .set_password(&serde_json::to_string(credential)?)
+                .context("Failed to write to the keyring"),
+        }
+    }
+
+    /// Forget `profile`; returns whether it was stored
+    pub fn delete(&self, profile: &str) -> Result<bool> {
+        match self {
+            CredentialStore::File { path, passphrase, kdf } => {
+                let mut credentials = open(path, passphrase)?;
+                if credentials.remove(profile).is_none() {
+                    return Ok(false);
+                }
+                seal(path, passphrase, *kdf, &credentials)?;
+                Ok(true)
+            }
+            #[cfg(feature = "keyring")]
+            CredentialStore::Keyring => match keyring_entry(profile)?.delete_credential() {
+                Ok(()) => Ok(true),
+                Err(keyring::Error::NoEntry) => Ok(false),
+                Err(e) => Err(e).context("Failed to delete from the keyring"),
+            },
+        }
+    }
+
+    /// Profiles with a stored credential; the keyring cannot be listed
+    pub fn profiles(&self) -> Result<Vec<String>> {
+        match self {
+            CredentialStore::File { path, passphrase, .. } => Ok(open(path, passphrase)?.into_keys().collect()),
+            #[cfg(feature = "keyring")]
+            CredentialStore::Keyring => bail!("The keyring cannot list stored profiles"),
+        }
+    }
+}
+
+impl NotionHeaders {
+    /// Headers for the credential stored under `profile`
+    pub fn from_store(store: &CredentialStore, profile: &str) -> Result<Self> {
+        let credential = store
+            .get(profile)?
+            .with_context(|| format!("No stored credential for profile {:?}", profile))?;
+        Ok(credential.headers())
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_entry(profile: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, profile).context("Keyring is not available")
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], kdf: KdfParams) -> Result<Key> {
+    let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(KEY_LEN))
+        .map_err(|e| anyhow!("Invalid key derivation parameters: {}", e))?;
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+/// Decrypt the file; a missing file holds no credentials
+fn open(path: &Path, passphrase: &str) -> Result<BTreeMap<String, Credential>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let sealed: SealedFile =
+        serde_json::from_slice(&data).with_context(|| format!("Invalid credentials file {}", path.display()))?;
+    if sealed.version != FILE_VERSION {
+        bail!("Unsupported credentials file version {}", sealed.version);
+    }
+    let salt = BASE64.decode(&sealed.salt).context("Invalid salt")?;
+    let nonce = BASE64.decode(&sealed.nonce).context("Invalid nonce")?;
+    let ciphertext = BASE64.decode(&sealed.ciphertext).context("Invalid ciphertext")?;
+    if nonce.len() != 12 {
+        bail!("Invalid nonce length {}", nonce.len());
+    }
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, sealed.kdf)?);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| anyhow!("Wrong passphrase or corrupted credentials file {}", path.display()))?;
+    serde_json::from_slice(&plaintext).context("Invalid decrypted credentials")
+}
+
+/// Encrypt `credentials` with a fresh salt and nonce and replace the file
+fn seal(path: &Path, passphrase: &str, kdf: KdfParams, credentials: &BTreeMap<String, Credential>) -> Result<()> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, kdf)?);
+    let ciphertext = cipher
+        .encrypt(&nonce, serde_json::to_vec(credentials)?.as_ref())
+        .map_err(|_| anyhow!("Encryption failed"))?;
+    let sealed = SealedFile {
+        version: FILE_VERSION,
+        kdf,
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    // Write beside the target and rename, so a crash never leaves half a file
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(&sealed)?).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600))?;
+    }
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap enough for debug builds
+    const FAST: KdfParams = KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn test_file_store_round_trip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("credentials.enc");
+        let store = CredentialStore::file(&path, "correct horse").with_kdf_params(FAST);
+
+        let work = Credential {
+            token: "v02%3Asecret".to_string(),
+            user_id: Some("u1".to_string()),
+        };
+        store.set("work", &work).unwrap();
+        store.set("home", &Credential::new("other".to_string())).unwrap();
+        let debug = format!("{:?} {:?}", store, work);
+        assert!(!debug.contains("secret") && !debug.contains("horse"), "{}", debug);
+
+        let raw = fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("secret"), "token stored in the clear");
+        assert_eq!(store.get("work").unwrap(), Some(work));
+        assert_eq!(store.profiles().unwrap(), vec!["home", "work"]);
+
+        let headers = NotionHeaders::from_store(&store, "work").unwrap();
+        assert_eq!((headers.token.as_str(), headers.user_id.as_deref()), ("v02%3Asecret", Some("u1")));
+
+        let wrong = CredentialStore::file(&path, "battery staple");
+        assert!(wrong.get("work").unwrap_err().to_string().contains("Wrong passphrase"));
+
+        assert!(store.delete("work").unwrap());
+        assert!(!store.delete("work").unwrap());
+        assert!(NotionHeaders::from_store(&store, "work").is_err());
+    }
+}
@@ -0,0 +1,378 @@
+//! Structural diff of block trees
+//!
+//! Compares two record maps of the same page, e.g. two history snapshots or
+//! a cached copy against a fresh `loadPageChunk`. Blocks are matched by id,
+//! so an edited block is reported as modified rather than as a delete plus an
+//! insert. Within a parent, children that kept their relative order are left
+//! alone and only the ones taken out of sequence count as moved. Rich text
+//! properties also get a word-level diff of their plain text.
+
+mod render;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use similar::{ChangeTag, DiffOp, TextDiff};
+
+use crate::models::{plain_text, RecordMap};
+use crate::watch;
+
+/// Whether a run of text was kept, added or removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpanKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextSpan {
+    pub kind: SpanKind,
+    pub text: String,
+}
+
+/// One changed property or format key; `path` is dotted, e.g. `properties.title`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropertyChange {
+    pub path: String,
+    pub old: Value,
+    pub new: Value,
+    /// Word diff of the plain text, when both sides are rich text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub words: Option<Vec<TextSpan>>,
+}
+
+/// Where a block sits in the tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub parent: Option<String>,
+    /// Index in the parent's `content`, if listed there
+    pub index: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum BlockChange {
+    Inserted { id: String, block_type: String, text: String, at: Position },
+    Deleted { id: String, block_type: String, text: String, at: Position },
+    Moved { id: String, block_type: String, text: String, from: Position, to: Position },
+    Modified { id: String, block_type: String, text: String, properties: Vec<PropertyChange> },
+}
+
+impl BlockChange {
+    pub fn id(&self) -> &str {
+        match self {
+            BlockChange::Inserted { id, .. }
+            | BlockChange::Deleted { id, .. }
+            | BlockChange::Moved { id, .. }
+            | BlockChange::Modified { id, .. } => id,
+        }
+    }
+}
+
+/// Changes between two versions of a page, in document order
+///
+/// Inserted, moved and modified blocks follow the newer tree; deleted blocks
+/// come last, in the order they had in the older one.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PageDiff {
+    pub changes: Vec<BlockChange>,
+}
+
+impl PageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Diff the blocks of two record maps
+///
+/// Archived blocks (`alive: false`) count as absent, so archiving shows up as
+/// a deletion and restoring as an insertion.
+pub fn diff(before: &RecordMap, after: &RecordMap) -> PageDiff {
+    let old = Tree::new(before);
+    let new = Tree::new(after);
+    let reordered = reordered_children(&old, &new);
+
+    let mut changes = Vec::new();
+    for id in new.document_order() {
+        let value = new.blocks[id];
+        let Some(previous) = old.blocks.get(id) else {
+            changes.push(BlockChange::Inserted {
+                id: id.to_string(),
+                block_type: block_type(value),
+                text: block_text(value),
+                at: new.position(id),
+            });
+            continue;
+        };
+
+        let (from, to) = (old.position(id), new.position(id));
+        if from.parent != to.parent || reordered.contains(id) {
+            changes.push(BlockChange::Moved {
+                id: id.to_string(),
+                block_type: block_type(value),
+                text: block_text(value),
+                from,
+                to,
+            });
+        }
+
+        let properties: Vec<PropertyChange> = watch::changed_fields(previous, value)
+            .into_iter()
+            .map(|field| {
+                let words = (field.path.starts_with("properties.") && is_rich_text(&field.old) && is_rich_text(&field.new))
+                    .then(|| word_diff(&plain_text(&field.old), &plain_text(&field.new)));
+                PropertyChange {
+                    path: field.path,
+                    old: field.old,
+                    new: field.new,
+                    words,
+                }
+            })
+            .collect();
+        if !properties.is_empty() {
+            changes.push(BlockChange::Modified {
+                id: id.to_string(),
+                block_type: block_type(value),
+                text: block_text(value),
+                properties,
+            });
+        }
+    }
+
+    for id in old.document_order() {
+        if !new.blocks.contains_key(id) {
+            let value = old.blocks[id];
+            changes.push(BlockChange::Deleted {
+                id: id.to_string(),
+                block_type: block_type(value),
+                text: block_text(value),
+                at: old.position(id),
+            });
+        }
+    }
+    PageDiff { changes }
+}
+
+/// Word-level diff of two strings; whitespace is kept with the words
+pub fn word_diff(old: &str, new: &str) -> Vec<TextSpan> {
+    let mut spans: Vec<TextSpan> = Vec::new();
+    for change in TextDiff::from_words(old, new).iter_all_changes() {
+        let kind = match change.tag() {
+            ChangeTag::Equal => SpanKind::Equal,
+            ChangeTag::Insert => SpanKind::Insert,
+            ChangeTag::Delete => SpanKind::Delete,
+        };
+        match spans.last_mut() {
+            Some(last) if last.kind == kind => last.text.push_str(change.value()),
+            _ => spans.push(TextSpan {
+                kind,
+                text: change.value().to_string(),
+            }),
+        }
+    }
+    spans
+}
+
+/// Live blocks of a record map with their parent links
+struct Tree<'a> {
+    blocks: HashMap<&'a str, &'a Value>,
+}
+
+impl<'a> Tree<'a> {
+    fn new(map: &'a RecordMap) -> Self {
+        let blocks = map
+            .block
+            .iter()
+            .filter(|(_, record)| record.value.get("alive").and_then(Value::as_bool).unwrap_or(true))
+            .map(|(id, record)| (id.as_str(), &record.value))
+            .collect();
+        Self { blocks }
+    }
+
+    fn parent(&self, id: &str) -> Option<&'a str> {
+        self.blocks.get(id)?.get("parent_id")?.as_str()
+    }
+
+    /// Children listed in `content` that are present in this map
+    fn children(&self, id: &str) -> Vec<&'a str> {
+        self.blocks
+            .get(id)
+            .and_then(|v| v.get("content"))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .filter(|child| self.blocks.contains_key(child))
+            .collect()
+    }
+
+    fn position(&self, id: &str) -> Position {
+        let parent = self.parent(id);
+        let index = parent
+            .and_then(|p| self.blocks.get(p))
+            .and_then(|v| v.get("content"))
+            .and_then(Value::as_array)
+            .and_then(|content| content.iter().position(|c| c.as_str() == Some(id)));
+        Position {
+            parent: parent.map(str::to_string),
+            index,
+        }
+    }
+
+    /// Depth first from the roots, then anything not reachable through `content`
+    fn document_order(&self) -> Vec<&'a str> {
+        let mut roots: Vec<&str> = self
+            .blocks
+            .keys()
+            .copied()
+            .filter(|id| self.parent(id).is_none_or(|p| !self.blocks.contains_key(p)))
+            .collect();
+        roots.sort();
+
+        let mut order = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack: Vec<&str> = roots.into_iter().rev().collect();
+        while let Some(id) = stack.pop() {
+            if !seen.insert(id) {
+                continue;
+            }
+            order.push(id);
+            stack.extend(self.children(id).into_iter().rev());
+        }
+        let rest: BTreeSet<&str> = self.blocks.keys().copied().filter(|id| !seen.contains(id)).collect();
+        order.extend(rest);
+        order
+    }
+}
+
+/// Blocks that stayed under the same parent but left the longest common ordering
+fn reordered_children<'a>(old: &Tree<'a>, new: &Tree<'a>) -> HashSet<&'a str> {
+    let mut moved = HashSet::new();
+    for parent in new.blocks.keys().filter(|id| old.blocks.contains_key(*id)) {
+        let stayed = |tree: &Tree<'a>, other: &Tree<'a>| -> Vec<&'a str> {
+            tree.children(parent)
+                .into_iter()
+                .filter(|c| other.blocks.contains_key(c) && old.parent(c) == new.parent(c))
+                .collect()
+        };
+        let (a, b) = (stayed(old, new), stayed(new, old));
+        for op in similar::capture_diff_slices(similar::Algorithm::Myers, &a, &b) {
+            if let DiffOp::Insert { new_index, new_len, .. } | DiffOp::Replace { new_index, new_len, .. } = op {
+                moved.extend(&b[new_index..new_index + new_len]);
+            }
+        }
+    }
+    moved
+}
+
+fn block_type(value: &Value) -> String {
+    value.get("type").and_then(Value::as_str).unwrap_or("unknown").to_string()
+}
+
+fn block_text(value: &Value) -> String {
+    value.pointer("/properties/title").map(plain_text).unwrap_or_default()
+}
+
+/// Rich text as stored in properties: an array of `[text, formatting?]` segments
+fn is_rich_text(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Array(segments) => segments
+            .iter()
+            .all(|seg| seg.as_array().and_then(|s| s.first()).is_some_and(Value::is_string)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Record;
+    use serde_json::json;
+
+    fn page(blocks: Vec<(&str, Value)>) -> RecordMap {
+        let mut map = RecordMap::default();
+        for (id, value) in blocks {
+            map.block.insert(id.to_string(), Record::new(value));
+        }
+        map
+    }
+
+    fn text(parent: &str, title: &str) -> Value {
+        json!({ "type": "text", "parent_id": parent, "properties": { "title": [[title]] } })
+    }
+
+    fn before() -> RecordMap {
+        page(vec![
+            ("p", json!({ "type": "page", "content": ["a", "b", "c", "d"] })),
+            ("a", text("p", "The quick fox")),
+            ("b", text("p", "Second")),
+            ("c", text("p", "Third")),
+            ("d", text("p", "Gone soon")),
+        ])
+    }
+
+    #[test]
+    fn test_detects_each_kind_of_change() {
+        let after = page(vec![
+            ("p", json!({ "type": "page", "content": ["a", "c", "b", "e"] })),
+            ("a", text("p", "The quick brown fox")),
+            ("b", text("p", "Second")),
+            ("c", text("p", "Third")),
+            ("e", text("p", "New")),
+        ]);
+        let diff = diff(&before(), &after);
+        let ids: Vec<(&str, &str)> = diff
+            .changes
+            .iter()
+            .map(|c| {
+                let kind = match c {
+                    BlockChange::Inserted { .. } => "inserted",
+                    BlockChange::Deleted { .. } => "deleted",
+                    BlockChange::Moved { .. } => "moved",
+                    BlockChange::Modified { .. } => "modified",
+                };
+                (c.id(), kind)
+            })
+            .collect();
+        // Only one of b and c needs to move to restore the order
+        assert_eq!(ids, vec![("a", "modified"), ("c", "moved"), ("e", "inserted"), ("d", "deleted")]);
+
+        let BlockChange::Modified { properties, .. } = &diff.changes[0] else {
+            unreachable!()
+        };
+        assert_eq!(
+            properties[0].words.as_ref().unwrap(),
+            &vec![
+                TextSpan { kind: SpanKind::Equal, text: "The quick ".into() },
+                TextSpan { kind: SpanKind::Insert, text: "brown ".into() },
+                TextSpan { kind: SpanKind::Equal, text: "fox".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reparenting_and_archiving() {
+        let mut after = before();
+        after.block.insert("p".into(), Record::new(json!({ "type": "page", "content": ["a", "c", "d"] })));
+        after.block.insert("c".into(), Record::new(json!({ "type": "toggle", "parent_id": "p", "content": ["b"] })));
+        after.block.insert("b".into(), Record::new(text("c", "Second")));
+        after.block.get_mut("d").unwrap().value["alive"] = json!(false);
+
+        let diff = diff(&before(), &after);
+        assert!(matches!(
+            &diff.changes[..],
+            [
+                BlockChange::Modified { id: c, .. },
+                BlockChange::Moved { id: b, from, to, .. },
+                BlockChange::Deleted { id: d, .. },
+            ] if c == "c" && b == "b" && d == "d"
+                && from.parent.as_deref() == Some("p") && to == &Position { parent: Some("c".into()), index: Some(0) }
+        ));
+        assert!(super::diff(&after, &after).is_empty());
+    }
+}
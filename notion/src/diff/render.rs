@@ -0,0 +1,203 @@
+//! Text and HTML views of a page diff
+
+use std::fmt::Write;
+
+use serde_json::Value;
+
+use super::{BlockChange, PageDiff, Position, PropertyChange, SpanKind, TextSpan};
+
+const STYLE: &str = "table.notion-diff{border-collapse:collapse;width:100%;font-family:sans-serif}\
+.notion-diff td,.notion-diff th{border:1px solid #ddd;padding:4px 8px;vertical-align:top;width:50%}\
+.notion-diff th{background:#f6f6f6;text-align:left;font-weight:normal;color:#555}\
+.notion-diff del{background:#fdd}.notion-diff ins{background:#dfd;text-decoration:none}\
+.notion-diff tr.deleted td.before{background:#fee}.notion-diff tr.inserted td.after{background:#efe}";
+
+impl PageDiff {
+    /// Patch-like text, one entry per change
+    ///
+    /// `+` inserted, `-` deleted, `>` moved, `~` modified. Word changes in
+    /// rich text are marked `[-removed-]` and `{+added+}` as in `git diff --word-diff`.
+    pub fn to_unified(&self) -> String {
+        let mut out = String::from("--- before\n+++ after\n");
+        for change in &self.changes {
+            match change {
+                BlockChange::Inserted { id, block_type, text, at } => {
+                    let _ = writeln!(out, "+ {} {} {:?} at {}", block_type, id, text, position(at));
+                }
+                BlockChange::Deleted { id, block_type, text, at } => {
+                    let _ = writeln!(out, "- {} {} {:?} from {}", block_type, id, text, position(at));
+                }
+                BlockChange::Moved { id, block_type, text, from, to } => {
+                    let _ = writeln!(out, "> {} {} {:?} {} -> {}", block_type, id, text, position(from), position(to));
+                }
+                BlockChange::Modified { id, block_type, text, properties } => {
+                    let _ = writeln!(out, "~ {} {} {:?}", block_type, id, text);
+                    for property in properties {
+                        let _ = writeln!(out, "    {}: {}", property.path, inline_change(property));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Standalone HTML page with the old version on the left and the new on the right
+    pub fn to_html(&self) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><style>{}</style></head><body>\n\
+             <table class=\"notion-diff\">\n<tr><th>Before</th><th>After</th></tr>\n",
+            STYLE
+        );
+        for change in &self.changes {
+            let (class, id, block_type, before, after) = match change {
+                BlockChange::Inserted { id, block_type, text, .. } => {
+                    ("inserted", id, block_type, String::new(), format!("<ins>{}</ins>", escape(text)))
+                }
+                BlockChange::Deleted { id, block_type, text, .. } => {
+                    ("deleted", id, block_type, format!("<del>{}</del>", escape(text)), String::new())
+                }
+                BlockChange::Moved { id, block_type, text, from, to } => (
+                    "moved",
+                    id,
+                    block_type,
+                    format!("{}<br><small>at {}</small>", escape(text), escape(&position(from))),
+                    format!("{}<br><small>at {}</small>", escape(text), escape(&position(to))),
+                ),
+                BlockChange::Modified { id, block_type, properties, .. } => {
+                    let (mut before, mut after) = (String::new(), String::new());
+                    for property in properties {
+                        let (old, new) = side_by_side(property);
+                        let label = format!("<small>{}</small><br>", escape(&property.path));
+                        before.push_str(&format!("<div>{}{}</div>", label, old));
+                        after.push_str(&format!("<div>{}{}</div>", label, new));
+                    }
+                    ("modified", id, block_type, before, after)
+                }
+            };
+            let _ = write!(
+                out,
+                "<tr><th colspan=\"2\">{} {} <code>{}</code></th></tr>\n\
+                 <tr class=\"{}\"><td class=\"before\">{}</td><td class=\"after\">{}</td></tr>\n",
+                class,
+                escape(block_type),
+                escape(id),
+                class,
+                before,
+                after
+            );
+        }
+        out.push_str("</table>\n</body></html>\n");
+        out
+    }
+}
+
+fn position(at: &Position) -> String {
+    let parent = at.parent.as_deref().unwrap_or("(root)");
+    match at.index {
+        Some(index) => format!("{}[{}]", parent, index),
+        None => parent.to_string(),
+    }
+}
+
+fn compact(value: &Value) -> String {
+    if value.is_null() {
+        "(none)".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn inline_change(property: &PropertyChange) -> String {
+    let Some(words) = &property.words else {
+        return format!("{} -> {}", compact(&property.old), compact(&property.new));
+    };
+    words
+        .iter()
+        .map(|span| match span.kind {
+            SpanKind::Equal => span.text.clone(),
+            SpanKind::Delete => format!("[-{}-]", span.text),
+            SpanKind::Insert => format!("{{+{}+}}", span.text),
+        })
+        .collect()
+}
+
+fn side_by_side(property: &PropertyChange) -> (String, String) {
+    let Some(words) = &property.words else {
+        return (escape(&compact(&property.old)), escape(&compact(&property.new)));
+    };
+    let side = |keep: SpanKind, tag: &str| -> String {
+        words
+            .iter()
+            .filter(|span| span.kind == SpanKind::Equal || span.kind == keep)
+            .map(|TextSpan { kind, text }| match kind {
+                SpanKind::Equal => escape(text),
+                _ => format!("<{0}>{1}</{0}>", tag, escape(text)),
+            })
+            .collect()
+    };
+    (side(SpanKind::Delete, "del"), side(SpanKind::Insert, "ins"))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::word_diff;
+    use super::*;
+    use serde_json::json;
+
+    fn sample() -> PageDiff {
+        PageDiff {
+            changes: vec![
+                BlockChange::Modified {
+                    id: "a".into(),
+                    block_type: "text".into(),
+                    text: "Hello <world>".into(),
+                    properties: vec![
+                        PropertyChange {
+                            path: "properties.title".into(),
+                            old: json!([["Hello there"]]),
+                            new: json!([["Hello <world>"]]),
+                            words: Some(word_diff("Hello there", "Hello <world>")),
+                        },
+                        PropertyChange {
+                            path: "format.block_color".into(),
+                            old: Value::Null,
+                            new: json!("red"),
+                            words: None,
+                        },
+                    ],
+                },
+                BlockChange::Inserted {
+                    id: "b".into(),
+                    block_type: "to_do".into(),
+                    text: "Buy milk".into(),
+                    at: Position { parent: Some("p".into()), index: Some(1) },
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_unified() {
+        assert_eq!(
+            sample().to_unified(),
+            "--- before\n+++ after\n\
+             ~ text a \"Hello <world>\"\n    properties.title: Hello [-there-]{+<world>+}\n    format.block_color: (none) -> \"red\"\n\
+             + to_do b \"Buy milk\" at p[1]\n"
+        );
+    }
+
+    #[test]
+    fn test_html_escapes_and_marks_words() {
+        let html = sample().to_html();
+        assert!(html.contains("<td class=\"before\"><div><small>properties.title</small><br>Hello <del>there</del></div>"));
+        assert!(html.contains("Hello <ins>&lt;world&gt;</ins>"));
+        assert!(html.contains("<tr class=\"inserted\"><td class=\"before\"></td><td class=\"after\"><ins>Buy milk</ins></td></tr>"));
+    }
+}
@@ -0,0 +1,290 @@
+//! Endpoint discovery from saved Notion web-app bundles
+//!
+//! Scans JavaScript files we downloaded ourselves for `/api/v3/<name>` calls,
+//! guesses the argument keys from the object literal that follows each call,
+//! and compares the result with `paths` or a report from an earlier release.
+//! Nothing here touches the network.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::api::endpoints::paths;
+
+/// How far after a call site we look for its argument object
+const ARGUMENT_WINDOW: usize = 400;
+
+/// An API path in bundle source, capturing the endpoint name
+static ENDPOINT_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:/api)?/v3/([A-Za-z][A-Za-z0-9]*)").expect("valid regex"));
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EndpointInfo {
+    /// `file:offset` of every match
+    pub occurrences: Vec<String>,
+    /// Top-level keys seen in argument objects near the call sites
+    pub argument_keys: BTreeSet<String>,
+}
+
+/// Endpoints found in one set of bundles, keyed by name (`loadPageChunk`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveryReport {
+    pub endpoints: BTreeMap<String, EndpointInfo>,
+}
+
+/// Difference between two reports, e.g. two app releases
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReportDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Endpoints whose argument keys changed: `(name, keys added, keys removed)`
+    pub changed: Vec<(String, Vec<String>, Vec<String>)>,
+}
+
+impl DiscoveryReport {
+    /// Scan every `.js`/`.mjs` file below `dir` (or `dir` itself if it is a file)
+    pub fn scan_path<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let mut report = DiscoveryReport::default();
+        for file in js_files(dir.as_ref())? {
+            let source = fs::read_to_string(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+            report.scan_source(&file.display().to_string(), &source);
+        }
+        Ok(report)
+    }
+
+    /// Scan one bundle's source text
+    pub fn scan_source(&mut self, name: &str, source: &str) {
+        for m in ENDPOINT_PATTERN.captures_iter(source) {
+            let whole = m.get(0).expect("group 0 always matches");
+            let endpoint = m[1].to_string();
+            let info = self.endpoints.entry(endpoint).or_default();
+            info.occurrences.push(format!("{}:{}", name, whole.start()));
+
+            let window_end = floor_char_boundary(source, whole.end() + ARGUMENT_WINDOW);
+            if let Some(keys) = argument_keys(&source[whole.end()..window_end]) {
+                info.argument_keys.extend(keys);
+            }
+        }
+        debug!("Scanned {}: {} endpoints so far", name, self.endpoints.len());
+    }
+
+    /// Discovered endpoints that have no constant in `paths`
+    pub fn missing_from_paths(&self) -> Vec<String> {
+        let known: BTreeSet<&str> = paths::ALL
+            .iter()
+            .filter_map(|(_, path)| path.strip_prefix("/v3/"))
+            .collect();
+        self.endpoints
+            .keys()
+            .filter(|name| !known.contains(name.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    /// Constants in `paths` that no longer appear in the bundles
+    pub fn unseen_paths(&self) -> Vec<&'static str> {
+        paths::ALL
+            .iter()
+            .filter(|(_, path)| {
+                let name = path.trim_start_matches("/v3/");
+                !self.endpoints.contains_key(name)
+            })
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// What changed going from `previous` to this report
+    pub fn diff(&self, previous: &DiscoveryReport) -> ReportDiff {
+        let mut diff = ReportDiff::default();
+        for (name, info) in &self.endpoints {
+            match previous.endpoints.get(name) {
+                None => diff.added.push(name.clone()),
+                Some(old) if old.argument_keys != info.argument_keys => diff.changed.push((
+                    name.clone(),
+                    info.argument_keys.difference(&old.argument_keys).cloned().collect(),
+                    old.argument_keys.difference(&info.argument_keys).cloned().collect(),
+                )),
+                Some(_) => {}
+            }
+        }
+        diff.removed = previous
+            .endpoints
+            .keys()
+            .filter(|name| !self.endpoints.contains_key(*name))
+            .cloned()
+            .collect();
+        diff
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = fs::read(path.as_ref()).with_context(|| format!("Failed to read {}", path.as_ref().display()))?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+fn js_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path).with_context(|| format!("Failed to list {}", path.display()))? {
+        let entry = entry?.path();
+        if entry.is_dir() {
+            files.extend(js_files(&entry)?);
+        } else if entry.extension().is_some_and(|e| e == "js" || e == "mjs") {
+            files.push(entry);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn floor_char_boundary(s: &str, mut index: usize) -> usize {
+    index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Top-level keys of the first object literal in `text`
+///
+/// Minified code is not parsed properly; this tracks nesting and string
+/// literals well enough to pull `{pageId:e,limit:100}` apart. The search stops
+/// at a `;` or closing paren before any `{`, since the call has ended by then.
+fn argument_keys(text: &str) -> Option<BTreeSet<String>> {
+    let start = text.find(['{', ';', ')']).filter(|&i| text.as_bytes()[i] == b'{')?;
+    let chars: Vec<char> = text[start + 1..].chars().collect();
+
+    let mut keys = BTreeSet::new();
+    let mut depth = 0;
+    let mut token = String::new();
+    let mut expecting_key = true;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' | '\'' | '`' => {
+                let quote = c;
+                let mut literal = String::new();
+                i += 1;
+                while i < chars.len() && chars[i] != quote {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    if let Some(&ch) = chars.get(i) {
+                        literal.push(ch);
+                    }
+                    i += 1;
+                }
+                if depth == 0 && expecting_key {
+                    token = literal;
+                }
+            }
+            '{' | '[' | '(' => depth += 1,
+            '}' | ']' | ')' if depth == 0 => {
+                if expecting_key && is_identifier(&token) {
+                    keys.insert(token.clone());
+                }
+                break;
+            }
+            '}' | ']' | ')' => depth -= 1,
+            ':' if depth == 0 && expecting_key => {
+                if !token.is_empty() {
+                    keys.insert(token.clone());
+                }
+                expecting_key = false;
+            }
+            ',' if depth == 0 => {
+                // Shorthand property: `{pageId,limit}`
+                if expecting_key && is_identifier(&token) {
+                    keys.insert(token.clone());
+                }
+                token.clear();
+                expecting_key = true;
+            }
+            c if depth == 0 && expecting_key && (c.is_alphanumeric() || c == '_' || c == '$') => token.push(c),
+            '.' if depth == 0 && expecting_key => {
+                // Spread `...rest` is not a key
+                token.clear();
+                expecting_key = false;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    (!keys.is_empty()).then_some(keys)
+}
+
+fn is_identifier(token: &str) -> bool {
+    token.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLE: &str = r#"
+        function a(e){return o.post("/api/v3/loadPageChunk",{pageId:e.id,limit:100,cursor:{stack:[]},"chunkNumber":0,verticalColumns:!1})}
+        const b=async t=>fetch("/api/v3/getNewThing",{method:"POST",body:JSON.stringify(t)});
+        n.send("/api/v3/syncRecordValues",{requests,...rest});
+        const u="/api/v3/submitTransaction";
+    "#;
+
+    fn report() -> DiscoveryReport {
+        let mut report = DiscoveryReport::default();
+        report.scan_source("app.js", BUNDLE);
+        report
+    }
+
+    #[test]
+    fn test_scan_finds_endpoints_and_arguments() {
+        let report = report();
+        let load = &report.endpoints["loadPageChunk"];
+        assert_eq!(
+            load.argument_keys.iter().map(String::as_str).collect::<Vec<_>>(),
+            vec!["chunkNumber", "cursor", "limit", "pageId", "verticalColumns"]
+        );
+        assert!(load.occurrences[0].starts_with("app.js:"));
+        assert_eq!(
+            report.endpoints["syncRecordValues"].argument_keys.iter().collect::<Vec<_>>(),
+            vec!["requests"]
+        );
+        assert!(report.endpoints["submitTransaction"].argument_keys.is_empty());
+    }
+
+    #[test]
+    fn test_missing_from_paths() {
+        let missing = report().missing_from_paths();
+        assert_eq!(missing, vec!["getNewThing"]);
+    }
+
+    #[test]
+    fn test_diff_between_releases() {
+        let old = report();
+        let mut new = DiscoveryReport::default();
+        new.scan_source(
+            "app.js",
+            r#"o.post("/api/v3/loadPageChunk",{page:{id:e},limit:100});x("/api/v3/getActivityLog",{})"#,
+        );
+
+        let diff = new.diff(&old);
+        assert_eq!(diff.added, vec!["getActivityLog"]);
+        assert_eq!(diff.removed, vec!["getNewThing", "submitTransaction", "syncRecordValues"]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].1, vec!["page"]);
+        assert!(diff.changed[0].2.contains(&"pageId".to_string()));
+    }
+}
@@ -0,0 +1,478 @@
+//! Schema drift detection
+//!
+//! Deserializes responses into the `models` types and reports where the two
+//! disagree: fields we do not model, required fields that went missing and
+//! enum variants we have never seen. Paths are generalized (`[]` for array
+//! indexes, `*` for record ids) so one change to a block shape shows up once
+//! with a count rather than once per block. Record values are untyped in
+//! the response models, so blocks in a `recordMap` are also checked against
+//! [`Block`](crate::models::Block).
+
+use std::fmt;
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::fixture::Fixture;
+use crate::models::{
+    Block, GetRecordValuesResponse, LoadPageChunkResponse, QueryCollectionResponse, SearchResponse,
+    UploadFileUrlResponse,
+};
+use crate::utils::to_uuid_format;
+
+/// Give up after this many errors in one document
+const MAX_ERRORS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    UnknownField,
+    MissingField,
+    UnknownVariant,
+    InvalidType,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DriftIssue {
+    pub kind: IssueKind,
+    /// Generalized path, e.g. `recordMap.block.*.value.format`
+    pub path: String,
+    pub detail: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DriftReport {
+    /// Model type the responses were checked against
+    pub type_name: String,
+    /// Number of documents checked
+    pub documents: usize,
+    pub issues: Vec<DriftIssue>,
+}
+
+impl DriftReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Fold another report for the same type into this one
+    pub fn merge(&mut self, other: DriftReport) {
+        self.documents += other.documents;
+        for issue in other.issues {
+            self.add(issue.kind, issue.path, issue.detail, issue.count);
+        }
+    }
+
+    fn add(&mut self, kind: IssueKind, path: String, detail: String, count: usize) {
+        match self
+            .issues
+            .iter_mut()
+            .find(|i| i.kind == kind && i.path == path && i.detail == detail)
+        {
+            Some(existing) => existing.count += count,
+            None => {
+                self.issues.push(DriftIssue { kind, path, detail, count });
+                self.issues.sort_by(|a, b| (a.kind, &a.path).cmp(&(b.kind, &b.path)));
+            }
+        }
+    }
+}
+
+impl fmt::Display for DriftReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_clean() {
+            return write!(f, "{}: no drift in {} document(s)", self.type_name, self.documents);
+        }
+        writeln!(f, "{}: {} issue(s) in {} document(s)", self.type_name, self.issues.len(), self.documents)?;
+        for issue in &self.issues {
+            let kind = match issue.kind {
+                IssueKind::UnknownField => "unknown field",
+                IssueKind::MissingField => "missing field",
+                IssueKind::UnknownVariant => "unknown variant",
+                IssueKind::InvalidType => "invalid type",
+            };
+            writeln!(f, "  {:<16} {} ({}) x{}", kind, issue.path, issue.detail, issue.count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check one document against `T`
+///
+/// Errors inside array elements (e.g. one odd block) are recorded and the
+/// element is dropped so the rest of the document is still checked.
+pub fn check<T: DeserializeOwned + Serialize>(value: &Value) -> DriftReport {
+    let mut report = DriftReport {
+        type_name: short_type_name::<T>(),
+        documents: 1,
+        issues: Vec::new(),
+    };
+    let mut working = value.clone();
+
+    for _ in 0..MAX_ERRORS {
+        match serde_path_to_error::deserialize::<_, T>(&working) {
+            Ok(parsed) => {
+                let round_trip = serde_json::to_value(&parsed).unwrap_or(Value::Null);
+                let mut unknown = Vec::new();
+                unknown_fields(&working, &round_trip, &mut Vec::new(), &mut unknown);
+                for path in unknown {
+                    report.add(IssueKind::UnknownField, path, String::new(), 1);
+                }
+                break;
+            }
+            Err(err) => {
+                let segments: Vec<String> = err.path().iter().map(segment_name).collect();
+                let message = err.inner().to_string();
+                let (kind, detail) = classify(&message);
+                report.add(kind, generalize(&segments), detail, 1);
+
+                if !drop_array_element(&mut working, &segments) {
+                    break;
+                }
+            }
+        }
+    }
+
+    check_blocks(value, &mut report);
+    report
+}
+
+/// Check every block in the document's `recordMap` against [`Block`]
+fn check_blocks(value: &Value, report: &mut DriftReport) {
+    let blocks = value.get("recordMap").and_then(|m| m.get("block")).and_then(Value::as_object);
+    for record in blocks.into_iter().flat_map(|b| b.values()) {
+        let Some(block) = record.get("value").filter(|v| !v.is_null()) else {
+            continue;
+        };
+        for issue in check::<Block>(block).issues {
+            let path = match issue.path.as_str() {
+                "." => "recordMap.block.*.value".to_string(),
+                inner => format!("recordMap.block.*.value.{}", inner),
+            };
+            report.add(issue.kind, path, issue.detail, issue.count);
+        }
+    }
+}
+
+/// Check the response of a captured exchange against the matching model type
+///
+/// Returns `None` for error responses and for endpoints without a typed
+/// response model.
+pub fn check_fixture(fixture: &Fixture) -> Option<DriftReport> {
+    if !(200..300).contains(&fixture.status) {
+        return None;
+    }
+    let value = &fixture.response;
+    let report = match fixture.endpoint.as_str() {
+        "loadPageChunk" => check::<LoadPageChunkResponse>(value),
+        "getRecordValues" => check::<GetRecordValuesResponse>(value),
+        "search" => check::<SearchResponse>(value),
+        "queryCollection" => check::<QueryCollectionResponse>(value),
+        "getUploadFileUrl" => check::<UploadFileUrlResponse>(value),
+        _ => return None,
+    };
+    Some(report)
+}
+
+type HookFn = dyn Fn(&str, &DriftReport) + Send + Sync;
+
+/// Callback invoked by the client when a response does not match its model
+#[derive(Clone)]
+pub struct DriftHook(Arc<HookFn>);
+
+impl DriftHook {
+    pub fn new<F: Fn(&str, &DriftReport) + Send + Sync + 'static>(f: F) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// Log drift as a `tracing` warning
+    pub fn log() -> Self {
+        Self::new(|path, report| warn!("Response of {} drifted from the model:\n{}", path, report))
+    }
+
+    pub fn call(&self, path: &str, report: &DriftReport) {
+        (self.0)(path, report)
+    }
+}
+
+impl fmt::Debug for DriftHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DriftHook")
+    }
+}
+
+fn segment_name(segment: &serde_path_to_error::Segment) -> String {
+    use serde_path_to_error::Segment;
+
+    match segment {
+        Segment::Seq { index } => index.to_string(),
+        Segment::Map { key } => key.clone(),
+        Segment::Enum { variant } => variant.clone(),
+        Segment::Unknown => "?".to_string(),
+    }
+}
+
+fn classify(message: &str) -> (IssueKind, String) {
+    let quoted = message.split('`').nth(1).unwrap_or_default().to_string();
+    if message.starts_with("missing field") {
+        (IssueKind::MissingField, quoted)
+    } else if message.starts_with("unknown variant") {
+        (IssueKind::UnknownVariant, quoted)
+    } else {
+        (IssueKind::InvalidType, message.to_string())
+    }
+}
+
+/// Keys present in `input` that did not survive deserialize + serialize
+fn unknown_fields(input: &Value, parsed: &Value, path: &mut Vec<String>, found: &mut Vec<String>) {
+    match (input, parsed) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, value) in a {
+                path.push(key.clone());
+                match b.get(key) {
+                    Some(other) => unknown_fields(value, other, path, found),
+                    // Nulls and empty tables are dropped by `skip_serializing_if`
+                    None if !is_empty(value) => found.push(generalize(path)),
+                    None => {}
+                }
+                path.pop();
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for (i, (x, y)) in a.iter().zip(b).enumerate() {
+                path.push(i.to_string());
+                unknown_fields(x, y, path, found);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Object(map) => map.is_empty(),
+        Value::Array(items) => items.is_empty(),
+        _ => false,
+    }
+}
+
+/// Remove the innermost array element on `path`; false if there is none
+fn drop_array_element(value: &mut Value, path: &[String]) -> bool {
+    let mut last_array = None;
+    let mut current = &*value;
+    for (depth, segment) in path.iter().enumerate() {
+        let next = match current {
+            Value::Array(items) => {
+                let index: Option<usize> = segment.parse().ok();
+                if index.is_some() {
+                    last_array = Some(depth);
+                }
+                index.and_then(|i| items.get(i))
+            }
+            Value::Object(map) => map.get(segment),
+            _ => None,
+        };
+        match next {
+            Some(next) => current = next,
+            None => break,
+        }
+    }
+
+    let Some(depth) = last_array else {
+        return false;
+    };
+    let mut target = value;
+    for segment in &path[..depth] {
+        target = match target {
+            Value::Array(items) => &mut items[segment.parse::<usize>().expect("index was parsed above")],
+            Value::Object(map) => map.get_mut(segment).expect("segment was walked above"),
+            _ => return false,
+        };
+    }
+    match (target, path[depth].parse::<usize>()) {
+        (Value::Array(items), Ok(index)) if index < items.len() => {
+            items.remove(index);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn generalize(path: &[String]) -> String {
+    let parts: Vec<&str> = path
+        .iter()
+        .map(|s| {
+            if s.parse::<usize>().is_ok() {
+                "[]"
+            } else if to_uuid_format(s).is_some() {
+                "*"
+            } else {
+                s.as_str()
+            }
+        })
+        .collect();
+    if parts.is_empty() {
+        ".".to_string()
+    } else {
+        parts.join(".")
+    }
+}
+
+fn short_type_name<T>() -> String {
+    std::any::type_name::<T>().rsplit("::").next().unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Color {
+        Red,
+        Blue,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Item {
+        id: String,
+        color: Color,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Doc {
+        items: Vec<Item>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        note: Option<String>,
+    }
+
+    #[test]
+    fn test_collects_every_kind_of_drift() {
+        let doc = json!({
+            "items": [
+                { "id": "a", "color": "red", "shade": 3 },
+                { "id": "b", "color": "green" },
+                { "color": "blue" },
+                { "id": "c", "color": "blue", "shade": 1 },
+            ],
+            "note": null,
+            "extra": true,
+            "empty": {},
+            "none": [],
+        });
+
+        let report = check::<Doc>(&doc);
+        let summary: Vec<(IssueKind, &str, &str, usize)> = report
+            .issues
+            .iter()
+            .map(|i| (i.kind, i.path.as_str(), i.detail.as_str(), i.count))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (IssueKind::UnknownField, "extra", "", 1),
+                (IssueKind::UnknownField, "items.[].shade", "", 2),
+                (IssueKind::MissingField, "items.[]", "id", 1),
+                (IssueKind::UnknownVariant, "items.[].color", "green", 1),
+            ]
+        );
+        assert!(report.to_string().contains("unknown variant"));
+    }
+
+    fn block(id: &str, block_type: &str) -> Value {
+        json!({
+            "id": id, "version": 1, "type": block_type, "properties": {}, "alive": true,
+            "created_time": 0, "last_edited_time": 0,
+        })
+    }
+
+    #[test]
+    fn test_record_ids_are_generalized() {
+        let response = json!({
+            "recordMap": { "block": {
+                "11111111-1111-1111-1111-111111111111": { "value": block("x", "text"), "spaceId": "s" },
+                "22222222-2222-2222-2222-222222222222": { "value": block("y", "page"), "spaceId": "s" },
+            } },
+            "cursor": { "stack": [] },
+        });
+        let report = check::<LoadPageChunkResponse>(&response);
+        assert_eq!(report.issues.len(), 1, "{}", report);
+        assert_eq!(report.issues[0].path, "recordMap.block.*.spaceId");
+        assert_eq!(report.issues[0].count, 2);
+    }
+
+    #[test]
+    fn test_blocks_checked_against_model() {
+        let response = json!({
+            "recordMap": { "block": {
+                "11111111-1111-1111-1111-111111111111": { "value": block("x", "hologram") },
+                "22222222-2222-2222-2222-222222222222": { "value": { "id": "y" } },
+            } },
+        });
+        let report = check::<LoadPageChunkResponse>(&response);
+        let issues: Vec<(IssueKind, &str, &str)> =
+            report.issues.iter().map(|i| (i.kind, i.path.as_str(), i.detail.as_str())).collect();
+        assert!(issues.contains(&(IssueKind::UnknownVariant, "recordMap.block.*.value.type", "hologram")), "{}", report);
+        assert!(issues.contains(&(IssueKind::MissingField, "recordMap.block.*.value", "version")), "{}", report);
+    }
+
+    #[test]
+    fn test_clean_fixture() {
+        let fixture = Fixture {
+            endpoint: "getRecordValues".to_string(),
+            status: 200,
+            request: json!({}),
+            response: json!({ "results": [{ "role": "reader", "value": { "id": "a" } }] }),
+        };
+        let report = check_fixture(&fixture).unwrap();
+        assert!(report.is_clean(), "{}", report);
+        assert_eq!(report.type_name, "GetRecordValuesResponse");
+    }
+
+    #[test]
+    fn test_error_fixture_skipped() {
+        let fixture = Fixture {
+            endpoint: "loadPageChunk".to_string(),
+            status: 400,
+            request: json!({}),
+            response: json!({ "errorId": "e", "name": "ValidationError", "message": "Invalid input." }),
+        };
+        assert!(check_fixture(&fixture).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_client_hook_reports_drift() {
+        use crate::api::endpoints::RecordPointer;
+        use crate::api::NotionClient;
+        use axum::{routing::post, Json, Router};
+        use std::sync::Mutex;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/api", listener.local_addr().unwrap());
+        let app = Router::new().route(
+            "/api/v3/getRecordValues",
+            post(|| async { Json(json!({ "results": [{ "value": { "id": "a" }, "spaceId": "s" }] })) }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let client = NotionClient::from_token("token".to_string())
+            .with_base_url(&base)
+            .with_drift_hook(DriftHook::new(move |path, report| {
+                sink.lock().unwrap().push((path.to_string(), report.issues[0].path.clone()));
+            }));
+
+        client.get_record_values(&[RecordPointer::block("a")]).await.unwrap();
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![("/v3/getRecordValues".to_string(), "results.[].spaceId".to_string())]
+        );
+    }
+}
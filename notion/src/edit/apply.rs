@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+
+use crate::api::endpoints::Operation;
+use crate::models::{Record, RecordMap};
+
+/// Apply a single operation to `records`, mirroring what the server does
+///
+/// Records that do not exist yet are created as empty objects first, the same
+/// way `submitTransaction` treats a `set`/`update` on a fresh id. Versions are
+/// left untouched; the server-assigned version shows up on the next fetch.
+pub fn apply_operation(records: &mut RecordMap, op: &Operation) -> Result<()> {
+    let record = records
+        .table_mut(&op.table)
+        .entry(op.id.clone())
+        .or_insert_with(|| Record::new(Value::Object(Map::new())));
+
+    apply_to_value(&mut record.value, op)
+        .with_context(|| format!("Failed to apply {} on {}/{}", op.command, op.table, op.id))
+}
+
+/// Apply operations in order, all or nothing
+///
+/// If any operation fails every touched record is restored, so `records` is
+/// never left half-updated.
+pub fn apply_operations(records: &mut RecordMap, ops: &[Operation]) -> Result<()> {
+    let mut saved: HashMap<(String, String), Option<Record>> = HashMap::new();
+
+    for op in ops {
+        saved
+            .entry((op.table.clone(), op.id.clone()))
+            .or_insert_with(|| records.table(&op.table).and_then(|t| t.get(&op.id)).cloned());
+
+        if let Err(e) = apply_operation(records, op) {
+            for ((table, id), original) in saved {
+                let table = records.table_mut(&table);
+                match original {
+                    Some(record) => {
+                        table.insert(id, record);
+                    }
+                    None => {
+                        table.remove(&id);
+                    }
+                }
+            }
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Return a copy of `records` with `ops` applied, leaving the original alone
+pub fn preview(records: &RecordMap, ops: &[Operation]) -> Result<RecordMap> {
+    let mut copy = records.clone();
+    apply_operations(&mut copy, ops)?;
+    Ok(copy)
+}
+
+fn apply_to_value(value: &mut Value, op: &Operation) -> Result<()> {
+    match op.command.as_str() {
+        "set" => {
+            *target_mut(value, &op.path) = op.args.clone();
+        }
+        "update" => {
+            let Some(args) = op.args.as_object() else {
+                bail!("update args must be an object");
+            };
+            let target = target_mut(value, &op.path);
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            let object = target.as_object_mut().expect("target was just made an object");
+            for (key, val) in args {
+                object.insert(key.clone(), val.clone());
+            }
+        }
+        "listAfter" => {
+            let id = list_item(&op.args)?;
+            let list = list_mut(value, &op.path);
+            list.retain(|v| v != &id);
+            let index = op
+                .args
+                .get("after")
+                .and_then(|after| list.iter().position(|v| v == after))
+                .map(|i| i + 1)
+                .unwrap_or(list.len());
+            list.insert(index, id);
+        }
+        "listBefore" => {
+            let id = list_item(&op.args)?;
+            let list = list_mut(value, &op.path);
+            list.retain(|v| v != &id);
+            let index = op
+                .args
+                .get("before")
+                .and_then(|before| list.iter().position(|v| v == before))
+                .unwrap_or(0);
+            list.insert(index, id);
+        }
+        "listRemove" => {
+            let id = list_item(&op.args)?;
+            list_mut(value, &op.path).retain(|v| v != &id);
+        }
+        "setParent" => {
+            let parent_id = op
+                .args
+                .get("parentId")
+                .context("setParent args are missing parentId")?;
+            let parent_table = op
+                .args
+                .get("parentTable")
+                .context("setParent args are missing parentTable")?;
+            let target = target_mut(value, &op.path);
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            target["parent_id"] = parent_id.clone();
+            target["parent_table"] = parent_table.clone();
+        }
+        other => bail!("Unsupported command: {}", other),
+    }
+
+    Ok(())
+}
+
+/// Walk `path`, creating intermediate objects as needed
+fn target_mut<'a>(value: &'a mut Value, path: &[String]) -> &'a mut Value {
+    path.iter().fold(value, |current, key| {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        current
+            .as_object_mut()
+            .expect("current was just made an object")
+            .entry(key.clone())
+            .or_insert(Value::Null)
+    })
+}
+
+fn list_mut<'a>(value: &'a mut Value, path: &[String]) -> &'a mut Vec<Value> {
+    let target = target_mut(value, path);
+    if !target.is_array() {
+        *target = Value::Array(Vec::new());
+    }
+    target.as_array_mut().expect("target was just made an array")
+}
+
+fn list_item(args: &Value) -> Result<Value> {
+    args.get("id").cloned().context("list command args are missing id")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn page(content: &[&str]) -> RecordMap {
+        let mut records = RecordMap::default();
+        records.block.insert(
+            "page".to_string(),
+            Record::new(json!({ "id": "page", "version": 3, "type": "page", "content": content })),
+        );
+        records
+    }
+
+    fn content(records: &RecordMap) -> Value {
+        records.get("block", "page").unwrap()["content"].clone()
+    }
+
+    #[test]
+    fn test_set_nested_and_root() {
+        let mut records = page(&[]);
+        apply_operation(
+            &mut records,
+            &Operation::set("block", "page", &["properties", "title"], json!([["Hello"]])),
+        )
+        .unwrap();
+        assert_eq!(records.get("block", "page").unwrap()["properties"]["title"], json!([["Hello"]]));
+
+        apply_operation(&mut records, &Operation::set("block", "new", &[], json!({ "id": "new" }))).unwrap();
+        assert_eq!(records.get("block", "new").unwrap(), &json!({ "id": "new" }));
+    }
+
+    #[test]
+    fn test_update_merges_keys() {
+        let mut records = page(&["a"]);
+        apply_operation(
+            &mut records,
+            &Operation::update("block", "page", &[], json!({ "alive": false, "type": "text" })),
+        )
+        .unwrap();
+        let value = records.get("block", "page").unwrap();
+        assert_eq!(value["alive"], json!(false));
+        assert_eq!(value["type"], json!("text"));
+        assert_eq!(value["content"], json!(["a"]));
+        assert_eq!(value["version"], json!(3));
+    }
+
+    #[test]
+    fn test_list_after() {
+        let mut records = page(&["a", "b", "c"]);
+        apply_operation(&mut records, &Operation::list_after("block", "page", &["content"], "x", Some("a"))).unwrap();
+        assert_eq!(content(&records), json!(["a", "x", "b", "c"]));
+
+        // Missing anchor appends, existing item is moved rather than duplicated
+        apply_operation(&mut records, &Operation::list_after("block", "page", &["content"], "a", None)).unwrap();
+        assert_eq!(content(&records), json!(["x", "b", "c", "a"]));
+    }
+
+    #[test]
+    fn test_list_before() {
+        let mut records = page(&["a", "b"]);
+        apply_operation(&mut records, &Operation::list_before("block", "page", &["content"], "x", Some("b"))).unwrap();
+        assert_eq!(content(&records), json!(["a", "x", "b"]));
+
+        apply_operation(&mut records, &Operation::list_before("block", "page", &["content"], "y", None)).unwrap();
+        assert_eq!(content(&records), json!(["y", "a", "x", "b"]));
+    }
+
+    #[test]
+    fn test_list_remove_and_set_parent() {
+        let mut records = page(&["a", "b"]);
+        apply_operation(&mut records, &Operation::list_remove("block", "page", &["content"], "a")).unwrap();
+        assert_eq!(content(&records), json!(["b"]));
+
+        apply_operation(&mut records, &Operation::set_parent("block", "b", "page", "block")).unwrap();
+        let child = records.get("block", "b").unwrap();
+        assert_eq!(child["parent_id"], json!("page"));
+        assert_eq!(child["parent_table"], json!("block"));
+    }
+
+    #[test]
+    fn test_apply_operations_is_atomic() {
+        let mut records = page(&["a"]);
+        let ops = vec![
+            Operation::list_after("block", "page", &["content"], "b", None),
+            Operation::set("block", "fresh", &[], json!({ "id": "fresh" })),
+            Operation::new("block", "page", &[], "bogus", json!({})),
+        ];
+
+        assert!(apply_operations(&mut records, &ops).is_err());
+        assert_eq!(content(&records), json!(["a"]));
+        assert!(records.get("block", "fresh").is_none());
+    }
+
+    #[test]
+    fn test_preview_leaves_original() {
+        let records = page(&["a"]);
+        let ops = [Operation::list_remove("block", "page", &["content"], "a")];
+        let previewed = preview(&records, &ops).unwrap();
+        assert_eq!(content(&previewed), json!([]));
+        assert_eq!(content(&records), json!(["a"]));
+    }
+}
@@ -0,0 +1,297 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::info;
+
+use super::apply::apply_operation;
+use crate::api::endpoints::{Operation, RecordPointer};
+use crate::api::NotionClient;
+use crate::models::{Record, RecordMap};
+
+/// Compute operations that undo `ops` when applied after them
+///
+/// `prior` must hold the state of every touched record before `ops` ran.
+/// Records created by a `set` or `update` cannot be deleted, so they are
+/// archived with `alive: false` instead.
+pub fn invert(prior: &RecordMap, ops: &[Operation]) -> Result<Vec<Operation>> {
+    let mut state = prior.clone();
+    let mut created: Vec<RecordPointer> = Vec::new();
+    let mut inverse = Vec::new();
+
+    for op in ops {
+        let pointer = op.pointer();
+        let before = state.get(&op.table, &op.id).cloned().unwrap_or(Value::Null);
+        if created.contains(&pointer) {
+            // Archiving undoes every later change as well
+        } else if before.is_null() && matches!(op.command.as_str(), "set" | "update") {
+            created.push(pointer);
+        } else {
+            inverse.push(invert_one(&before, op)?);
+        }
+        apply_operation(&mut state, op)?;
+    }
+
+    inverse.reverse();
+    inverse.extend(
+        created
+            .iter()
+            .map(|p| Operation::update(&p.table, &p.id, &[], json!({ "alive": false }))),
+    );
+    Ok(inverse)
+}
+
+fn invert_one(before: &Value, op: &Operation) -> Result<Operation> {
+    let path: Vec<&str> = op.path.iter().map(String::as_str).collect();
+    let at_path = op.path.iter().try_fold(before, |v, key| v.get(key));
+
+    let inverse = match op.command.as_str() {
+        "set" => restore(before, op),
+        "update" => {
+            let keys = op.args.as_object().context("update args must be an object")?;
+            let old: Option<serde_json::Map<String, Value>> = keys
+                .keys()
+                .map(|k| Some((k.clone(), at_path?.get(k)?.clone())))
+                .collect();
+            match old {
+                Some(old) => Operation::update(&op.table, &op.id, &path, Value::Object(old)),
+                None => restore(before, op),
+            }
+        }
+        "listAfter" | "listBefore" | "listRemove" => {
+            let item = op.args.get("id").and_then(Value::as_str).context("list command args are missing id")?;
+            let list: Vec<&str> = at_path
+                .and_then(Value::as_array)
+                .map(|l| l.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            match list.iter().position(|v| *v == item) {
+                None => Operation::list_remove(&op.table, &op.id, &path, item),
+                Some(0) => Operation::list_before(&op.table, &op.id, &path, item, list.get(1).copied()),
+                Some(i) => Operation::list_after(&op.table, &op.id, &path, item, Some(list[i - 1])),
+            }
+        }
+        "setParent" => {
+            let parent = |key: &str| at_path.and_then(|v| v.get(key)).and_then(Value::as_str);
+            match (parent("parent_id"), parent("parent_table")) {
+                (Some(id), Some(table)) => Operation::set_parent(&op.table, &op.id, id, table),
+                _ => restore(before, op),
+            }
+        }
+        other => bail!("Cannot invert unsupported command: {}", other),
+    };
+
+    Ok(inverse)
+}
+
+/// `set` putting back the deepest part of `op.path` that existed in `before`
+///
+/// No command deletes keys, so when the path (or a key below it) was added
+/// the nearest existing ancestor is restored whole.
+fn restore(before: &Value, op: &Operation) -> Operation {
+    let mut current = before;
+    let mut depth = 0;
+    for key in &op.path {
+        let Some(next) = current.get(key) else {
+            break;
+        };
+        current = next;
+        depth += 1;
+    }
+    let path: Vec<&str> = op.path[..depth].iter().map(String::as_str).collect();
+    Operation::set(&op.table, &op.id, &path, current.clone())
+}
+
+/// One submitted transaction and what it takes to undo it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub operations: Vec<Operation>,
+    pub inverse: Vec<Operation>,
+    /// Server state of each touched record before the transaction
+    pub prior: Vec<(RecordPointer, Record)>,
+}
+
+impl JournalEntry {
+    pub fn new(prior: &RecordMap, operations: Vec<Operation>) -> Result<Self> {
+        let inverse = invert(prior, &operations)?;
+        let mut pointers: Vec<RecordPointer> = operations.iter().map(Operation::pointer).collect();
+        pointers.sort();
+        pointers.dedup();
+        let prior = pointers
+            .into_iter()
+            .filter_map(|p| {
+                let record = prior.table(&p.table)?.get(&p.id)?.clone();
+                Some((p, record))
+            })
+            .collect();
+
+        Ok(Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            operations,
+            inverse,
+            prior,
+        })
+    }
+}
+
+/// Append-only JSON Lines file of journal entries
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// `notion-re/journal.jsonl` in the platform data directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|d| d.join("notion-re").join("journal.jsonl"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, entry: &JournalEntry) -> Result<()> {
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open journal {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    /// All entries, oldest first
+    pub fn entries(&self) -> Result<Vec<JournalEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        fs::read_to_string(&self.path)?
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| serde_json::from_str(l).context("Invalid journal entry"))
+            .collect()
+    }
+
+    pub fn find(&self, id: &str) -> Result<Option<JournalEntry>> {
+        Ok(self.entries()?.into_iter().find(|e| e.id == id))
+    }
+}
+
+/// Undo a journaled transaction by submitting its inverse operations
+pub async fn rollback(client: &NotionClient, entry: &JournalEntry) -> Result<()> {
+    info!("Rolling back journal entry {} ({} operations)", entry.id, entry.inverse.len());
+    client.submit_transaction(entry.inverse.clone()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit::preview;
+
+    fn prior() -> RecordMap {
+        let mut records = RecordMap::default();
+        records.block.insert(
+            "page".to_string(),
+            Record::new(json!({
+                "id": "page",
+                "content": ["a", "b", "c"],
+                "properties": { "title": [["Old"]] },
+            })),
+        );
+        records.block.insert(
+            "b".to_string(),
+            Record::new(json!({ "id": "b", "parent_id": "page", "parent_table": "block", "alive": true })),
+        );
+        records
+    }
+
+    fn assert_round_trip(ops: Vec<Operation>) {
+        let prior = prior();
+        let after = preview(&prior, &ops).unwrap();
+        let inverse = invert(&prior, &ops).unwrap();
+        let restored = preview(&after, &inverse).unwrap();
+        for id in ["page", "b"] {
+            assert_eq!(restored.get("block", id), prior.get("block", id), "{} differs", id);
+        }
+    }
+
+    #[test]
+    fn test_invert_set_and_update() {
+        assert_round_trip(vec![
+            Operation::set("block", "page", &["properties", "title"], json!([["New"]])),
+            Operation::update("block", "b", &[], json!({ "alive": false, "parent_id": "x" })),
+        ]);
+    }
+
+    #[test]
+    fn test_invert_update_removes_added_keys() {
+        assert_round_trip(vec![
+            Operation::update("block", "b", &[], json!({ "alive": false, "format": { "page_icon": "x" } })),
+            Operation::update("block", "page", &["properties"], json!({ "status": [["Done"]] })),
+        ]);
+    }
+
+    #[test]
+    fn test_invert_list_moves() {
+        assert_round_trip(vec![
+            Operation::list_remove("block", "page", &["content"], "a"),
+            Operation::list_after("block", "page", &["content"], "b", None),
+            Operation::set_parent("block", "b", "other", "block"),
+            Operation::list_before("block", "page", &["content"], "x", None),
+        ]);
+    }
+
+    #[test]
+    fn test_invert_restores_added_paths() {
+        assert_round_trip(vec![
+            Operation::set("block", "page", &["format", "page_icon"], json!("x")),
+            Operation::set("block", "b", &["properties", "title"], json!([["New"]])),
+            Operation::set_parent("block", "page", "other", "block"),
+        ]);
+    }
+
+    #[test]
+    fn test_invert_archives_created_records() {
+        let ops = vec![
+            Operation::set("block", "new", &[], json!({ "id": "new", "alive": true })),
+            Operation::update("block", "new", &[], json!({ "type": "text" })),
+            Operation::list_after("block", "page", &["content"], "new", Some("c")),
+            Operation::set("block", "missing", &["properties", "title"], json!([["x"]])),
+        ];
+        let inverse = invert(&prior(), &ops).unwrap();
+        assert_eq!(inverse.len(), 3);
+        assert_eq!(inverse[0], Operation::list_remove("block", "page", &["content"], "new"));
+        assert_eq!(inverse[1], Operation::update("block", "new", &[], json!({ "alive": false })));
+        assert_eq!(inverse[2], Operation::update("block", "missing", &[], json!({ "alive": false })));
+    }
+
+    #[test]
+    fn test_journal_append_and_find() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("journal.jsonl");
+        let journal = Journal::new(&path);
+        let entry = JournalEntry::new(&prior(), vec![Operation::list_remove("block", "page", &["content"], "a")]).unwrap();
+        journal.append(&entry).unwrap();
+
+        let found = journal.find(&entry.id).unwrap().unwrap();
+        assert_eq!(found.inverse, entry.inverse);
+        assert_eq!(found.prior.len(), 1);
+        assert_eq!(journal.entries().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,12 @@
+//! Local editing support
+//!
+//! Helpers for keeping an in-memory `RecordMap` in step with the operations we
+//! send through `submitTransaction`.
+
+mod apply;
+mod journal;
+mod session;
+
+pub use apply::{apply_operation, apply_operations, preview};
+pub use journal::{invert, rollback, Journal, JournalEntry};
+pub use session::{find_conflicts, Conflict, ConflictError, EditSession};
@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::Result;
+use tracing::{debug, warn};
+
+use super::apply::apply_operation;
+use super::journal::Journal;
+use crate::api::endpoints::{Operation, RecordPointer};
+use crate::api::NotionClient;
+use crate::models::{Record, RecordMap};
+
+/// A record whose server version no longer matches the one we edited against
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub pointer: RecordPointer,
+    /// Version seen when the record was first touched, `None` if it did not exist
+    pub expected: Option<u64>,
+    /// Version currently on the server, `None` if it does not exist
+    pub actual: Option<u64>,
+}
+
+/// Returned (inside `anyhow::Error`) when a commit detects concurrent edits
+#[derive(Debug, Clone)]
+pub struct ConflictError {
+    pub conflicts: Vec<Conflict>,
+}
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} record(s) changed on the server:", self.conflicts.len())?;
+        for c in &self.conflicts {
+            write!(f, " {} (expected {:?}, found {:?})", c.pointer, c.expected, c.actual)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
+/// Batch of edits that is only submitted if nobody else touched the same records
+///
+/// Every record an operation touches has its version recorded the first time it
+/// is seen. `commit` re-reads those versions with `getRecordValues` and refuses
+/// to send the transaction on a mismatch. There is still a small window between
+/// the check and `submitTransaction`; this catches stale edits, not every race.
+pub struct EditSession {
+    client: NotionClient,
+    records: RecordMap,
+    expected: BTreeMap<RecordPointer, Option<u64>>,
+    operations: Vec<Operation>,
+}
+
+impl EditSession {
+    pub fn new(client: NotionClient) -> Self {
+        Self {
+            client,
+            records: RecordMap::default(),
+            expected: BTreeMap::new(),
+            operations: Vec::new(),
+        }
+    }
+
+    /// Record every committed transaction and its inverse in `journal`
+    ///
+    /// Same as giving the client a journal; the versions fetched at commit
+    /// double as the journal's prior state.
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.client = self.client.with_journal(journal);
+        self
+    }
+
+    /// Start from records already loaded, e.g. a `loadPageChunk` record map
+    pub fn with_records(mut self, records: RecordMap) -> Self {
+        self.records = records;
+        self
+    }
+
+    /// Local view of the records with all queued operations applied
+    pub fn records(&self) -> &RecordMap {
+        &self.records
+    }
+
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Versions recorded for every touched record
+    pub fn expected_versions(&self) -> &BTreeMap<RecordPointer, Option<u64>> {
+        &self.expected
+    }
+
+    /// Queue an operation and apply it to the local records
+    ///
+    /// A record missing from the local map is fetched with `getRecordValues`
+    /// the first time it is touched, so its version can be checked at commit.
+    pub async fn push(&mut self, op: Operation) -> Result<()> {
+        let pointer = op.pointer();
+        if !self.expected.contains_key(&pointer) {
+            let local = self.records.table(&pointer.table).and_then(|t| t.get(&pointer.id));
+            let version = match local {
+                Some(record) => record.version(),
+                None => self.fetch(&pointer).await?,
+            };
+            self.expected.insert(pointer, version);
+        }
+
+        apply_operation(&mut self.records, &op)?;
+        self.operations.push(op);
+        Ok(())
+    }
+
+    /// Load a record from the server into the local map, returning its version
+    async fn fetch(&mut self, pointer: &RecordPointer) -> Result<Option<u64>> {
+        let record = self
+            .client
+            .get_record_values(std::slice::from_ref(pointer))
+            .await?
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        let version = record.version();
+        if !record.value.is_null() {
+            self.records.table_mut(&pointer.table).insert(pointer.id.clone(), record);
+        }
+        Ok(version)
+    }
+
+    /// Revalidate versions and submit, failing with `ConflictError` on a mismatch
+    pub async fn commit(self) -> Result<RecordMap> {
+        self.commit_with(|conflict, _, _| Err(conflict.into())).await
+    }
+
+    /// Revalidate versions and submit, letting `merge` resolve conflicts
+    ///
+    /// `merge` receives the conflicts, the current server records of every
+    /// touched pointer and the queued operations. It returns the operations to
+    /// submit instead, or an error to abort. The records returned then hold
+    /// the server records with the merged operations applied.
+    pub async fn commit_with<F>(self, merge: F) -> Result<RecordMap>
+    where
+        F: FnOnce(ConflictError, &RecordMap, Vec<Operation>) -> Result<Vec<Operation>>,
+    {
+        if self.operations.is_empty() {
+            return Ok(self.records);
+        }
+
+        let pointers: Vec<RecordPointer> = self.expected.keys().cloned().collect();
+        let current = self.client.get_record_values(&pointers).await?;
+        let conflicts = find_conflicts(&self.expected, &pointers, &current);
+
+        let mut latest = RecordMap::default();
+        for (pointer, record) in pointers.iter().zip(current) {
+            latest.table_mut(&pointer.table).insert(pointer.id.clone(), record);
+        }
+
+        if conflicts.is_empty() {
+            debug!("Submitting {} operation(s)", self.operations.len());
+            self.client.submit_with_prior(self.operations, &latest).await?;
+            return Ok(self.records);
+        }
+
+        warn!("Detected {} conflicting record(s)", conflicts.len());
+        let operations = merge(ConflictError { conflicts }, &latest, self.operations)?;
+        let mut records = self.records;
+        for pointer in &pointers {
+            let table = records.table_mut(&pointer.table);
+            match latest.table(&pointer.table).and_then(|t| t.get(&pointer.id)) {
+                Some(record) if !record.value.is_null() => table.insert(pointer.id.clone(), record.clone()),
+                _ => table.remove(&pointer.id),
+            };
+        }
+        for op in &operations {
+            apply_operation(&mut records, op)?;
+        }
+
+        debug!("Submitting {} merged operation(s)", operations.len());
+        self.client.submit_with_prior(operations, &latest).await?;
+        Ok(records)
+    }
+}
+
+/// Compare recorded versions with the server results for the same pointers
+pub fn find_conflicts(
+    expected: &BTreeMap<RecordPointer, Option<u64>>,
+    pointers: &[RecordPointer],
+    current: &[Record],
+) -> Vec<Conflict> {
+    pointers
+        .iter()
+        .enumerate()
+        .filter_map(|(i, pointer)| {
+            let expected = expected.get(pointer).copied().flatten();
+            let actual = current.get(i).and_then(Record::version);
+            (expected != actual).then(|| Conflict {
+                pointer: pointer.clone(),
+                expected,
+                actual,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Emulator, EmulatorHandle};
+    use serde_json::json;
+
+    const A: &str = "aaaaaaaa-aaaa-aaaa-aaaa-aaaaaaaaaaaa";
+    const B: &str = "bbbbbbbb-bbbb-bbbb-bbbb-bbbbbbbbbbbb";
+    const C: &str = "cccccccc-cccc-cccc-cccc-cccccccccccc";
+
+    async fn emulator() -> EmulatorHandle {
+        let mut records = RecordMap::default();
+        records.block.insert(A.to_string(), Record::new(json!({ "id": A, "version": 7 })));
+        records.block.insert(C.to_string(), Record::new(json!({ "id": C, "version": 3, "alive": true })));
+        Emulator::new("t").with_records(records).start().await.unwrap()
+    }
+
+    fn session(client: NotionClient) -> EditSession {
+        let mut records = RecordMap::default();
+        records.block.insert(A.to_string(), Record::new(json!({ "id": A, "version": 7 })));
+        EditSession::new(client).with_records(records)
+    }
+
+    #[tokio::test]
+    async fn test_push_records_first_seen_version() {
+        let emulator = emulator().await;
+        let mut session = session(emulator.client());
+        session.push(Operation::update("block", A, &[], json!({ "version": 99 }))).await.unwrap();
+        session.push(Operation::update("block", A, &[], json!({ "alive": true }))).await.unwrap();
+        session.push(Operation::set("block", B, &[], json!({ "id": B }))).await.unwrap();
+        session.push(Operation::update("block", C, &[], json!({ "type": "text" }))).await.unwrap();
+
+        let expected = session.expected_versions();
+        assert_eq!(expected[&RecordPointer::block(A)], Some(7));
+        assert_eq!(expected[&RecordPointer::block(B)], None);
+        assert_eq!(expected[&RecordPointer::block(C)], Some(3));
+        assert_eq!(session.records().get("block", A).unwrap()["alive"], json!(true));
+        assert_eq!(session.records().get("block", C).unwrap()["alive"], json!(true));
+    }
+
+    #[tokio::test]
+    async fn test_commit_returns_merged_records() {
+        let emulator = emulator().await;
+        let mut fresh = session(emulator.client());
+        fresh.push(Operation::update("block", C, &[], json!({ "type": "text" }))).await.unwrap();
+        fresh.commit().await.unwrap();
+        assert_eq!(emulator.records().get("block", C).unwrap()["type"], json!("text"));
+
+        let mut stale = session(emulator.client());
+        stale.push(Operation::update("block", C, &[], json!({ "type": "todo" }))).await.unwrap();
+        emulator
+            .client()
+            .submit_transaction(vec![Operation::update("block", C, &[], json!({ "alive": false }))])
+            .await
+            .unwrap();
+
+        let records = stale
+            .commit_with(|err, latest, _| {
+                assert_eq!(err.conflicts.len(), 1);
+                assert_eq!(latest.get("block", C).unwrap()["alive"], json!(false));
+                Ok(vec![Operation::update("block", C, &[], json!({ "type": "header" }))])
+            })
+            .await
+            .unwrap();
+        let c = records.get("block", C).unwrap();
+        assert_eq!(c["type"], json!("header"));
+        assert_eq!(c["alive"], json!(false));
+        assert_eq!(emulator.records().get("block", C).unwrap()["type"], json!("header"));
+    }
+
+    #[test]
+    fn test_find_conflicts() {
+        let mut expected = BTreeMap::new();
+        expected.insert(RecordPointer::block("a"), Some(7));
+        expected.insert(RecordPointer::block("b"), None);
+        expected.insert(RecordPointer::block("c"), Some(1));
+        let pointers: Vec<_> = expected.keys().cloned().collect();
+        let current = vec![
+            Record::new(json!({ "version": 7 })),
+            Record::new(json!({ "version": 1 })),
+            Record::default(),
+        ];
+
+        let conflicts = find_conflicts(&expected, &pointers, &current);
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].pointer, RecordPointer::block("b"));
+        assert_eq!(conflicts[0].actual, Some(1));
+        assert_eq!(conflicts[1].pointer, RecordPointer::block("c"));
+        assert_eq!(conflicts[1].actual, None);
+    }
+
+    #[test]
+    fn test_conflict_error_downcasts() {
+        let err: anyhow::Error = ConflictError {
+            conflicts: vec![Conflict {
+                pointer: RecordPointer::block("a"),
+                expected: Some(1),
+                actual: Some(2),
+            }],
+        }
+        .into();
+        assert!(err.to_string().contains("block/a"));
+        assert!(err.downcast_ref::<ConflictError>().is_some());
+    }
+}
@@ -0,0 +1,640 @@
+//! In-process stand-in for the Notion API
+//!
+//! `Emulator` serves `/api/v3/*` from an in-memory `RecordMap`. Transactions
+//! are applied with the same code `edit` uses locally, and the read endpoints
+//! (`loadPageChunk`, `getRecordValues`, `syncRecordValues`, `search` and
+//! `queryCollection`) answer from the result, so a bot under test sees its own
+//! writes. Other endpoints in `paths::ALL` are answered from seeded fixtures.
+//! Every request must carry the expected `token_v2` cookie; errors and latency
+//! can be injected to exercise retries.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::body::Bytes;
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+use crate::api::endpoints::{paths, Operation, RecordPointer, SyncRecordRequest, Transaction};
+use crate::api::{normalize_request, NotionClient};
+use crate::edit::apply_operations;
+use crate::fixture::{self, body_key, endpoint_name, Fixture};
+use crate::models::{Record, RecordMap};
+use crate::search::SearchIndex;
+use crate::utils::to_uuid_format;
+
+mod query;
+
+/// Block types whose content is loaded with their own `loadPageChunk`
+const PAGE_TYPES: [&str; 2] = ["page", "collection_view_page"];
+
+/// An error the emulator returns instead of handling a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fault {
+    status: u16,
+    endpoint: Option<String>,
+    remaining: Option<u32>,
+}
+
+impl Fault {
+    /// Fail every request with `status`
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            endpoint: None,
+            remaining: None,
+        }
+    }
+
+    /// Only fail requests to this endpoint (`search` or `paths::SEARCH`)
+    pub fn on(mut self, endpoint: &str) -> Self {
+        self.endpoint = Some(endpoint_name(endpoint).unwrap_or(endpoint).to_string());
+        self
+    }
+
+    /// Only fail the next `count` matching requests; `times(0)` never fires
+    pub fn times(mut self, count: u32) -> Self {
+        self.remaining = Some(count);
+        self
+    }
+}
+
+#[derive(Default)]
+struct EmulatorState {
+    records: RecordMap,
+    /// Seeded responses keyed by endpoint and normalized body key
+    fixtures: HashMap<(String, String), Fixture>,
+    index: SearchIndex,
+    faults: Vec<Fault>,
+    latency: Duration,
+}
+
+struct Shared {
+    token: String,
+    state: Mutex<EmulatorState>,
+}
+
+/// Builder for an emulated API; `start` binds it to a local port
+pub struct Emulator {
+    token: String,
+    state: EmulatorState,
+}
+
+impl Emulator {
+    /// Accept only requests whose `token_v2` cookie is `token`
+    pub fn new(token: &str) -> Self {
+        Self {
+            token: token.to_string(),
+            state: EmulatorState::default(),
+        }
+    }
+
+    /// Add records to the store, replacing older versions
+    pub fn with_records(mut self, records: RecordMap) -> Self {
+        self.state.seed(records);
+        self
+    }
+
+    /// Seed from a fixture directory
+    ///
+    /// Record maps and `getRecordValues` results in successful responses go
+    /// into the store; every fixture is also kept to answer endpoints the
+    /// emulator does not model.
+    pub fn with_fixtures<P: AsRef<Path>>(mut self, dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+        let fixtures = fixture::load_dir(dir)?;
+        debug!("Seeding emulator with {} fixture(s) from {}", fixtures.len(), dir.display());
+        for fixture in fixtures {
+            self.state.seed_fixture(fixture);
+        }
+        Ok(self)
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.state.latency = latency;
+        self
+    }
+
+    pub fn with_fault(mut self, fault: Fault) -> Self {
+        self.state.faults.push(fault);
+        self
+    }
+
+    /// Serve on an ephemeral port on 127.0.0.1
+    pub async fn start(self) -> Result<EmulatorHandle> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .context("Failed to bind emulator")?;
+        let base_url = format!("http://{}/api", listener.local_addr()?);
+        let shared = Arc::new(Shared {
+            token: self.token,
+            state: Mutex::new(self.state),
+        });
+
+        let app = Router::new()
+            .route("/api/v3/{endpoint}", post(handle))
+            .with_state(shared.clone());
+        let server = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!("Emulator stopped: {}", e);
+            }
+        });
+        debug!("Emulator listening on {}", base_url);
+
+        Ok(EmulatorHandle {
+            base_url,
+            shared,
+            server,
+        })
+    }
+}
+
+/// A running emulator; the server stops when the handle is dropped
+pub struct EmulatorHandle {
+    base_url: String,
+    shared: Arc<Shared>,
+    server: JoinHandle<()>,
+}
+
+impl EmulatorHandle {
+    /// Base URL to pass to `NotionClient::with_base_url`
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// A client already pointed at the emulator with the accepted token
+    pub fn client(&self) -> NotionClient {
+        NotionClient::from_token(self.shared.token.clone()).with_base_url(&self.base_url)
+    }
+
+    /// Snapshot of the current store
+    pub fn records(&self) -> RecordMap {
+        self.state().records.clone()
+    }
+
+    pub fn inject(&self, fault: Fault) {
+        self.state().faults.push(fault);
+    }
+
+    pub fn clear_faults(&self) {
+        self.state().faults.clear();
+    }
+
+    pub fn set_latency(&self, latency: Duration) {
+        self.state().latency = latency;
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, EmulatorState> {
+        self.shared.state.lock().expect("emulator state poisoned")
+    }
+}
+
+impl Drop for EmulatorHandle {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn handle(
+    State(shared): State<Arc<Shared>>,
+    UrlPath(endpoint): UrlPath<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let latency = shared.state.lock().expect("emulator state poisoned").latency;
+    if !latency.is_zero() {
+        tokio::time::sleep(latency).await;
+    }
+
+    if cookie(&headers, "token_v2") != Some(shared.token.as_str()) {
+        return error(StatusCode::UNAUTHORIZED, "Token was invalid or expired.");
+    }
+    let body: Value = if body.is_empty() {
+        json!({})
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(body) => body,
+            Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid JSON body: {}", e)),
+        }
+    };
+
+    let mut state = shared.state.lock().expect("emulator state poisoned");
+    if let Some(status) = state.take_fault(&endpoint) {
+        let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        return error(status, "Injected fault");
+    }
+
+    debug!("Emulating {}", endpoint);
+    let result = match endpoint.as_str() {
+        "submitTransaction" => state.submit_transaction(&body),
+        "loadPageChunk" => Ok(state.load_page_chunk(&body)),
+        "getRecordValues" => state.get_record_values(&body),
+        "syncRecordValues" => state.sync_record_values(&body),
+        "search" => Ok(state.search(&body)),
+        "queryCollection" => query::query_collection(&state.records, &body),
+        other => return state.replay(other, &body),
+    };
+    match result {
+        Ok(value) => Json(value).into_response(),
+        Err(e) => error(StatusCode::BAD_REQUEST, &format!("{:#}", e)),
+    }
+}
+
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Error body shaped like Notion's
+fn error(status: StatusCode, message: &str) -> Response {
+    let name = match status {
+        StatusCode::BAD_REQUEST => "ValidationError",
+        StatusCode::UNAUTHORIZED => "UnauthorizedError",
+        StatusCode::NOT_FOUND => "NotFound",
+        StatusCode::TOO_MANY_REQUESTS => "RateLimitedError",
+        StatusCode::NOT_IMPLEMENTED => "NotImplemented",
+        _ => "InternalServerError",
+    };
+    let body = Json(json!({
+        "errorId": uuid::Uuid::new_v4().to_string(),
+        "name": name,
+        "message": message,
+    }));
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        (status, [(header::RETRY_AFTER, "1")], body).into_response()
+    } else {
+        (status, body).into_response()
+    }
+}
+
+impl EmulatorState {
+    fn seed(&mut self, records: RecordMap) {
+        for (table, entries) in records.tables() {
+            for (id, record) in entries {
+                self.seed_record(table, id, record.clone());
+            }
+        }
+    }
+
+    /// Insert a record unless the store already has a newer version
+    fn seed_record(&mut self, table: &str, id: &str, record: Record) {
+        if record.value.is_null() {
+            return;
+        }
+        let table = self.records.table_mut(table);
+        let newer = table
+            .get(id)
+            .is_none_or(|existing| existing.version() <= record.version());
+        if newer {
+            table.insert(id.to_string(), record);
+        }
+    }
+
+    fn seed_fixture(&mut self, fixture: Fixture) {
+        if (200..300).contains(&fixture.status) {
+            if let Some(records) = fixture.response.get("recordMap") {
+                if let Ok(records) = serde_json::from_value::<RecordMap>(records.clone()) {
+                    self.seed(records);
+                }
+            }
+            if fixture.endpoint == "getRecordValues" {
+                let pointers = fixture.request.get("requests").cloned().unwrap_or_default();
+                let results = fixture.response.get("results").cloned().unwrap_or_default();
+                let pointers: Vec<RecordPointer> = serde_json::from_value(pointers).unwrap_or_default();
+                let results: Vec<Record> = serde_json::from_value(results).unwrap_or_default();
+                for (pointer, record) in pointers.into_iter().zip(results) {
+                    self.seed_record(&pointer.table, &pointer.id, record);
+                }
+            }
+        }
+
+        let key = body_key(&normalize_request(&fixture.request));
+        self.fixtures.insert((fixture.endpoint.clone(), key), fixture);
+    }
+
+    fn take_fault(&mut self, endpoint: &str) -> Option<u16> {
+        let index = self
+            .faults
+            .iter()
+            .position(|f| f.remaining != Some(0) && f.endpoint.as_deref().is_none_or(|e| e == endpoint))?;
+        let fault = &mut self.faults[index];
+        let status = fault.status;
+        if let Some(remaining) = &mut fault.remaining {
+            *remaining = remaining.saturating_sub(1);
+            if *remaining == 0 {
+                self.faults.remove(index);
+            }
+        }
+        Some(status)
+    }
+
+    fn find(&self, table: &str, id: &str) -> Option<(String, &Record)> {
+        let records = self.records.table(table)?;
+        if let Some(record) = records.get(id) {
+            return Some((id.to_string(), record));
+        }
+        let id = to_uuid_format(id)?;
+        records.get(&id).map(|record| (id, record))
+    }
+
+    /// Apply every operation of the request atomically and bump versions
+    fn submit_transaction(&mut self, body: &Value) -> Result<Value> {
+        let operations: Vec<Operation> = match body.get("transactions") {
+            Some(transactions) => serde_json::from_value::<Vec<Transaction>>(transactions.clone())
+                .context("Invalid transactions")?
+                .into_iter()
+                .flat_map(|t| t.operations)
+                .collect(),
+            // Older clients send the operations directly
+            None => serde_json::from_value(body.get("operations").cloned().unwrap_or_default())
+                .context("Invalid operations")?,
+        };
+
+        let touched: HashSet<(String, String)> = operations.iter().map(|op| (op.table.clone(), op.id.clone())).collect();
+        let prior: HashMap<&(String, String), u64> = touched
+            .iter()
+            .map(|key| {
+                let version = self.records.table(&key.0).and_then(|t| t.get(&key.1)).and_then(Record::version);
+                (key, version.unwrap_or(0))
+            })
+            .collect();
+
+        apply_operations(&mut self.records, &operations)?;
+        for ((table, id), version) in prior {
+            if let Some(record) = self.records.table_mut(table).get_mut(id) {
+                record.value["version"] = json!(version + 1);
+            }
+        }
+        debug!("Applied {} operation(s) to {} record(s)", operations.len(), touched.len());
+        Ok(json!({}))
+    }
+
+    /// The page, its content down to nested pages, and what they reference
+    fn load_page_chunk(&self, body: &Value) -> Value {
+        let mut out = RecordMap::default();
+        let page_id = body.get("pageId").and_then(Value::as_str).unwrap_or_default();
+        let mut pending = vec![(page_id.to_string(), true)];
+        let mut visited = HashSet::new();
+
+        while let Some((id, expand)) = pending.pop() {
+            let Some((id, block)) = self.find("block", &id) else {
+                continue;
+            };
+            if !visited.insert(id.clone()) {
+                continue;
+            }
+            let value = &block.value;
+            out.block.insert(id.clone(), block.clone());
+
+            let str_field = |key: &str| value.get(key).and_then(Value::as_str);
+            if let Some((space_id, space)) = str_field("space_id").and_then(|s| self.find("space", s)) {
+                out.space.insert(space_id, space.clone());
+            }
+            if let Some((coll_id, collection)) = str_field("collection_id").and_then(|c| self.find("collection", c)) {
+                out.collection.insert(coll_id, collection.clone());
+            }
+            for view_id in value.get("view_ids").and_then(Value::as_array).into_iter().flatten() {
+                if let Some((view_id, view)) = view_id.as_str().and_then(|v| self.find("collection_view", v)) {
+                    out.collection_view.insert(view_id, view.clone());
+                }
+            }
+
+            let is_page = str_field("type").is_some_and(|t| PAGE_TYPES.contains(&t));
+            if expand || !is_page {
+                let children = value.get("content").and_then(Value::as_array).into_iter().flatten();
+                // Reverse so the stack visits children in document order
+                let children: Vec<_> = children.filter_map(Value::as_str).collect();
+                pending.extend(children.into_iter().rev().map(|c| (c.to_string(), false)));
+            }
+        }
+
+        json!({ "recordMap": out, "cursor": { "stack": [] } })
+    }
+
+    fn get_record_values(&self, body: &Value) -> Result<Value> {
+        let pointers: Vec<RecordPointer> =
+            serde_json::from_value(body.get("requests").cloned().unwrap_or_default()).context("Invalid requests")?;
+        let results: Vec<Record> = pointers
+            .iter()
+            .map(|p| match self.find(&p.table, &p.id) {
+                Some((_, record)) => Record {
+                    role: Some("editor".to_string()),
+                    value: record.value.clone(),
+                },
+                None => Record {
+                    role: Some("none".to_string()),
+                    value: Value::Null,
+                },
+            })
+            .collect();
+        Ok(json!({ "results": results }))
+    }
+
+    /// Records newer than the version each request already has
+    fn sync_record_values(&self, body: &Value) -> Result<Value> {
+        let requests: Vec<SyncRecordRequest> =
+            serde_json::from_value(body.get("requests").cloned().unwrap_or_default()).context("Invalid requests")?;
+        let mut out = RecordMap::default();
+        for request in requests {
+            let Some((id, record)) = self.find(&request.pointer.table, &request.pointer.id) else {
+                continue;
+            };
+            if record.version().unwrap_or(0) as i64 > request.version {
+                let record = Record {
+                    role: Some("editor".to_string()),
+                    value: record.value.clone(),
+                };
+                out.table_mut(&request.pointer.table).insert(id, record);
+            }
+        }
+        Ok(json!({ "recordMap": out }))
+    }
+
+    /// Ids of a block's ancestors, nearest first
+    fn ancestors(&self, id: &str) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut visited = HashSet::from([id.to_string()]);
+        let mut current = self.records.get("block", id).and_then(|b| b.get("parent_id")).and_then(Value::as_str);
+        while let Some(parent) = current {
+            if !visited.insert(parent.to_string()) {
+                break;
+            }
+            chain.push(parent.to_string());
+            current = self
+                .records
+                .get("block", parent)
+                .or_else(|| self.records.get("collection", parent))
+                .and_then(|b| b.get("parent_id"))
+                .and_then(Value::as_str);
+        }
+        chain
+    }
+
+    fn search(&mut self, body: &Value) -> Value {
+        let query = body.get("query").and_then(Value::as_str).unwrap_or_default();
+        let limit = body.get("limit").and_then(Value::as_u64).unwrap_or(20) as usize;
+        let space_id = body.get("spaceId").and_then(Value::as_str);
+        let ancestor_id = body.get("ancestorId").and_then(Value::as_str);
+
+        self.index.update(&self.records);
+        let hits: Vec<_> = self
+            .index
+            .search(query, usize::MAX)
+            .into_iter()
+            .filter(|hit| {
+                let block = self.records.get("block", &hit.result.id);
+                let in_space = space_id.is_none_or(|s| {
+                    block.and_then(|b| b.get("space_id")).and_then(Value::as_str) == Some(s)
+                });
+                let in_ancestor = ancestor_id.is_none_or(|a| self.ancestors(&hit.result.id).iter().any(|p| p == a));
+                in_space && in_ancestor
+            })
+            .collect();
+
+        let mut records = RecordMap::default();
+        let mut results = Vec::new();
+        for hit in hits.iter().take(limit) {
+            let id = &hit.result.id;
+            for block_id in std::iter::once(id.clone()).chain(self.ancestors(id)) {
+                if let Some(block) = self.records.block.get(&block_id) {
+                    records.block.insert(block_id, block.clone());
+                }
+            }
+            let block = self.records.get("block", id);
+            results.push(json!({
+                "id": id,
+                "isNavigable": block.and_then(|b| b.get("type")).and_then(Value::as_str).is_some_and(|t| PAGE_TYPES.contains(&t)),
+                "score": hit.score,
+                "spaceId": block.and_then(|b| b.get("space_id")),
+                "highlight": { "text": hit.result.highlight },
+            }));
+        }
+
+        json!({ "results": results, "total": hits.len(), "recordMap": records })
+    }
+
+    /// Answer an endpoint without a model from seeded fixtures
+    fn replay(&self, endpoint: &str, body: &Value) -> Response {
+        let key = (endpoint.to_string(), body_key(&normalize_request(body)));
+        if let Some(fixture) = self.fixtures.get(&key) {
+            let status = StatusCode::from_u16(fixture.status).unwrap_or(StatusCode::OK);
+            return (status, Json(fixture.response.clone())).into_response();
+        }
+
+        let known = paths::ALL.iter().any(|(_, path)| endpoint_name(path) == Some(endpoint));
+        if known {
+            error(StatusCode::NOT_IMPLEMENTED, &format!("No fixture for {} with this body", endpoint))
+        } else {
+            error(StatusCode::NOT_FOUND, &format!("Unknown endpoint {}", endpoint))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::endpoints::SearchRequest;
+    use crate::models::{LoadPageChunkResponse, SearchResponse};
+
+    fn workspace() -> RecordMap {
+        let mut records = RecordMap::default();
+        let block = |id: &str, ty: &str, parent: &str, title: &str, content: &[&str]| {
+            Record::new(json!({
+                "id": id, "type": ty, "version": 1, "alive": true, "space_id": "space",
+                "parent_id": parent, "parent_table": "block",
+                "properties": { "title": [[title]] }, "content": content,
+            }))
+        };
+        records.block.insert("root".into(), block("root", "page", "space", "Handbook", &["intro", "child"]));
+        records.block.insert("intro".into(), block("intro", "text", "root", "Welcome aboard", &[]));
+        records.block.insert("child".into(), block("child", "page", "root", "Onboarding", &["deep"]));
+        records.block.insert("deep".into(), block("deep", "text", "child", "Laptop setup", &[]));
+        records.space.insert("space".into(), Record::new(json!({ "id": "space", "name": "Acme" })));
+        records
+    }
+
+    #[tokio::test]
+    async fn test_writes_are_visible_to_reads() {
+        let emulator = Emulator::new("secret").with_records(workspace()).start().await.unwrap();
+        let client = emulator.client();
+
+        client
+            .submit_transaction(vec![Operation::set("block", "intro", &["properties", "title"], json!([["Hello"]]))])
+            .await
+            .unwrap();
+
+        let records = client.get_record_values(&[RecordPointer::block("intro")]).await.unwrap();
+        assert_eq!(records[0].value["properties"]["title"], json!([["Hello"]]));
+        assert_eq!(records[0].version(), Some(2));
+
+        let chunk: LoadPageChunkResponse = client
+            .post_json(paths::LOAD_PAGE_CHUNK, &json!({ "pageId": "root", "limit": 100 }))
+            .await
+            .unwrap();
+        let ids: HashSet<&str> = chunk.record_map.block.keys().map(String::as_str).collect();
+        assert_eq!(ids, HashSet::from(["root", "intro", "child"]));
+        assert!(chunk.record_map.space.contains_key("space"));
+
+        let found: SearchResponse = client
+            .post_json(paths::SEARCH, &SearchRequest::in_ancestor("root", "laptop"))
+            .await
+            .unwrap();
+        assert_eq!(found.results[0].id, "deep");
+        assert_eq!(found.total, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_token_is_enforced() {
+        let emulator = Emulator::new("secret").start().await.unwrap();
+        let client = NotionClient::from_token("wrong".to_string()).with_base_url(emulator.base_url());
+        let err = client.post(paths::GET_SPACES, &json!({})).await.unwrap_err();
+        assert!(err.to_string().contains("401"));
+    }
+
+    #[tokio::test]
+    async fn test_faults_and_fixture_fallback() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        Fixture {
+            endpoint: "getSpaces".to_string(),
+            status: 200,
+            request: json!({}),
+            response: json!({ "user": { "space": {} } }),
+        }
+        .save(dir)
+        .unwrap();
+
+        let emulator = Emulator::new("secret")
+            .with_fixtures(dir)
+            .unwrap()
+            .with_fault(Fault::status(503).times(0))
+            .with_fault(Fault::status(429).on(paths::GET_SPACES).times(1))
+            .start()
+            .await
+            .unwrap();
+        let client = emulator.client();
+
+        let err = client.post(paths::GET_SPACES, &json!({})).await.unwrap_err();
+        assert!(err.to_string().contains("429"));
+        let spaces = client.post(paths::GET_SPACES, &json!({})).await.unwrap();
+        assert!(spaces.contains("space"));
+
+        let err = client.post(paths::EXPORT_PAGE, &json!({})).await.unwrap_err();
+        assert!(err.to_string().contains("501"));
+    }
+}
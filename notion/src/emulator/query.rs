@@ -0,0 +1,351 @@
+//! `queryCollection` evaluated over the emulator's store
+//!
+//! Understands the reducer loader `QueryBuilder` sends: filter groups, sorts,
+//! a search query, `results` reducers and aggregations.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use anyhow::{bail, Context, Result};
+use chrono::DateTime;
+use serde_json::{json, Map, Value};
+
+use crate::collection::{Collection, PropertyValue};
+use crate::models::{plain_text, RecordMap};
+
+pub(super) fn query_collection(records: &RecordMap, body: &Value) -> Result<Value> {
+    let collection_id = body
+        .pointer("/collection/id")
+        .or_else(|| body.get("collectionId"))
+        .and_then(Value::as_str)
+        .context("Missing collection id")?;
+    let collection = Collection::from_record_map(records, collection_id)?;
+    let loader = body.get("loader").cloned().unwrap_or_default();
+
+    let mut rows: Vec<(&String, &Value)> = records
+        .block
+        .iter()
+        .map(|(id, r)| (id, &r.value))
+        .filter(|(_, row)| {
+            row.get("parent_id").and_then(Value::as_str) == Some(collection_id)
+                && row.get("parent_table").and_then(Value::as_str) == Some("collection")
+                && row.get("alive").and_then(Value::as_bool).unwrap_or(true)
+        })
+        .collect();
+    rows.sort_by(|a, b| {
+        let created = |row: &Value| row.get("created_time").and_then(Value::as_i64).unwrap_or(0);
+        created(a.1).cmp(&created(b.1)).then_with(|| a.0.cmp(b.0))
+    });
+
+    let search = loader.get("searchQuery").and_then(Value::as_str).unwrap_or_default().to_lowercase();
+    if !search.is_empty() {
+        rows.retain(|(_, row)| {
+            let title = row.pointer("/properties/title").map(plain_text).unwrap_or_default();
+            title.to_lowercase().contains(&search)
+        });
+    }
+    if let Some(filter) = loader.get("filter") {
+        let mut kept = Vec::new();
+        for (id, row) in rows {
+            if matches(&collection, row, filter)? {
+                kept.push((id, row));
+            }
+        }
+        rows = kept;
+    }
+    for sort in loader.get("sort").and_then(Value::as_array).into_iter().flatten().rev() {
+        let prop_id = sort.get("property").and_then(Value::as_str).context("Sort without property")?;
+        let descending = sort.get("direction").and_then(Value::as_str) == Some("descending");
+        // Stable sorts applied last-key-first give a multi-key ordering
+        rows.sort_by(|a, b| {
+            let ordering = compare(&sort_key(&collection, prop_id, a.1), &sort_key(&collection, prop_id, b.1));
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+
+    let mut record_map = RecordMap::default();
+    record_map.collection.insert(collection_id.to_string(), records.collection[collection_id].clone());
+    let mut results = Map::new();
+    let reducers = loader.get("reducers").and_then(Value::as_object).cloned().unwrap_or_default();
+    for (name, reducer) in reducers {
+        let result = match reducer.get("type").and_then(Value::as_str) {
+            Some("results") => {
+                let limit = reducer.get("limit").and_then(Value::as_u64).unwrap_or(50) as usize;
+                let ids: Vec<&String> = rows.iter().take(limit).map(|(id, _)| *id).collect();
+                for id in &ids {
+                    record_map.block.insert(id.to_string(), records.block[*id].clone());
+                }
+                json!({ "type": "results", "blockIds": ids, "hasMore": rows.len() > limit })
+            }
+            Some("aggregation") => {
+                let prop_id = reducer
+                    .pointer("/aggregation/property")
+                    .and_then(Value::as_str)
+                    .context("Aggregation without property")?;
+                let aggregator = reducer
+                    .pointer("/aggregation/aggregator")
+                    .and_then(Value::as_str)
+                    .context("Aggregation without aggregator")?;
+                let values: Vec<&Value> = rows.iter().map(|(_, row)| *row).collect();
+                let value = aggregate(&collection, prop_id, aggregator, &values)?;
+                json!({ "type": "aggregation", "aggregationResult": { "type": "number", "value": value } })
+            }
+            other => bail!("Unsupported reducer type {:?} for '{}'", other, name),
+        };
+        results.insert(name, result);
+    }
+
+    Ok(json!({
+        "result": { "type": "reducer", "reducerResults": results },
+        "recordMap": record_map,
+    }))
+}
+
+fn property_type<'a>(collection: &'a Collection, prop_id: &str) -> &'a str {
+    collection.schema.get(prop_id).map(|p| p.property_type.as_str()).unwrap_or("text")
+}
+
+fn decoded(collection: &Collection, prop_id: &str, row: &Value) -> Option<PropertyValue> {
+    let raw = row.get("properties")?.get(prop_id)?;
+    collection.decode(prop_id, raw)
+}
+
+fn text(row: &Value, prop_id: &str) -> String {
+    row.get("properties").and_then(|p| p.get(prop_id)).map(plain_text).unwrap_or_default()
+}
+
+/// `YYYY-MM-DD` of a date property, or of the row's own timestamps
+fn date(collection: &Collection, prop_id: &str, row: &Value) -> Option<String> {
+    match property_type(collection, prop_id) {
+        ty @ ("created_time" | "last_edited_time") => {
+            let millis = row.get(ty)?.as_i64()?;
+            Some(DateTime::from_timestamp_millis(millis)?.format("%Y-%m-%d").to_string())
+        }
+        _ => match decoded(collection, prop_id, row)? {
+            PropertyValue::Date { start, .. } => Some(start.chars().take(10).collect()),
+            _ => None,
+        },
+    }
+}
+
+fn number(collection: &Collection, prop_id: &str, row: &Value) -> Option<f64> {
+    match decoded(collection, prop_id, row)? {
+        PropertyValue::Number(n) => Some(n),
+        _ => None,
+    }
+}
+
+fn ids(collection: &Collection, prop_id: &str, row: &Value) -> Vec<String> {
+    match decoded(collection, prop_id, row) {
+        Some(PropertyValue::MultiSelect(items) | PropertyValue::Relation(items) | PropertyValue::Person(items)) => items,
+        _ => Vec::new(),
+    }
+}
+
+fn matches(collection: &Collection, row: &Value, filter: &Value) -> Result<bool> {
+    if let Some(children) = filter.get("filters").and_then(Value::as_array) {
+        let any = filter.get("operator").and_then(Value::as_str) == Some("or");
+        for child in children {
+            if matches(collection, row, child)? == any {
+                return Ok(any);
+            }
+        }
+        return Ok(!any);
+    }
+
+    let prop_id = filter.get("property").and_then(Value::as_str).context("Filter without property")?;
+    let operator = filter
+        .pointer("/filter/operator")
+        .and_then(Value::as_str)
+        .context("Filter without operator")?;
+    let expected = filter.pointer("/filter/value/value").cloned().unwrap_or_default();
+    let expected_str = expected.as_str().unwrap_or_default();
+    let needle = expected_str.to_lowercase();
+    let haystack = text(row, prop_id).to_lowercase();
+    let compare_number = |f: fn(f64, f64) -> bool| {
+        number(collection, prop_id, row).zip(expected.as_f64()).is_some_and(|(n, e)| f(n, e))
+    };
+    let compare_date = |f: fn(&str, &str) -> bool| {
+        let bound = expected.get("start_date").and_then(Value::as_str);
+        date(collection, prop_id, row).zip(bound).is_some_and(|(d, b)| f(&d, b))
+    };
+
+    let result = match operator {
+        "is_empty" => haystack.is_empty() && date(collection, prop_id, row).is_none(),
+        "is_not_empty" => !haystack.is_empty() || date(collection, prop_id, row).is_some(),
+        "string_is" => haystack == needle,
+        "string_is_not" => haystack != needle,
+        "string_contains" => haystack.contains(&needle),
+        "string_does_not_contain" => !haystack.contains(&needle),
+        "string_starts_with" => haystack.starts_with(&needle),
+        "string_ends_with" => haystack.ends_with(&needle),
+        "enum_is" => text(row, prop_id) == expected_str,
+        "enum_is_not" => text(row, prop_id) != expected_str,
+        "enum_contains" | "relation_contains" => ids(collection, prop_id, row).iter().any(|i| i == expected_str),
+        "enum_does_not_contain" | "relation_does_not_contain" => {
+            !ids(collection, prop_id, row).iter().any(|i| i == expected_str)
+        }
+        "number_equals" => compare_number(|n, e| n == e),
+        "number_greater_than" => compare_number(|n, e| n > e),
+        "number_less_than" => compare_number(|n, e| n < e),
+        "checkbox_is" => (text(row, prop_id) == "Yes") == expected.as_bool().unwrap_or(true),
+        "date_is" => compare_date(|d, b| d == b),
+        "date_is_before" => compare_date(|d, b| d < b),
+        "date_is_after" => compare_date(|d, b| d > b),
+        other => bail!("Unsupported filter operator {}", other),
+    };
+    Ok(result)
+}
+
+enum SortKey {
+    Empty,
+    Number(f64),
+    Text(String),
+}
+
+fn sort_key(collection: &Collection, prop_id: &str, row: &Value) -> SortKey {
+    match property_type(collection, prop_id) {
+        "number" => number(collection, prop_id, row).map_or(SortKey::Empty, SortKey::Number),
+        "date" | "created_time" | "last_edited_time" => date(collection, prop_id, row).map_or(SortKey::Empty, SortKey::Text),
+        _ => match text(row, prop_id) {
+            t if t.is_empty() => SortKey::Empty,
+            t => SortKey::Text(t.to_lowercase()),
+        },
+    }
+}
+
+/// Empty values sort last, as in the web app
+fn compare(a: &SortKey, b: &SortKey) -> Ordering {
+    match (a, b) {
+        (SortKey::Empty, SortKey::Empty) => Ordering::Equal,
+        (SortKey::Empty, _) => Ordering::Greater,
+        (_, SortKey::Empty) => Ordering::Less,
+        (SortKey::Number(x), SortKey::Number(y)) => x.total_cmp(y),
+        (SortKey::Text(x), SortKey::Text(y)) => x.cmp(y),
+        (SortKey::Number(_), SortKey::Text(_)) => Ordering::Less,
+        (SortKey::Text(_), SortKey::Number(_)) => Ordering::Greater,
+    }
+}
+
+fn aggregate(collection: &Collection, prop_id: &str, aggregator: &str, rows: &[&Value]) -> Result<Value> {
+    let texts: Vec<String> = rows.iter().map(|row| text(row, prop_id)).collect();
+    let filled = texts.iter().filter(|t| !t.is_empty()).count();
+    let percent = |count: usize| if rows.is_empty() { 0.0 } else { count as f64 * 100.0 / rows.len() as f64 };
+
+    let mut numbers: Vec<f64> = rows.iter().filter_map(|row| number(collection, prop_id, row)).collect();
+    numbers.sort_by(f64::total_cmp);
+    let min = numbers.first().copied();
+    let max = numbers.last().copied();
+
+    let value = match aggregator {
+        "count" => json!(rows.len()),
+        "count_values" => json!(filled),
+        "unique" => json!(texts.iter().filter(|t| !t.is_empty()).collect::<HashSet<_>>().len()),
+        "empty" => json!(rows.len() - filled),
+        "not_empty" => json!(filled),
+        "percent_empty" => json!(percent(rows.len() - filled)),
+        "percent_not_empty" => json!(percent(filled)),
+        "sum" => json!(numbers.iter().sum::<f64>()),
+        "average" if numbers.is_empty() => Value::Null,
+        "average" => json!(numbers.iter().sum::<f64>() / numbers.len() as f64),
+        "median" if numbers.is_empty() => Value::Null,
+        "median" => {
+            let mid = numbers.len() / 2;
+            let median = if numbers.len().is_multiple_of(2) {
+                (numbers[mid - 1] + numbers[mid]) / 2.0
+            } else {
+                numbers[mid]
+            };
+            json!(median)
+        }
+        "min" => json!(min),
+        "max" => json!(max),
+        "range" => json!(min.zip(max).map(|(lo, hi)| hi - lo)),
+        other => bail!("Unsupported aggregator {}", other),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collection::{Aggregator, Condition, Filter, QueryBuilder, Sort};
+    use crate::emulator::Emulator;
+    use crate::models::Record;
+
+    fn database() -> RecordMap {
+        let mut records = RecordMap::default();
+        records.collection.insert(
+            "coll".to_string(),
+            Record::new(json!({
+                "id": "coll",
+                "space_id": "space",
+                "schema": {
+                    "title": { "name": "Name", "type": "title" },
+                    "pts": { "name": "Points", "type": "number" },
+                    "stat": { "name": "Status", "type": "select", "options": [] },
+                }
+            })),
+        );
+        for (i, (name, points, status)) in [("Alpha", "5", "Done"), ("Beta", "2", "Todo"), ("Gamma", "8", "Done")]
+            .into_iter()
+            .enumerate()
+        {
+            records.block.insert(
+                name.to_lowercase(),
+                Record::new(json!({
+                    "id": name.to_lowercase(), "type": "page", "parent_id": "coll", "parent_table": "collection",
+                    "created_time": i,
+                    "properties": { "title": [[name]], "pts": [[points]], "stat": [[status]] },
+                })),
+            );
+        }
+        records
+    }
+
+    #[tokio::test]
+    async fn test_query_builder_against_emulator() {
+        let records = database();
+        let collection = Collection::from_record_map(&records, "coll").unwrap();
+        let emulator = Emulator::new("t").with_records(records).start().await.unwrap();
+
+        let result = QueryBuilder::new(&collection)
+            .filter(Filter::property("Status", Condition::Is("Done".into())))
+            .sort(Sort::descending("Points"))
+            .aggregate("total", "Points", Aggregator::Sum)
+            .page_size(1)
+            .run(&emulator.client())
+            .await
+            .unwrap();
+
+        let ids: Vec<&str> = result.rows.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["gamma", "alpha"]);
+        assert_eq!(result.aggregations["total"], json!(13.0));
+    }
+
+    #[test]
+    fn test_or_filter_and_median() {
+        let records = database();
+        let body = json!({
+            "collection": { "id": "coll" },
+            "loader": {
+                "filter": { "operator": "or", "filters": [
+                    { "property": "pts", "filter": { "operator": "number_less_than", "value": { "type": "exact", "value": 3 } } },
+                    { "property": "title", "filter": { "operator": "string_starts_with", "value": { "type": "exact", "value": "gam" } } },
+                ] },
+                "reducers": {
+                    "rows": { "type": "results", "limit": 10 },
+                    "mid": { "type": "aggregation", "aggregation": { "property": "pts", "aggregator": "median" } },
+                },
+            },
+        });
+        let response = query_collection(&records, &body).unwrap();
+        let results = &response["result"]["reducerResults"];
+        assert_eq!(results["rows"]["blockIds"], json!(["beta", "gamma"]));
+        assert_eq!(results["mid"]["aggregationResult"]["value"], json!(5.0));
+    }
+}
@@ -0,0 +1,122 @@
+//! Captured request/response pairs stored on disk
+//!
+//! Fixtures live in `<dir>/<endpoint>/<key>.json`, where the key is a hash of
+//! the request body with object keys sorted. Capturing the same request twice
+//! therefore overwrites one file instead of piling up duplicates.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Stands in for credentials scrubbed from fixtures and headers
+pub const REDACTED: &str = "[REDACTED]";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    /// Endpoint name without prefix, e.g. `loadPageChunk`
+    pub endpoint: String,
+    pub status: u16,
+    pub request: Value,
+    pub response: Value,
+}
+
+impl Fixture {
+    /// Stable key of the request body
+    pub fn key(&self) -> String {
+        body_key(&self.request)
+    }
+
+    pub fn path_in(&self, dir: &Path) -> PathBuf {
+        dir.join(&self.endpoint).join(format!("{}.json", self.key()))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        let path = self.path_in(dir);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        crate::utils::save_response(&path, &serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+/// Endpoint name of an API path or URL: `/api/v3/getSpaces?x=1` -> `getSpaces`
+pub fn endpoint_name(path: &str) -> Option<&str> {
+    let (_, rest) = path.split_once("/v3/")?;
+    let name = rest.split(['?', '#', '/']).next()?;
+    (!name.is_empty()).then_some(name)
+}
+
+/// First 16 hex chars of the SHA-256 of the canonical (key-sorted) body
+pub fn body_key(body: &Value) -> String {
+    // serde_json's default map is ordered, so this serialization is canonical
+    let canonical = serde_json::to_string(body).unwrap_or_default();
+    hex::encode(&Sha256::digest(canonical.as_bytes())[..8])
+}
+
+/// Load every fixture below `dir`, sorted by endpoint then key
+pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Fixture>> {
+    let dir = dir.as_ref();
+    let mut fixtures = Vec::new();
+    if !dir.exists() {
+        return Ok(fixtures);
+    }
+    for endpoint in fs::read_dir(dir).with_context(|| format!("Failed to list {}", dir.display()))? {
+        let endpoint = endpoint?.path();
+        if !endpoint.is_dir() {
+            continue;
+        }
+        for file in fs::read_dir(&endpoint)? {
+            let file = file?.path();
+            if file.extension().is_some_and(|e| e == "json") {
+                let data = fs::read(&file)?;
+                let fixture: Fixture = serde_json::from_slice(&data)
+                    .with_context(|| format!("Invalid fixture {}", file.display()))?;
+                fixtures.push(fixture);
+            }
+        }
+    }
+    fixtures.sort_by(|a, b| (&a.endpoint, a.key()).cmp(&(&b.endpoint, b.key())));
+    Ok(fixtures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_body_key_ignores_key_order() {
+        let a: Value = serde_json::from_str(r#"{"b":1,"a":{"y":2,"x":3}}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"a":{"x":3,"y":2},"b":1}"#).unwrap();
+        assert_eq!(body_key(&a), body_key(&b));
+        assert_ne!(body_key(&a), body_key(&json!({ "b": 2 })));
+    }
+
+    #[test]
+    fn test_endpoint_name() {
+        assert_eq!(endpoint_name("https://www.notion.so/api/v3/getSpaces?x=1"), Some("getSpaces"));
+        assert_eq!(endpoint_name("/v3/loadPageChunk"), Some("loadPageChunk"));
+        assert_eq!(endpoint_name("/api/v2/other"), None);
+    }
+
+    #[test]
+    fn test_save_and_load_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let fixture = Fixture {
+            endpoint: "getSpaces".to_string(),
+            status: 200,
+            request: json!({}),
+            response: json!({ "ok": true }),
+        };
+        fixture.save(dir).unwrap();
+        fixture.save(dir).unwrap();
+
+        assert_eq!(load_dir(dir).unwrap(), vec![fixture]);
+    }
+}
@@ -0,0 +1,374 @@
+//! Link graph between pages
+//!
+//! Notion's backlinks panel is computed client side from the same records we
+//! load, so we can rebuild it: mentions in rich text, `link_to_page`/`alias`
+//! blocks, relation properties, child pages and synced-block references.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::models::{plain_text, RecordMap};
+use crate::utils::{extract_page_id_from_url, to_uuid_format};
+
+/// Block types that own their content, i.e. the nodes of the graph
+const PAGE_TYPES: [&str; 2] = ["page", "collection_view_page"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    Mention,
+    LinkToPage,
+    Relation,
+    ChildPage,
+    SyncedBlock,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct Link {
+    /// Page that contains the link
+    pub source: String,
+    /// Page (or synced block) being linked to
+    pub target: String,
+    pub kind: LinkKind,
+    /// Block the link was found in
+    pub via: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LinkGraph {
+    /// Titles of known pages, by id
+    pub titles: BTreeMap<String, String>,
+    pub links: BTreeSet<Link>,
+}
+
+impl LinkGraph {
+    pub fn from_record_map(records: &RecordMap) -> Self {
+        let mut graph = LinkGraph::default();
+        for (id, record) in &records.block {
+            let block = &record.value;
+            if block.get("alive").and_then(Value::as_bool) == Some(false) {
+                continue;
+            }
+            let ty = block.get("type").and_then(Value::as_str).unwrap_or_default();
+            if PAGE_TYPES.contains(&ty) {
+                let title = block.pointer("/properties/title").map(plain_text).unwrap_or_default();
+                graph.titles.insert(to_uuid_format(id).unwrap_or_else(|| id.to_string()), title);
+            }
+            let Some(source) = owning_page(records, id) else {
+                continue;
+            };
+            graph.collect(records, &source, id, block, ty);
+        }
+        graph
+    }
+
+    fn collect(&mut self, records: &RecordMap, source: &str, id: &str, block: &Value, ty: &str) {
+        let via = to_uuid_format(id).unwrap_or_else(|| id.to_string());
+        // Child pages link from their parent page
+        if PAGE_TYPES.contains(&ty) {
+            if let Some(parent) = block.get("parent_id").and_then(Value::as_str).and_then(|p| owning_page(records, p)) {
+                self.links.insert(Link {
+                    source: parent,
+                    target: via.clone(),
+                    kind: LinkKind::ChildPage,
+                    via: via.clone(),
+                });
+            }
+        }
+
+        let mut add = |target: &str, kind: LinkKind| {
+            let target = to_uuid_format(target).unwrap_or_else(|| target.to_string());
+            if target != source {
+                self.links.insert(Link {
+                    source: source.to_string(),
+                    target,
+                    kind,
+                    via: via.clone(),
+                });
+            }
+        };
+
+        match ty {
+            "link_to_page" | "alias" => {
+                if let Some(target) = block.pointer("/format/alias_pointer/id").and_then(Value::as_str) {
+                    add(target, LinkKind::LinkToPage);
+                }
+            }
+            "transclusion_reference" => {
+                if let Some(target) = block
+                    .pointer("/format/transclusion_reference_pointer/id")
+                    .and_then(Value::as_str)
+                {
+                    add(target, LinkKind::SyncedBlock);
+                }
+            }
+            _ => {}
+        }
+
+        let relations = relation_properties(records, block);
+        for (prop_id, value) in block.get("properties").and_then(Value::as_object).into_iter().flatten() {
+            let kind = if relations.contains(prop_id.as_str()) {
+                LinkKind::Relation
+            } else {
+                LinkKind::Mention
+            };
+            for target in linked_pages(value) {
+                add(&target, kind);
+            }
+        }
+    }
+
+    /// Links pointing at `page_id`
+    pub fn backlinks(&self, page_id: &str) -> Vec<&Link> {
+        let page_id = to_uuid_format(page_id).unwrap_or_else(|| page_id.to_string());
+        self.links.iter().filter(|l| l.target == page_id).collect()
+    }
+
+    /// Links found on `page_id`
+    pub fn outlinks(&self, page_id: &str) -> Vec<&Link> {
+        let page_id = to_uuid_format(page_id).unwrap_or_else(|| page_id.to_string());
+        self.links.iter().filter(|l| l.source == page_id).collect()
+    }
+
+    fn nodes(&self) -> BTreeSet<&str> {
+        self.titles
+            .keys()
+            .map(String::as_str)
+            .chain(self.links.iter().flat_map(|l| [l.source.as_str(), l.target.as_str()]))
+            .collect()
+    }
+
+    fn label<'a>(&'a self, id: &'a str) -> &'a str {
+        self.titles.get(id).map(String::as_str).filter(|t| !t.is_empty()).unwrap_or(id)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph notion {\n");
+        for id in self.nodes() {
+            let _ = writeln!(out, "  \"{}\" [label=\"{}\"];", id, escape_dot(self.label(id)));
+        }
+        for link in &self.links {
+            let kind = serde_json::to_value(link.kind).unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "  \"{}\" -> \"{}\" [label=\"{}\"];",
+                link.source,
+                link.target,
+                kind.as_str().unwrap_or_default()
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"title\" for=\"node\" attr.name=\"title\" attr.type=\"string\"/>\n",
+            "  <key id=\"kind\" for=\"edge\" attr.name=\"kind\" attr.type=\"string\"/>\n",
+            "  <key id=\"via\" for=\"edge\" attr.name=\"via\" attr.type=\"string\"/>\n",
+            "  <graph id=\"notion\" edgedefault=\"directed\">\n",
+        ));
+        for id in self.nodes() {
+            let _ = writeln!(
+                out,
+                "    <node id=\"{}\"><data key=\"title\">{}</data></node>",
+                escape_xml(id),
+                escape_xml(self.label(id))
+            );
+        }
+        for link in &self.links {
+            let kind = serde_json::to_value(link.kind).unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "    <edge source=\"{}\" target=\"{}\"><data key=\"kind\">{}</data><data key=\"via\">{}</data></edge>",
+                escape_xml(&link.source),
+                escape_xml(&link.target),
+                kind.as_str().unwrap_or_default(),
+                escape_xml(&link.via)
+            );
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+}
+
+/// Walk up `parent_id` until a page is found (the block itself if it is one)
+fn owning_page(records: &RecordMap, id: &str) -> Option<String> {
+    let mut visited = HashSet::new();
+    let mut current = id.to_string();
+    loop {
+        if !visited.insert(current.clone()) {
+            return None;
+        }
+        let block = records.get("block", &current)?;
+        let ty = block.get("type").and_then(Value::as_str).unwrap_or_default();
+        if PAGE_TYPES.contains(&ty) {
+            return Some(to_uuid_format(&current).unwrap_or(current));
+        }
+        if block.get("parent_table").and_then(Value::as_str).is_some_and(|t| t != "block") {
+            return None;
+        }
+        current = block.get("parent_id")?.as_str()?.to_string();
+    }
+}
+
+/// Schema ids of relation properties when `block` is a database row
+fn relation_properties<'a>(records: &'a RecordMap, block: &Value) -> HashSet<&'a str> {
+    if block.get("parent_table").and_then(Value::as_str) != Some("collection") {
+        return HashSet::new();
+    }
+    block
+        .get("parent_id")
+        .and_then(Value::as_str)
+        .and_then(|id| records.get("collection", id))
+        .and_then(|c| c.get("schema"))
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+        .filter(|(_, p)| p.get("type").and_then(Value::as_str) == Some("relation"))
+        .map(|(id, _)| id.as_str())
+        .collect()
+}
+
+/// Page ids referenced from a rich text value via `p` mentions or internal links
+fn linked_pages(value: &Value) -> Vec<String> {
+    let annotations = value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|seg| seg.get(1)?.as_array())
+        .flatten();
+
+    annotations
+        .filter_map(|a| {
+            let kind = a.get(0)?.as_str()?;
+            let arg = a.get(1)?.as_str()?;
+            match kind {
+                "p" => Some(arg.to_string()),
+                "a" => {
+                    let url = if arg.starts_with('/') {
+                        format!("https://www.notion.so{}", arg)
+                    } else {
+                        arg.to_string()
+                    };
+                    extract_page_id_from_url(&url)
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Record;
+    use serde_json::json;
+
+    const HOME: &str = "00000000-0000-0000-0000-000000000001";
+    const DOCS: &str = "00000000-0000-0000-0000-000000000002";
+    const ROW: &str = "00000000-0000-0000-0000-000000000003";
+    const SYNCED: &str = "00000000-0000-0000-0000-000000000004";
+
+    fn insert(records: &mut RecordMap, id: &str, value: Value) {
+        records.block.insert(id.to_string(), Record::new(value));
+    }
+
+    fn records() -> RecordMap {
+        let mut records = RecordMap::default();
+        insert(&mut records, HOME, json!({ "type": "page", "properties": { "title": [["Home & <root>"]] } }));
+        insert(&mut records, DOCS, json!({
+            "type": "page", "parent_id": HOME, "parent_table": "block",
+            "properties": { "title": [["Docs"]] },
+        }));
+        insert(&mut records, "t1", json!({
+            "type": "text", "parent_id": HOME, "parent_table": "block",
+            "properties": { "title": [["see "], ["‣", [["p", DOCS]]], [" and ", [["a", "/00000000000000000000000000000003"]]]] },
+        }));
+        insert(&mut records, "l1", json!({
+            "type": "link_to_page", "parent_id": DOCS, "parent_table": "block",
+            "format": { "alias_pointer": { "id": HOME, "table": "block" } },
+        }));
+        insert(&mut records, SYNCED, json!({ "type": "transclusion_container", "parent_id": HOME, "parent_table": "block" }));
+        insert(&mut records, "s1", json!({
+            "type": "transclusion_reference", "parent_id": DOCS, "parent_table": "block",
+            "format": { "transclusion_reference_pointer": { "id": SYNCED, "table": "block" } },
+        }));
+        insert(&mut records, ROW, json!({
+            "type": "page", "parent_id": "coll", "parent_table": "collection",
+            "properties": { "title": [["Row"]], "rel": [["‣", [["p", DOCS]]]] },
+        }));
+        records.collection.insert(
+            "coll".to_string(),
+            Record::new(json!({ "schema": { "rel": { "name": "Docs", "type": "relation" } } })),
+        );
+        records
+    }
+
+    fn kinds(links: Vec<&Link>) -> Vec<(String, LinkKind)> {
+        links.into_iter().map(|l| (l.source.clone(), l.kind)).collect()
+    }
+
+    #[test]
+    fn test_backlinks() {
+        let graph = LinkGraph::from_record_map(&records());
+        let mut back = kinds(graph.backlinks(DOCS));
+        back.sort();
+        assert_eq!(
+            back,
+            vec![
+                (HOME.to_string(), LinkKind::Mention),
+                (HOME.to_string(), LinkKind::ChildPage),
+                (ROW.to_string(), LinkKind::Relation),
+            ]
+        );
+        assert_eq!(kinds(graph.backlinks(HOME)), vec![(DOCS.to_string(), LinkKind::LinkToPage)]);
+    }
+
+    #[test]
+    fn test_outlinks() {
+        let graph = LinkGraph::from_record_map(&records());
+        let out: Vec<(String, LinkKind)> = graph.outlinks(DOCS).into_iter().map(|l| (l.target.clone(), l.kind)).collect();
+        assert!(out.contains(&(HOME.to_string(), LinkKind::LinkToPage)));
+        assert!(out.contains(&(SYNCED.to_string(), LinkKind::SyncedBlock)));
+
+        let from_home: Vec<_> = graph.outlinks(HOME).into_iter().map(|l| l.target.clone()).collect();
+        assert!(from_home.contains(&ROW.to_string()), "internal link via URL");
+    }
+
+    #[test]
+    fn test_exports() {
+        let graph = LinkGraph::from_record_map(&records());
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph notion {"));
+        assert!(dot.contains(&format!("\"{}\" -> \"{}\" [label=\"link_to_page\"];", DOCS, HOME)));
+
+        let graphml = graph.to_graphml();
+        assert!(graphml.contains("Home &amp; &lt;root&gt;"));
+        assert!(graphml.contains("<data key=\"kind\">relation</data>"));
+
+        let json: Value = serde_json::from_str(&graph.to_json().unwrap()).unwrap();
+        assert_eq!(json["titles"][DOCS], "Docs");
+        assert!(json["links"].as_array().unwrap().iter().any(|l| l["kind"] == "synced_block"));
+    }
+}
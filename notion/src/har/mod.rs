@@ -0,0 +1,307 @@
+//! Import browser HAR captures
+//!
+//! DevTools can export a session as a HAR file. We pull every `/api/v3/*`
+//! exchange out of it, strip cookies and auth headers, scrub the captured
+//! session token from the bodies, and store them as fixtures.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::api::endpoints::paths;
+use crate::api::scrub_response;
+use crate::fixture::{endpoint_name, Fixture, REDACTED};
+
+/// Headers whose values are credentials
+const SECRET_HEADERS: [&str; 4] = ["cookie", "set-cookie", "authorization", "x-notion-auth"];
+
+/// Cookies whose values are credentials
+const SECRET_COOKIES: [&str; 2] = ["token_v2", "file_token"];
+
+#[derive(Debug, Deserialize)]
+struct Har {
+    log: HarLog,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarLog {
+    #[serde(default)]
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarEntry {
+    request: HarRequest,
+    response: HarResponse,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    url: String,
+    #[serde(default)]
+    headers: Vec<HarHeader>,
+    post_data: Option<HarPostData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarPostData {
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarResponse {
+    status: u16,
+    #[serde(default)]
+    headers: Vec<HarHeader>,
+    content: HarContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarContent {
+    text: Option<String>,
+    encoding: Option<String>,
+}
+
+/// One API call from the capture, with credentials removed
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub fixture: Fixture,
+    pub request_headers: BTreeMap<String, String>,
+    pub response_headers: BTreeMap<String, String>,
+}
+
+/// Every `/api/v3` exchange in a HAR capture, grouped by endpoint
+#[derive(Debug, Clone, Default)]
+pub struct HarImport {
+    pub exchanges: BTreeMap<String, Vec<Exchange>>,
+}
+
+/// Which `paths` endpoints the captures cover
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    /// `(constant name, number of captured exchanges)`
+    pub captured: Vec<(&'static str, usize)>,
+    pub unseen: Vec<&'static str>,
+    /// Captured endpoints with no constant in `paths`
+    pub unknown: Vec<String>,
+}
+
+impl HarImport {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = fs::read(path.as_ref()).with_context(|| format!("Failed to read {}", path.as_ref().display()))?;
+        Self::from_slice(&data)
+    }
+
+    pub fn from_slice(data: &[u8]) -> Result<Self> {
+        let har: Har = serde_json::from_slice(data).context("Not a HAR file")?;
+        let mut import = HarImport::default();
+        for entry in har.log.entries {
+            let Some(endpoint) = endpoint_name(&entry.request.url).map(str::to_string) else {
+                continue;
+            };
+            if !entry.request.url.contains("/api/v3/") {
+                continue;
+            }
+
+            let secrets = cookie_secrets(entry.request.headers.iter().chain(&entry.response.headers));
+            let mut request = parse_body(entry.request.post_data.and_then(|p| p.text));
+            let mut response = parse_body(decode_content(entry.response.content));
+            scrub_response(&mut request, "");
+            scrub_response(&mut response, "");
+            for secret in &secrets {
+                scrub_response(&mut request, secret);
+                scrub_response(&mut response, secret);
+            }
+
+            let exchange = Exchange {
+                fixture: Fixture {
+                    endpoint: endpoint.clone(),
+                    status: entry.response.status,
+                    request,
+                    response,
+                },
+                request_headers: redact_headers(entry.request.headers),
+                response_headers: redact_headers(entry.response.headers),
+            };
+            import.exchanges.entry(endpoint).or_default().push(exchange);
+        }
+        debug!("HAR contained {} API endpoints", import.exchanges.len());
+        Ok(import)
+    }
+
+    /// Write one fixture per distinct request body; returns the files written
+    pub fn write_fixtures<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<PathBuf>> {
+        let mut written = Vec::new();
+        for exchange in self.exchanges.values().flatten() {
+            written.push(exchange.fixture.save(dir.as_ref())?);
+        }
+        written.sort();
+        written.dedup();
+        Ok(written)
+    }
+
+    pub fn coverage(&self) -> Coverage {
+        let mut coverage = Coverage::default();
+        let mut known = BTreeSet::new();
+        for (name, path) in paths::ALL {
+            let endpoint = path.trim_start_matches("/v3/");
+            known.insert(endpoint);
+            match self.exchanges.get(endpoint) {
+                Some(found) => coverage.captured.push((*name, found.len())),
+                None => coverage.unseen.push(*name),
+            }
+        }
+        coverage.unknown = self
+            .exchanges
+            .keys()
+            .filter(|e| !known.contains(e.as_str()))
+            .cloned()
+            .collect();
+        coverage
+    }
+}
+
+fn redact_headers(headers: Vec<HarHeader>) -> BTreeMap<String, String> {
+    headers
+        .into_iter()
+        .filter(|h| !h.name.starts_with(':'))
+        .map(|h| {
+            let name = h.name.to_lowercase();
+            let value = if SECRET_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                h.value
+            };
+            (name, value)
+        })
+        .collect()
+}
+
+/// Values of credential cookies sent or set in these headers
+fn cookie_secrets<'a>(headers: impl Iterator<Item = &'a HarHeader>) -> BTreeSet<String> {
+    headers
+        .filter(|h| h.name.eq_ignore_ascii_case("cookie") || h.name.eq_ignore_ascii_case("set-cookie"))
+        .flat_map(|h| h.value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .filter(|(name, value)| SECRET_COOKIES.contains(name) && !value.is_empty())
+        .map(|(_, value)| value.to_string())
+        .collect()
+}
+
+fn decode_content(content: HarContent) -> Option<String> {
+    let text = content.text?;
+    if content.encoding.as_deref() != Some("base64") {
+        return Some(text);
+    }
+    match base64::engine::general_purpose::STANDARD.decode(text.as_bytes()) {
+        Ok(bytes) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+        Err(e) => {
+            warn!("Could not decode base64 response body: {}", e);
+            None
+        }
+    }
+}
+
+/// JSON bodies are parsed, anything else is kept as a string
+fn parse_body(text: Option<String>) -> Value {
+    match text {
+        None => Value::Null,
+        Some(text) => serde_json::from_str(&text).unwrap_or(Value::String(text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn har() -> Vec<u8> {
+        let spaces = base64::engine::general_purpose::STANDARD.encode(r#"{"spaces":[],"token":"other"}"#);
+        serde_json::to_vec(&json!({ "log": { "entries": [
+            {
+                "request": {
+                    "method": "POST",
+                    "url": "https://www.notion.so/api/v3/loadPageChunk",
+                    "headers": [
+                        { "name": "Cookie", "value": "token_v2=secret; notion_user_id=abc" },
+                        { "name": "Content-Type", "value": "application/json" },
+                    ],
+                    "postData": { "mimeType": "application/json", "text": "{\"pageId\":\"p1\",\"echo\":\"secret\"}" },
+                },
+                "response": {
+                    "status": 200,
+                    "headers": [{ "name": "set-cookie", "value": "token_v2=rotated" }],
+                    "content": { "text": "{\"recordMap\":{},\"cookie\":\"token_v2=secret; path=/\",\"next\":\"rotated\"}" },
+                },
+            },
+            {
+                "request": { "method": "POST", "url": "https://www.notion.so/api/v3/getSpaces", "headers": [] },
+                "response": { "status": 200, "content": { "text": spaces, "encoding": "base64" } },
+            },
+            {
+                "request": { "method": "POST", "url": "https://www.notion.so/api/v3/getFeatureFlags", "headers": [] },
+                "response": { "status": 200, "content": { "text": "ok" } },
+            },
+            {
+                "request": { "method": "GET", "url": "https://www.notion.so/images/logo.png", "headers": [] },
+                "response": { "status": 200, "content": {} },
+            },
+        ] } }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_extracts_api_exchanges_and_redacts() {
+        let import = HarImport::from_slice(&har()).unwrap();
+        assert_eq!(import.exchanges.len(), 3);
+
+        let load = &import.exchanges["loadPageChunk"][0];
+        assert_eq!(load.fixture.request, json!({ "pageId": "p1", "echo": REDACTED }));
+        assert_eq!(
+            load.fixture.response,
+            json!({ "recordMap": {}, "cookie": format!("token_v2={}; path=/", REDACTED), "next": REDACTED })
+        );
+        assert_eq!(load.request_headers["cookie"], REDACTED);
+        assert_eq!(load.request_headers["content-type"], "application/json");
+        assert_eq!(load.response_headers["set-cookie"], REDACTED);
+
+        assert_eq!(import.exchanges["getSpaces"][0].fixture.response, json!({ "spaces": [], "token": REDACTED }));
+        assert_eq!(import.exchanges["getFeatureFlags"][0].fixture.response, json!("ok"));
+    }
+
+    #[test]
+    fn test_coverage() {
+        let coverage = HarImport::from_slice(&har()).unwrap().coverage();
+        assert!(coverage.captured.contains(&("LOAD_PAGE_CHUNK", 1)));
+        assert!(coverage.captured.contains(&("GET_SPACES", 1)));
+        assert!(coverage.unseen.contains(&"SUBMIT_TRANSACTION"));
+        assert_eq!(coverage.unknown, vec!["getFeatureFlags"]);
+    }
+
+    #[test]
+    fn test_write_fixtures_has_no_secrets() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let written = HarImport::from_slice(&har()).unwrap().write_fixtures(dir).unwrap();
+        assert_eq!(written.len(), 3);
+        for path in written {
+            let text = fs::read_to_string(path).unwrap();
+            for secret in ["secret", "rotated", "other"] {
+                assert!(!text.contains(secret), "{} leaked into {}", secret, text);
+            }
+        }
+    }
+}
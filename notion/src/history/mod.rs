@@ -0,0 +1,118 @@
+//! Page history
+//!
+//! Notion saves a snapshot of a page every few minutes while it is being
+//! edited. `getSnapshotsList` lists them newest first and
+//! `getSnapshotContents` returns the page as it was at one of them, as a
+//! record map shaped like the one `loadPageChunk` returns, so two of them can
+//! be compared with [`diff::diff`](crate::diff::diff).
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::api::endpoints::{
+    paths, GetActivityLogRequest, GetSnapshotContentsRequest, GetSnapshotsListRequest, NavigableBlock,
+    SnapshotPointer,
+};
+use crate::api::NotionClient;
+use crate::models::{GetActivityLogResponse, GetSnapshotContentsResponse, GetSnapshotsListResponse, RecordMap, Snapshot};
+
+/// Snapshots of a page, newest first
+pub async fn list_snapshots(client: &NotionClient, page_id: &str, limit: u32) -> Result<Vec<Snapshot>> {
+    let request = GetSnapshotsListRequest {
+        block_id: page_id.to_string(),
+        size: limit,
+    };
+    let response: GetSnapshotsListResponse = client.post_json(paths::GET_SNAPSHOTS_LIST, &request).await?;
+    Ok(response.snapshots)
+}
+
+/// The page as it was when the snapshot was taken
+pub async fn fetch_snapshot(client: &NotionClient, space_id: &str, snapshot_id: &str) -> Result<RecordMap> {
+    let request = GetSnapshotContentsRequest {
+        snapshot: SnapshotPointer {
+            id: snapshot_id.to_string(),
+            space_id: space_id.to_string(),
+        },
+    };
+    let response: GetSnapshotContentsResponse = client.post_json(paths::GET_SNAPSHOT_CONTENTS, &request).await?;
+    Ok(response.record_map)
+}
+
+/// Recent activity in a space, or only on one page, newest first
+///
+/// Returns the `activity` records in log order; users and blocks they refer
+/// to are in the rest of the record map and not returned here.
+pub async fn activity_log(client: &NotionClient, space_id: &str, page_id: Option<&str>, limit: u32) -> Result<Vec<Value>> {
+    let request = GetActivityLogRequest {
+        space_id: space_id.to_string(),
+        navigable_block: page_id.map(|id| NavigableBlock { id: id.to_string() }),
+        limit,
+    };
+    let response: GetActivityLogResponse = client.post_json(paths::GET_ACTIVITY_LOG, &request).await?;
+    let activities = response.record_map.other.get("activity");
+    Ok(response
+        .activity_ids
+        .iter()
+        .filter_map(|id| activities?.get(id))
+        .map(|record| record.value.clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::fixture::Fixture;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_list_and_fetch_snapshots() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let fixtures = [
+            Fixture {
+                endpoint: "getSnapshotsList".to_string(),
+                status: 200,
+                request: json!({ "blockId": "p", "size": 10 }),
+                response: json!({ "snapshots": [{
+                    "id": "s1", "version": 12, "lastVersion": 3, "timestamp": 1700000000000i64,
+                    "authors": [{ "id": "u1", "table": "notion_user" }]
+                }] }),
+            },
+            Fixture {
+                endpoint: "getSnapshotContents".to_string(),
+                status: 200,
+                request: json!({ "snapshot": { "id": "s1", "spaceId": "sp" } }),
+                response: json!({ "recordMap": { "block": { "p": { "value": { "id": "p", "type": "page" } } } } }),
+            },
+            Fixture {
+                endpoint: "getActivityLog".to_string(),
+                status: 200,
+                request: json!({ "spaceId": "sp", "navigableBlock": { "id": "p" }, "limit": 5 }),
+                response: json!({
+                    "activityIds": ["a2", "a1"],
+                    "recordMap": { "activity": {
+                        "a1": { "value": { "id": "a1", "type": "block-edited" } },
+                        "a2": { "value": { "id": "a2", "type": "commented" } }
+                    } }
+                }),
+            },
+        ];
+        for fixture in &fixtures {
+            fixture.save(dir).unwrap();
+        }
+        let emulator = Emulator::new("secret").with_fixtures(dir).unwrap().start().await.unwrap();
+        let client = emulator.client();
+
+        let snapshots = list_snapshots(&client, "p", 10).await.unwrap();
+        assert_eq!(snapshots[0].version, 12);
+        assert_eq!(snapshots[0].authors[0].id, "u1");
+
+        let contents = fetch_snapshot(&client, "sp", "s1").await.unwrap();
+        assert!(contents.block.contains_key("p"));
+
+        let activities = activity_log(&client, "sp", Some("p"), 5).await.unwrap();
+        let ids: Vec<&str> = activities.iter().filter_map(|a| a["id"].as_str()).collect();
+        assert_eq!(ids, vec!["a2", "a1"]);
+    }
+}
@@ -3,8 +3,34 @@
 //! This library provides tools for exploring and interacting with Notion's undocumented API.
 
 pub mod api;
+pub mod cache;
+pub mod collection;
+pub mod config;
+pub mod credentials;
+pub mod diff;
+pub mod discovery;
+pub mod drift;
+pub mod edit;
+#[cfg(any(test, feature = "emulator"))]
+pub mod emulator;
+pub mod fixture;
+pub mod graph;
+pub mod har;
+pub mod history;
 pub mod models;
+pub mod output;
+#[cfg(feature = "realtime")]
+pub mod realtime;
+#[cfg(feature = "cli")]
+pub mod repl;
+pub mod schema;
+pub mod search;
+#[cfg(feature = "cli")]
+pub mod tui;
+pub mod upload;
 pub mod utils;
+pub mod watch;
+pub mod webhook;
 
 pub use api::{NotionClient, NotionHeaders};
 pub use api::paths;
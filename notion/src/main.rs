@@ -1,20 +1,649 @@
-mod api;
-mod models;
-mod utils;
+//! `notion-re` command-line interface
+//!
+//! Pages and databases can be given as IDs (with or without dashes) or as
+//! notion.so URLs. Settings come from the options, then `NOTION_*`
+//! variables, then the chosen profile in the config file.
 
-use anyhow::Result;
+use std::collections::HashSet;
+use std::io::{IsTerminal, Read};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use futures::StreamExt;
+use serde_json::{json, Value};
 use tracing::{info, Level};
-use tracing_subscriber;
+
+use notion_re::api::endpoints::{paths, RecordPointer, SearchRequest};
+use notion_re::api::NotionClient;
+use notion_re::cache::PageCache;
+use notion_re::collection::{Collection, Condition, Filter, QueryBuilder, Sort};
+use notion_re::config::{Config, Profile};
+use notion_re::edit::{self, Journal};
+use notion_re::credentials::{Credential, CredentialStore, DEFAULT_PROFILE};
+use notion_re::models::{plain_text, RecordMap};
+use notion_re::output::{self, Format};
+use notion_re::repl::Repl;
+use notion_re::search::search_stream;
+use notion_re::tui::App;
+use notion_re::utils::{extract_page_id_from_url, to_uuid_format};
+
+#[derive(Parser)]
+#[command(name = "notion-re", version, about = "Command-line client for Notion's private API")]
+struct Cli {
+    /// Profile from the config file [default: `default_profile`]
+    #[arg(long, short = 'p', env = "NOTION_PROFILE", global = true)]
+    profile: Option<String>,
+    /// `token_v2` cookie
+    #[arg(long, env = "NOTION_TOKEN", hide_env_values = true, global = true)]
+    token: Option<String>,
+    /// API root to talk to instead of notion.so, e.g. an emulator
+    #[arg(long, env = "NOTION_BASE_URL", global = true)]
+    base_url: Option<String>,
+    /// Output format [default: table for lists, json otherwise]
+    #[arg(long, short, value_enum, global = true)]
+    format: Option<Format>,
+    /// Where pages are cached [default: the platform cache dir]
+    #[arg(long, env = "NOTION_CACHE_DIR", global = true)]
+    cache_dir: Option<PathBuf>,
+    /// Where submitted transactions are journaled [default: the platform data dir]
+    #[arg(long, env = "NOTION_JOURNAL", global = true)]
+    journal: Option<PathBuf>,
+    /// Log more (-v info, -vv debug); logs go to stderr
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the signed-in user
+    Whoami,
+    /// List profiles in the config file
+    Profiles,
+    /// Store a token for the profile, encrypted with a passphrase
+    Login {
+        /// Active user to store along with the token
+        #[arg(long)]
+        user_id: Option<String>,
+        /// Use the OS keyring instead of the encrypted file
+        #[arg(long)]
+        keyring: bool,
+    },
+    /// Remove the stored token of the profile
+    Logout {
+        #[arg(long)]
+        keyring: bool,
+    },
+    /// List workspaces
+    Spaces,
+    /// Fetch a page or block and cache it
+    Get {
+        target: String,
+        /// Print the whole record map instead of the block
+        #[arg(long)]
+        records: bool,
+        /// Read from the cache only
+        #[arg(long)]
+        offline: bool,
+    },
+    /// Show the block tree of a page, or the top-level pages of every space
+    Tree {
+        target: Option<String>,
+        /// Stop below this many levels
+        #[arg(long)]
+        depth: Option<usize>,
+        /// Read from the cache only
+        #[arg(long)]
+        offline: bool,
+    },
+    /// Search pages
+    Search {
+        query: String,
+        /// Space to search in [default: the profile's space, else the first one]
+        #[arg(long)]
+        space: Option<String>,
+        /// Only search below this page
+        #[arg(long = "in")]
+        ancestor: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Ask Notion to export a page
+    Export {
+        target: String,
+        #[arg(long = "type", value_enum, default_value_t = ExportType::Markdown)]
+        export_type: ExportType,
+        /// Include subpages
+        #[arg(long)]
+        recursive: bool,
+        /// Download the export to this file
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Query a database
+    Query {
+        target: String,
+        /// View to query [default: the database's first view]
+        #[arg(long)]
+        view: Option<String>,
+        /// `Prop=value`, `Prop!=value` or `Prop~substring`; repeat to combine with AND
+        #[arg(long)]
+        filter: Vec<String>,
+        /// Property to sort by, `-Prop` for descending; repeatable
+        #[arg(long)]
+        sort: Vec<String>,
+        /// Full-text search within the rows
+        #[arg(long)]
+        search: Option<String>,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Undo a journaled transaction
+    Rollback {
+        /// Journal entry to undo [default: the latest]
+        id: Option<String>,
+        /// List journal entries instead
+        #[arg(long)]
+        list: bool,
+    },
+    /// Call any endpoint with a JSON body
+    Call {
+        /// `getSpaces`, `/v3/getSpaces` or `GET_SPACES`
+        endpoint: String,
+        /// JSON body, `@file` or `-` for stdin [default: {}]
+        body: Option<String>,
+    },
+    /// Explore endpoints interactively
+    Repl {
+        /// Where `:save` writes fixtures
+        #[arg(long, default_value = "fixtures")]
+        fixtures: PathBuf,
+    },
+    /// Browse the workspace in a terminal UI
+    Tui {
+        /// Only show cached pages, without a token or network
+        #[arg(long)]
+        offline: bool,
+    },
+    /// Manage the local page cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// List cached pages
+    List,
+    /// Print a cached page's record map
+    Show { target: String },
+    /// Drop a page from the cache
+    Remove { target: String },
+    /// Drop every cached page
+    Clear,
+    /// Print the cache directory
+    Path,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ExportType {
+    Markdown,
+    Html,
+    Pdf,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let level = match cli.verbose {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        _ => Level::DEBUG,
+    };
     tracing_subscriber::fmt()
-        .with_max_level(Level::DEBUG)
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
         .init();
 
-    info!("Notion API Reverse Engineering Tool");
+    if let Command::Repl { fixtures } = &cli.command {
+        let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        let history = dirs::data_dir().map(|d| d.join("notion-re").join("repl_history"));
+        return Repl::new(client(&cli)?)
+            .with_fixture_dir(fixtures)
+            .with_color(color)
+            .run(history)
+            .await;
+    }
 
-    // TODO: Add your reverse engineering logic here
+    if let Command::Tui { offline } = &cli.command {
+        let app = App::new(cache(&cli)?);
+        // Without a token the browser still works from the cache
+        let app = match (*offline, client(&cli)) {
+            (false, Ok(client)) => app.with_client(client),
+            _ => app,
+        };
+        return app.run().await;
+    }
 
+    let (value, default_format) = run(&cli).await?;
+    let format = cli.format.unwrap_or(default_format);
+    print!("{}", output::render(&value, format)?);
     Ok(())
 }
+
+/// Run the command, returning its result and the format that suits it best
+async fn run(cli: &Cli) -> Result<(Value, Format)> {
+    match &cli.command {
+        Command::Whoami => {
+            let records = client(cli)?.get_spaces().await?;
+            let users = records
+                .notion_user
+                .values()
+                .map(|r| {
+                    let name = [&r.value["given_name"], &r.value["family_name"]]
+                        .iter()
+                        .filter_map(|v| v.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    json!({ "id": r.value["id"], "name": name, "email": r.value["email"] })
+                })
+                .collect();
+            Ok((Value::Array(users), Format::Table))
+        }
+        Command::Profiles => {
+            let config = Config::load_default()?;
+            let active = config.profile_name(cli.profile.as_deref());
+            let rows = config
+                .profiles
+                .iter()
+                .map(|(name, p)| {
+                    json!({
+                        "name": name,
+                        "active": active.as_deref() == Some(name.as_str()),
+                        "token": p.token.is_some(),
+                        "user_id": p.user_id,
+                        "space_id": p.space_id,
+                        "base_url": p.base_url,
+                    })
+                })
+                .collect();
+            Ok((Value::Array(rows), Format::Table))
+        }
+        Command::Login { user_id, keyring } => {
+            let name = profile_name(cli)?;
+            let store = credential_store(*keyring, true)?;
+            let token = match cli.token.as_deref().filter(|t| !t.is_empty()) {
+                Some(token) => token.to_string(),
+                None => rpassword::prompt_password("token_v2: ")?.trim().to_string(),
+            };
+            if token.is_empty() {
+                bail!("No token given");
+            }
+            let credential = Credential {
+                token,
+                user_id: user_id.clone(),
+            };
+            store.set(&name, &credential)?;
+            Ok((json!({ "profile": name, "stored": true }), Format::Json))
+        }
+        Command::Logout { keyring } => {
+            let name = profile_name(cli)?;
+            let removed = credential_store(*keyring, false)?.delete(&name)?;
+            Ok((json!({ "profile": name, "removed": removed }), Format::Json))
+        }
+        Command::Spaces => {
+            let records = client(cli)?.get_spaces().await?;
+            let mut rows: Vec<Value> = records
+                .space
+                .values()
+                .map(|r| {
+                    let pages = r.value["pages"].as_array().map_or(0, Vec::len);
+                    json!({ "id": r.value["id"], "name": r.value["name"], "domain": r.value["domain"], "pages": pages })
+                })
+                .collect();
+            rows.sort_by_key(|r| r["name"].as_str().unwrap_or_default().to_string());
+            Ok((Value::Array(rows), Format::Table))
+        }
+        Command::Get { target, records, offline } => {
+            let page_id = page_id(target)?;
+            let map = load(cli, &page_id, *offline).await?;
+            if *records {
+                return Ok((serde_json::to_value(&map)?, Format::Json));
+            }
+            let block = map
+                .get("block", &page_id)
+                .with_context(|| format!("Block {} is not in the response", page_id))?;
+            Ok((block.clone(), Format::Json))
+        }
+        Command::Tree { target: Some(target), depth, offline } => {
+            let page_id = page_id(target)?;
+            let map = load(cli, &page_id, *offline).await?;
+            let indent = cli.format.unwrap_or(Format::Table) == Format::Table;
+            let mut rows = Vec::new();
+            // Content lists can point back up the tree
+            let mut seen = HashSet::new();
+            let mut stack = vec![(page_id, 0)];
+            while let Some((id, level)) = stack.pop() {
+                if !seen.insert(id.clone()) {
+                    continue;
+                }
+                let Some(block) = map.get("block", &id) else {
+                    continue;
+                };
+                rows.push(tree_row(&id, block, level, indent));
+                if depth.is_none_or(|max| level < max) {
+                    let children = block["content"].as_array().into_iter().flatten().filter_map(Value::as_str);
+                    let children: Vec<_> = children.map(|c| (c.to_string(), level + 1)).collect();
+                    stack.extend(children.into_iter().rev());
+                }
+            }
+            Ok((Value::Array(rows), Format::Table))
+        }
+        Command::Tree { target: None, .. } => {
+            let client = client(cli)?;
+            let records = client.get_spaces().await?;
+            let indent = cli.format.unwrap_or(Format::Table) == Format::Table;
+            let mut spaces: Vec<&Value> = records.space.values().map(|r| &r.value).collect();
+            spaces.sort_by_key(|s| s["name"].as_str().unwrap_or_default().to_string());
+
+            let mut rows = Vec::new();
+            for space in spaces {
+                rows.push(tree_row(space["id"].as_str().unwrap_or_default(), &json!({ "type": "space", "properties": { "title": [[space["name"]]] } }), 0, indent));
+                let pages: Vec<RecordPointer> = space["pages"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .map(RecordPointer::block)
+                    .collect();
+                for (pointer, record) in pages.iter().zip(client.get_record_values(&pages).await?) {
+                    rows.push(tree_row(&pointer.id, &record.value, 1, indent));
+                }
+            }
+            Ok((Value::Array(rows), Format::Table))
+        }
+        Command::Search { query, space, ancestor, limit } => {
+            let client = client(cli)?;
+            let request = match (ancestor, space) {
+                (Some(ancestor), _) => SearchRequest::in_ancestor(&page_id(ancestor)?, query),
+                (None, Some(space)) => SearchRequest::in_space(space, query),
+                (None, None) => match &profile(cli)?.space_id {
+                    Some(space) => SearchRequest::in_space(space, query),
+                    None => {
+                        let records = client.get_spaces().await?;
+                        let space = records.space.keys().min().context("No workspace found")?;
+                        SearchRequest::in_space(space, query)
+                    }
+                },
+            };
+            let request = SearchRequest {
+                limit: Some((*limit).min(100) as u32),
+                ..request
+            };
+            let results: Vec<_> = search_stream(&client, request).take(*limit).collect().await;
+            let results = results.into_iter().collect::<Result<Vec<_>>>()?;
+            Ok((serde_json::to_value(results)?, Format::Table))
+        }
+        Command::Export { target, export_type, recursive, output } => {
+            let client = client(cli)?;
+            let export_type = format!("{:?}", export_type).to_lowercase();
+            let body = json!({
+                "block": { "id": page_id(target)? },
+                "recursive": recursive,
+                "exportOptions": { "exportType": export_type, "timeZone": "UTC", "locale": "en" },
+            });
+            let response: Value = serde_json::from_str(&client.post(paths::EXPORT_PAGE, &body).await?)?;
+            let Some(path) = output else {
+                return Ok((response, Format::Json));
+            };
+            let url = response["exportURL"]
+                .as_str()
+                .context("The response has no exportURL to download")?;
+            let bytes = reqwest::get(url).await?.error_for_status()?.bytes().await?;
+            std::fs::write(path, &bytes).with_context(|| format!("Failed to write {}", path.display()))?;
+            Ok((json!({ "file": path, "bytes": bytes.len() }), Format::Json))
+        }
+        Command::Query { target, view, filter, sort, search, limit } => {
+            let client = client(cli)?;
+            let page_id = page_id(target)?;
+            let map = client.load_page(&page_id).await?;
+            let block = map
+                .get("block", &page_id)
+                .with_context(|| format!("Block {} is not in the response", page_id))?;
+            let collection_id = block["collection_id"]
+                .as_str()
+                .with_context(|| format!("{} is not a database", page_id))?;
+            let collection = Collection::from_record_map(&map, collection_id)?;
+
+            let mut query = QueryBuilder::new(&collection);
+            let view = view.as_deref().or_else(|| block["view_ids"].get(0).and_then(Value::as_str));
+            if let Some(view) = view {
+                query = query.view(view);
+            }
+            let filters = filter.iter().map(|f| parse_filter(f)).collect::<Result<Vec<_>>>()?;
+            if !filters.is_empty() {
+                query = query.filter(Filter::And(filters));
+            }
+            for property in sort {
+                query = query.sort(match property.strip_prefix('-') {
+                    Some(property) => Sort::descending(property),
+                    None => Sort::ascending(property),
+                });
+            }
+            if let Some(search) = search {
+                query = query.search(search);
+            }
+            if let Some(limit) = limit {
+                query = query.max_rows(*limit);
+            }
+
+            let result = query.run(&client).await?;
+            let rows = result
+                .rows
+                .into_iter()
+                .map(|row| {
+                    let mut value = serde_json::to_value(&row.properties)?;
+                    value["id"] = json!(row.id);
+                    Ok(value)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok((Value::Array(rows), Format::Table))
+        }
+        Command::Rollback { id, list } => {
+            let journal = journal(cli)?;
+            let entries = journal.entries()?;
+            if *list {
+                let rows = entries
+                    .iter()
+                    .map(|e| json!({ "id": e.id, "timestamp": e.timestamp, "operations": e.operations.len() }))
+                    .collect();
+                return Ok((Value::Array(rows), Format::Table));
+            }
+            let entry = match id {
+                Some(id) => entries.iter().find(|e| &e.id == id),
+                None => entries.last(),
+            };
+            let entry = entry.with_context(|| match id {
+                Some(id) => format!("No entry {} in {}", id, journal.path().display()),
+                None => format!("{} is empty", journal.path().display()),
+            })?;
+            edit::rollback(&client(cli)?, entry).await?;
+            Ok((json!({ "rolled_back": entry.id, "operations": entry.inverse.len() }), Format::Json))
+        }
+        Command::Call { endpoint, body } => {
+            let body: Value = match body.as_deref() {
+                None => json!({}),
+                Some("-") => {
+                    let mut text = String::new();
+                    std::io::stdin().read_to_string(&mut text)?;
+                    serde_json::from_str(&text).context("Invalid JSON on stdin")?
+                }
+                Some(body) => match body.strip_prefix('@') {
+                    Some(file) => {
+                        let text = std::fs::read_to_string(file).with_context(|| format!("Failed to read {}", file))?;
+                        serde_json::from_str(&text).with_context(|| format!("Invalid JSON in {}", file))?
+                    }
+                    None => serde_json::from_str(body).context("Invalid JSON body")?,
+                },
+            };
+            let response = client(cli)?.post(&paths::resolve(endpoint), &body).await?;
+            let value = serde_json::from_str(&response).unwrap_or(Value::String(response));
+            Ok((value, Format::Json))
+        }
+        Command::Repl { .. } | Command::Tui { .. } => unreachable!("handled in main"),
+        Command::Cache { action } => {
+            let cache = cache(cli)?;
+            match action {
+                CacheAction::List => Ok((serde_json::to_value(cache.list()?)?, Format::Table)),
+                CacheAction::Show { target } => {
+                    let page = cache
+                        .get(&page_id(target)?)?
+                        .with_context(|| format!("{} is not cached", target))?;
+                    Ok((serde_json::to_value(page.record_map)?, Format::Json))
+                }
+                CacheAction::Remove { target } => {
+                    let removed = cache.remove(&page_id(target)?)?;
+                    Ok((json!({ "removed": removed }), Format::Json))
+                }
+                CacheAction::Clear => Ok((json!({ "removed": cache.clear()? }), Format::Json)),
+                CacheAction::Path => Ok((json!(cache.dir()), Format::Json)),
+            }
+        }
+    }
+}
+
+/// The chosen profile with command-line options applied on top
+///
+/// Resolved once per run. Stored credentials stay locked here; only
+/// `client` unlocks them, when it has no other token.
+fn profile(cli: &Cli) -> Result<&'static Profile> {
+    static PROFILE: OnceLock<Profile> = OnceLock::new();
+    if let Some(profile) = PROFILE.get() {
+        return Ok(profile);
+    }
+    let mut profile = Profile::resolve(cli.profile.as_deref())?;
+    if let Some(token) = cli.token.as_deref().filter(|t| !t.is_empty()) {
+        profile.token = Some(token.to_string());
+    }
+    if let Some(url) = &cli.base_url {
+        profile.base_url = Some(url.clone());
+    }
+    if let Some(dir) = &cli.cache_dir {
+        profile.cache_dir = Some(dir.clone());
+    }
+    Ok(PROFILE.get_or_init(|| profile))
+}
+
+/// Name credentials are stored under for the chosen profile
+fn profile_name(cli: &Cli) -> Result<String> {
+    let name = Config::load_default()?.profile_name(cli.profile.as_deref());
+    Ok(name.unwrap_or_else(|| DEFAULT_PROFILE.to_string()))
+}
+
+/// The keyring, or the encrypted file unlocked with `NOTION_PASSPHRASE` or a prompt
+fn credential_store(keyring: bool, creating: bool) -> Result<CredentialStore> {
+    if keyring {
+        #[cfg(feature = "keyring")]
+        return Ok(CredentialStore::keyring());
+        #[cfg(not(feature = "keyring"))]
+        bail!("Built without keyring support; rebuild with `--features keyring`");
+    }
+    let path = CredentialStore::default_path().context("No config directory for the credentials file")?;
+    if let Ok(store) = CredentialStore::file_from_env() {
+        return Ok(store);
+    }
+    let passphrase = rpassword::prompt_password(format!("Passphrase for {}: ", path.display()))?;
+    if creating && !path.exists() && rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        bail!("Passphrases do not match");
+    }
+    Ok(CredentialStore::file(path, &passphrase))
+}
+
+fn client(cli: &Cli) -> Result<NotionClient> {
+    let mut profile = profile(cli)?.clone();
+    if profile.token.is_none() {
+        let store = match CredentialStore::available() {
+            Some(store) => Some(store),
+            // The file exists but NOTION_PASSPHRASE is not set; ask if someone can answer
+            None if CredentialStore::default_path().is_some_and(|p| p.exists()) && std::io::stdin().is_terminal() => {
+                Some(credential_store(false, false)?)
+            }
+            None => None,
+        };
+        if let Some(store) = store {
+            profile = profile.with_stored_credential(&store, &profile_name(cli)?)?;
+        }
+    }
+    if profile.token.as_deref().is_none_or(str::is_empty) {
+        let path = Config::default_path().map(|p| p.display().to_string()).unwrap_or_default();
+        bail!("No token: run `notion-re login`, pass --token, set NOTION_TOKEN or add `token = \"...\"` to {}", path);
+    }
+    Ok(profile.client()?.with_journal(journal(cli)?))
+}
+
+fn journal(cli: &Cli) -> Result<Journal> {
+    let path = cli
+        .journal
+        .clone()
+        .or_else(Journal::default_path)
+        .context("No data directory for the journal; pass --journal")?;
+    Ok(Journal::new(path))
+}
+
+fn cache(cli: &Cli) -> Result<PageCache> {
+    let dir = profile(cli)?
+        .cache_dir
+        .clone()
+        .or_else(PageCache::default_dir)
+        .context("No cache directory; pass --cache-dir")?;
+    Ok(PageCache::new(dir))
+}
+
+/// Page ID from a URL or a bare ID, dashed as in record maps
+fn page_id(target: &str) -> Result<String> {
+    let id = extract_page_id_from_url(target).unwrap_or_else(|| target.to_string());
+    to_uuid_format(&id).with_context(|| format!("'{}' is not a Notion page ID or URL", target))
+}
+
+/// Load a page over the network and cache it, or read it from the cache
+async fn load(cli: &Cli, page_id: &str, offline: bool) -> Result<RecordMap> {
+    let cache = cache(cli)?;
+    if offline {
+        let page = cache
+            .get(page_id)?
+            .with_context(|| format!("{} is not cached; run `get` without --offline first", page_id))?;
+        return Ok(page.record_map);
+    }
+    let records = client(cli)?.load_page(page_id).await?;
+    let path = cache.put(page_id, &records)?;
+    info!("Cached {} at {}", page_id, path.display());
+    Ok(records)
+}
+
+/// Tables show depth by indenting the title, other formats get a `depth` field
+fn tree_row(id: &str, block: &Value, depth: usize, indent: bool) -> Value {
+    let title = block.get("properties").and_then(|p| p.get("title")).map(plain_text).unwrap_or_default();
+    if indent {
+        json!({ "title": format!("{}{}", "  ".repeat(depth), title), "type": block["type"], "id": id })
+    } else {
+        json!({ "title": title, "type": block["type"], "id": id, "depth": depth })
+    }
+}
+
+/// `Prop=value`, `Prop!=value` or `Prop~substring`
+fn parse_filter(filter: &str) -> Result<Filter> {
+    if let Some((property, value)) = filter.split_once("!=") {
+        return Ok(Filter::property(property, Condition::IsNot(value.to_string())));
+    }
+    if let Some((property, value)) = filter.split_once('~') {
+        return Ok(Filter::property(property, Condition::Contains(value.to_string())));
+    }
+    if let Some((property, value)) = filter.split_once('=') {
+        return Ok(Filter::property(property, Condition::Is(value.to_string())));
+    }
+    bail!("Invalid filter '{}'; expected Prop=value, Prop!=value or Prop~text", filter)
+}
@@ -1,7 +1,21 @@
 // Data models for Notion API responses
 
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
+/// Concatenate the text of a rich text value, skipping mention markers
+///
+/// Notion stores text as `[["Hello ", [["b"]]], ["‣", [["p", "<id>"]]]]`.
+pub fn plain_text(raw: &serde_json::Value) -> String {
+    raw.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|seg| seg.get(0)?.as_str())
+        .filter(|text| *text != "‣")
+        .collect()
+}
+
 /// Block type in Notion
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -121,17 +135,195 @@ pub struct Space {
 
 /// Page load chunk response
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct LoadPageChunkResponse {
     pub record_map: RecordMap,
     pub cursor: Option<Cursor>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Records returned by most read endpoints, grouped by table and keyed by record id
+///
+/// On the wire every table is an object of `{ "<id>": { "role": ..., "value": {...} } }`.
+/// Values are kept as raw JSON so that operations can be applied to them losslessly.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RecordMap {
-    pub block: Option<Vec<Block>>,
-    pub space: Option<Vec<Space>>,
-    pub user: Option<Vec<User>>,
-    pub notion_user: Option<Vec<User>>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub block: HashMap<String, Record>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub space: HashMap<String, Record>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub notion_user: HashMap<String, Record>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub collection: HashMap<String, Record>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub collection_view: HashMap<String, Record>,
+    /// Any other table (`space_view`, `user_root`, `discussion`, ...)
+    #[serde(flatten)]
+    pub other: HashMap<String, HashMap<String, Record>>,
+}
+
+/// A single entry of a `RecordMap` table
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub value: serde_json::Value,
+}
+
+impl Record {
+    pub fn new(value: serde_json::Value) -> Self {
+        Self { role: None, value }
+    }
+
+    /// The `version` field of the record value, if present
+    pub fn version(&self) -> Option<u64> {
+        self.value.get("version").and_then(|v| v.as_u64())
+    }
+}
+
+impl RecordMap {
+    /// Look up a table by its wire name
+    pub fn table(&self, table: &str) -> Option<&HashMap<String, Record>> {
+        match table {
+            "block" => Some(&self.block),
+            "space" => Some(&self.space),
+            "notion_user" => Some(&self.notion_user),
+            "collection" => Some(&self.collection),
+            "collection_view" => Some(&self.collection_view),
+            other => self.other.get(other),
+        }
+    }
+
+    /// Look up a table by its wire name, creating it if missing
+    pub fn table_mut(&mut self, table: &str) -> &mut HashMap<String, Record> {
+        match table {
+            "block" => &mut self.block,
+            "space" => &mut self.space,
+            "notion_user" => &mut self.notion_user,
+            "collection" => &mut self.collection,
+            "collection_view" => &mut self.collection_view,
+            other => self.other.entry(other.to_string()).or_default(),
+        }
+    }
+
+    /// Raw value of a record
+    pub fn get(&self, table: &str, id: &str) -> Option<&serde_json::Value> {
+        self.table(table)?.get(id).map(|r| &r.value)
+    }
+
+    /// Iterate over every table as `(name, records)`
+    pub fn tables(&self) -> impl Iterator<Item = (&str, &HashMap<String, Record>)> {
+        [
+            ("block", &self.block),
+            ("space", &self.space),
+            ("notion_user", &self.notion_user),
+            ("collection", &self.collection),
+            ("collection_view", &self.collection_view),
+        ]
+        .into_iter()
+        .chain(self.other.iter().map(|(k, v)| (k.as_str(), v)))
+    }
+
+    /// Copy every record of `other` into this map, replacing existing entries
+    pub fn merge(&mut self, other: RecordMap) {
+        let RecordMap { block, space, notion_user, collection, collection_view, other } = other;
+        self.block.extend(block);
+        self.space.extend(space);
+        self.notion_user.extend(notion_user);
+        self.collection.extend(collection);
+        self.collection_view.extend(collection_view);
+        for (table, records) in other {
+            self.table_mut(&table).extend(records);
+        }
+    }
+}
+
+/// Response of `getRecordValues`, one result per requested pointer in order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetRecordValuesResponse {
+    pub results: Vec<Record>,
+}
+
+/// Response of `syncRecordValues`: only records newer than the versions sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncRecordValuesResponse {
+    #[serde(default)]
+    pub record_map: RecordMap,
+}
+
+/// Response of `getSnapshotsList`, newest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetSnapshotsListResponse {
+    #[serde(default)]
+    pub snapshots: Vec<Snapshot>,
+}
+
+/// A saved version of a page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub id: String,
+    /// Page version this snapshot captured
+    pub version: u64,
+    /// Version of the previous snapshot
+    pub last_version: Option<u64>,
+    /// Milliseconds since the epoch
+    pub timestamp: i64,
+    #[serde(default)]
+    pub authors: Vec<SnapshotAuthor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotAuthor {
+    pub id: String,
+    pub table: String,
+}
+
+/// Response of `getSnapshotContents`: the page as it was
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSnapshotContentsResponse {
+    #[serde(default)]
+    pub record_map: RecordMap,
+}
+
+/// Response of `getActivityLog`; activities are in `record_map` under `activity`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetActivityLogResponse {
+    #[serde(default)]
+    pub activity_ids: Vec<String>,
+    #[serde(default)]
+    pub record_map: RecordMap,
+}
+
+/// Response of `queryCollection`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryCollectionResponse {
+    pub result: QueryCollectionResult,
+    #[serde(default)]
+    pub record_map: RecordMap,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryCollectionResult {
+    /// Results keyed by the reducer names used in the request
+    #[serde(default)]
+    pub reducer_results: HashMap<String, serde_json::Value>,
+}
+
+/// Response of `getUploadFileUrl`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadFileUrlResponse {
+    /// Permanent URL stored in the block's `source` property
+    pub url: String,
+    pub signed_get_url: Option<String>,
+    pub signed_put_url: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -146,6 +338,34 @@ pub struct SearchResults {
     pub total: Option<u32>,
 }
 
+/// Raw response of the `search` endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResponse {
+    #[serde(default)]
+    pub results: Vec<SearchHit>,
+    pub total: Option<u32>,
+    #[serde(default)]
+    pub record_map: RecordMap,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub id: String,
+    #[serde(default)]
+    pub is_navigable: bool,
+    pub score: Option<f64>,
+    pub space_id: Option<String>,
+    pub highlight: Option<SearchHighlight>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHighlight {
+    pub title: Option<String>,
+    pub text: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
     pub id: String,
@@ -0,0 +1,178 @@
+//! Rendering command results for the terminal
+//!
+//! Every command produces a JSON value: a list of rows or a single object.
+//! The same value can then be printed as pretty JSON, YAML, an aligned table
+//! or newline-delimited JSON for piping into `jq` and friends.
+
+use anyhow::Result;
+use serde_json::Value;
+
+/// Longest cell shown in a table before it is cut off
+const MAX_CELL_WIDTH: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Format {
+    Json,
+    Yaml,
+    Table,
+    Ndjson,
+}
+
+/// Render `value` in the given format, with a trailing newline
+pub fn render(value: &Value, format: Format) -> Result<String> {
+    let mut out = match format {
+        Format::Json => serde_json::to_string_pretty(value)?,
+        Format::Yaml => return Ok(serde_norway::to_string(value)?),
+        Format::Ndjson => match value {
+            Value::Array(items) => items
+                .iter()
+                .map(serde_json::to_string)
+                .collect::<serde_json::Result<Vec<_>>>()?
+                .join("\n"),
+            other => serde_json::to_string(other)?,
+        },
+        Format::Table => table(value),
+    };
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Rows become lines with one column per key; a single object becomes a
+/// key/value listing
+fn table(value: &Value) -> String {
+    match value {
+        Value::Array(rows) => {
+            let mut columns: Vec<&str> = Vec::new();
+            for row in rows {
+                for key in row.as_object().into_iter().flat_map(|o| o.keys()) {
+                    if !columns.contains(&key.as_str()) {
+                        columns.push(key);
+                    }
+                }
+            }
+            if columns.is_empty() {
+                return rows.iter().map(cell).collect::<Vec<_>>().join("\n");
+            }
+            let header: Vec<String> = columns.iter().map(|c| c.to_uppercase()).collect();
+            let body: Vec<Vec<String>> = rows
+                .iter()
+                .map(|row| columns.iter().map(|c| row.get(*c).map(cell).unwrap_or_default()).collect())
+                .collect();
+            aligned(header, body)
+        }
+        Value::Object(fields) => aligned(
+            vec!["KEY".to_string(), "VALUE".to_string()],
+            fields.iter().map(|(k, v)| vec![k.clone(), cell(v)]).collect(),
+        ),
+        other => cell(other),
+    }
+}
+
+fn aligned(header: Vec<String>, rows: Vec<Vec<String>>) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    std::iter::once(&header)
+        .chain(&rows)
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Pretty JSON with ANSI colors: keys blue, strings green, numbers cyan,
+/// booleans and null magenta
+pub fn colored_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_colored(value, 0, &mut out);
+    out
+}
+
+fn write_colored(value: &Value, indent: usize, out: &mut String) {
+    const RESET: &str = "\x1b[0m";
+    let pad = |n: usize| "  ".repeat(n);
+    match value {
+        Value::Null | Value::Bool(_) => out.push_str(&format!("\x1b[35m{}{}", value, RESET)),
+        Value::Number(n) => out.push_str(&format!("\x1b[36m{}{}", n, RESET)),
+        Value::String(_) => out.push_str(&format!("\x1b[32m{}{}", value, RESET)),
+        Value::Array(items) if items.is_empty() => out.push_str("[]"),
+        Value::Object(fields) if fields.is_empty() => out.push_str("{}"),
+        Value::Array(items) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                out.push_str(&pad(indent + 1));
+                write_colored(item, indent + 1, out);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&format!("{}]", pad(indent)));
+        }
+        Value::Object(fields) => {
+            out.push_str("{\n");
+            for (i, (key, item)) in fields.iter().enumerate() {
+                out.push_str(&format!("{}\x1b[34m{}{}: ", pad(indent + 1), Value::String(key.clone()), RESET));
+                write_colored(item, indent + 1, out);
+                out.push_str(if i + 1 < fields.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&format!("{}}}", pad(indent)));
+        }
+    }
+}
+
+fn cell(value: &Value) -> String {
+    let text = match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let text = text.replace('\n', " ");
+    if text.chars().count() > MAX_CELL_WIDTH {
+        let cut: String = text.chars().take(MAX_CELL_WIDTH - 1).collect();
+        format!("{}…", cut)
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_table_aligns_union_of_columns() {
+        let rows = json!([
+            { "id": "a", "title": "First" },
+            { "id": "bcd", "type": "page", "title": null },
+        ]);
+        assert_eq!(
+            render(&rows, Format::Table).unwrap(),
+            "ID   TITLE  TYPE\na    First\nbcd         page\n"
+        );
+    }
+
+    #[test]
+    fn test_ndjson_and_yaml() {
+        let rows = json!([{ "id": 1 }, { "id": 2 }]);
+        assert_eq!(render(&rows, Format::Ndjson).unwrap(), "{\"id\":1}\n{\"id\":2}\n");
+        assert_eq!(render(&json!({ "id": 1 }), Format::Yaml).unwrap(), "id: 1\n");
+    }
+
+    #[test]
+    fn test_colored_json_matches_pretty_layout() {
+        let value = json!({ "a": [1, "x", null], "b": {} });
+        let plain = regex::Regex::new("\x1b\\[[0-9]+m").unwrap().replace_all(&colored_json(&value), "").to_string();
+        assert_eq!(plain, serde_json::to_string_pretty(&value).unwrap());
+    }
+}
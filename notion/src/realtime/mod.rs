@@ -0,0 +1,340 @@
+//! Push notifications from Notion's message store
+//!
+//! The web app keeps an engine.io websocket open to `msgstore.www.notion.so`
+//! and registers a `versions/<id>:<table>` subscription for every record it
+//! shows. The server then sends a `notification` whenever a record's version
+//! moves past the one given at subscription time. Subscribing again with the
+//! last seen versions after a reconnect is therefore enough to catch up.
+
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::{SinkExt, Stream, StreamExt};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, warn};
+
+use crate::api::endpoints::RecordPointer;
+use crate::api::NotionHeaders;
+
+/// Realtime endpoint used by the web app
+pub const MSGSTORE_URL: &str = "wss://msgstore.www.notion.so/primus-v8/";
+
+const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(25);
+
+/// A record reached a new version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionChange {
+    pub pointer: RecordPointer,
+    pub version: u64,
+}
+
+/// Connects to the message store and hands out subscriptions
+#[derive(Debug, Clone)]
+pub struct RealtimeClient {
+    headers: NotionHeaders,
+    url: String,
+    reconnect_delay: Duration,
+    max_reconnect_delay: Duration,
+    max_retries: Option<u32>,
+}
+
+impl RealtimeClient {
+    pub fn new(headers: NotionHeaders) -> Self {
+        Self {
+            headers,
+            url: MSGSTORE_URL.to_string(),
+            reconnect_delay: Duration::from_secs(1),
+            max_reconnect_delay: Duration::from_secs(60),
+            max_retries: None,
+        }
+    }
+
+    pub fn from_token(token: String) -> Self {
+        Self::new(NotionHeaders::new(token))
+    }
+
+    /// Connect somewhere else, e.g. a local stand-in
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = url.to_string();
+        self
+    }
+
+    /// Back off from `initial`, doubling up to `max`, between reconnects
+    pub fn with_reconnect_delay(mut self, initial: Duration, max: Duration) -> Self {
+        self.reconnect_delay = initial;
+        self.max_reconnect_delay = max;
+        self
+    }
+
+    /// Give up after this many failed attempts in a row (default: never)
+    pub fn with_max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = Some(retries);
+        self
+    }
+
+    /// Watch records from their current version on
+    pub fn subscribe(&self, pointers: &[RecordPointer]) -> Subscription {
+        self.start(pointers.iter().map(|p| (p.clone(), None)).collect())
+    }
+
+    /// Watch records already known at the given versions
+    ///
+    /// Changes made since those versions are delivered right after connecting.
+    pub fn subscribe_since(&self, versions: &[(RecordPointer, u64)]) -> Subscription {
+        self.start(versions.iter().map(|(p, v)| (p.clone(), Some(*v))).collect())
+    }
+
+    fn start(&self, versions: BTreeMap<RecordPointer, Option<u64>>) -> Subscription {
+        let (sender, events) = mpsc::unbounded();
+        let task = tokio::spawn(run(self.clone(), versions, sender));
+        Subscription { events, task }
+    }
+}
+
+/// Stream of version changes; dropping it closes the connection
+pub struct Subscription {
+    events: UnboundedReceiver<Result<VersionChange>>,
+    task: JoinHandle<()>,
+}
+
+impl Stream for Subscription {
+    type Item = Result<VersionChange>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Keep a session alive, reconnecting with backoff until the receiver goes away
+async fn run(
+    client: RealtimeClient,
+    mut versions: BTreeMap<RecordPointer, Option<u64>>,
+    sender: UnboundedSender<Result<VersionChange>>,
+) {
+    let mut delay = client.reconnect_delay;
+    let mut failures = 0;
+    loop {
+        let mut connected = false;
+        let result = session(&client, &mut versions, &sender, &mut connected).await;
+        if sender.is_closed() {
+            return;
+        }
+        if connected {
+            delay = client.reconnect_delay;
+            failures = 0;
+        }
+        failures += 1;
+
+        let error = result.err().unwrap_or_else(|| anyhow::anyhow!("Connection closed"));
+        if client.max_retries.is_some_and(|max| failures > max) {
+            let _ = sender.unbounded_send(Err(error.context("Realtime connection failed")));
+            return;
+        }
+        warn!("Realtime connection lost ({:#}), reconnecting in {:?}", error, delay);
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(client.max_reconnect_delay);
+    }
+}
+
+/// One websocket connection: handshake, subscribe, then relay notifications
+async fn session(
+    client: &RealtimeClient,
+    versions: &mut BTreeMap<RecordPointer, Option<u64>>,
+    sender: &UnboundedSender<Result<VersionChange>>,
+    connected: &mut bool,
+) -> Result<()> {
+    let mut url = reqwest::Url::parse(&client.url).context("Invalid realtime URL")?;
+    url.query_pairs_mut()
+        .append_pair("sessionId", &uuid::Uuid::new_v4().to_string())
+        .append_pair("EIO", "3")
+        .append_pair("transport", "websocket");
+    let mut request = url.as_str().into_client_request()?;
+    request
+        .headers_mut()
+        .insert("Cookie", format!("token_v2={}", client.headers.token).parse()?);
+    if let Some(user_id) = &client.headers.user_id {
+        request.headers_mut().insert("x-notion-active-user-header", user_id.parse()?);
+    }
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(request)
+        .await
+        .context("Failed to connect to message store")?;
+    debug!("Connected to {}", client.url);
+
+    let mut ping_interval = DEFAULT_PING_INTERVAL;
+    if let Some(Message::Text(open)) = socket.next().await.transpose()? {
+        if let Some(handshake) = open.strip_prefix('0') {
+            let handshake: Value = serde_json::from_str(handshake).unwrap_or_default();
+            if let Some(ms) = handshake.get("pingInterval").and_then(Value::as_u64) {
+                ping_interval = Duration::from_millis(ms);
+            }
+        }
+    }
+
+    socket.send(Message::text(subscribe_packet(versions))).await?;
+    *connected = true;
+    debug!("Subscribed to {} record(s)", versions.len());
+
+    let mut ping = tokio::time::interval_at(tokio::time::Instant::now() + ping_interval, ping_interval);
+    loop {
+        tokio::select! {
+            _ = ping.tick() => socket.send(Message::text("2")).await?,
+            message = socket.next() => {
+                let text = match message.transpose()? {
+                    Some(Message::Text(text)) => text,
+                    Some(Message::Ping(data)) => {
+                        socket.send(Message::Pong(data)).await?;
+                        continue;
+                    }
+                    Some(Message::Close(_)) | None => bail!("Server closed the connection"),
+                    Some(_) => continue,
+                };
+                let (kind, payload) = text.split_at(text.len().min(1));
+                match kind {
+                    "1" => bail!("Server closed the session"),
+                    "2" => socket.send(Message::text(format!("3{}", payload))).await?,
+                    "4" => {
+                        for change in parse_notifications(payload) {
+                            let known = versions.get(&change.pointer).copied().flatten();
+                            if known.is_some_and(|v| v >= change.version) {
+                                continue;
+                            }
+                            versions.insert(change.pointer.clone(), Some(change.version));
+                            if sender.unbounded_send(Ok(change)).is_err() {
+                                return Ok(());
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Engine.io message registering every subscription at its last known version
+fn subscribe_packet(versions: &BTreeMap<RecordPointer, Option<u64>>) -> String {
+    let messages: Vec<Value> = versions
+        .iter()
+        .map(|(pointer, version)| {
+            json!({
+                "type": "/api/v1/registerSubscription",
+                "requestId": uuid::Uuid::new_v4().to_string(),
+                "key": format!("versions/{}:{}", pointer.id, pointer.table),
+                "version": version.map_or(-1, |v| v as i64),
+            })
+        })
+        .collect();
+    format!("4{}", Value::Array(messages))
+}
+
+/// Version notifications in a message payload (a single message or an array)
+fn parse_notifications(payload: &str) -> Vec<VersionChange> {
+    let messages = match serde_json::from_str(payload) {
+        Ok(Value::Array(messages)) => messages,
+        Ok(message) => vec![message],
+        Err(_) => return Vec::new(),
+    };
+    messages
+        .iter()
+        .filter(|m| m.get("type").and_then(Value::as_str) == Some("notification"))
+        .filter_map(|m| {
+            let key = m.get("key")?.as_str()?;
+            let (id, table) = key.strip_prefix("versions/")?.split_once(':')?;
+            Some(VersionChange {
+                pointer: RecordPointer::new(table, id),
+                version: m.get("value")?.as_u64()?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+
+    #[test]
+    fn test_parse_notifications() {
+        let payload = r#"[{"type":"notification","key":"versions/abc:block","value":7},{"type":"ack"}]"#;
+        assert_eq!(
+            parse_notifications(payload),
+            vec![VersionChange {
+                pointer: RecordPointer::block("abc"),
+                version: 7
+            }]
+        );
+    }
+
+    #[allow(clippy::result_large_err)] // callback signature is fixed by tungstenite
+    fn check_cookie(req: &Request, res: Response) -> Result<Response, ErrorResponse> {
+        assert_eq!(req.headers()["cookie"], "token_v2=secret");
+        Ok(res)
+    }
+
+    /// Accept one connection, check the cookie and return the subscribe packet
+    async fn accept(
+        listener: &TcpListener,
+    ) -> (tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>, Value) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut socket = tokio_tungstenite::accept_hdr_async(stream, check_cookie).await.unwrap();
+        socket
+            .send(Message::text(r#"0{"sid":"s","pingInterval":25000,"pingTimeout":60000}"#))
+            .await
+            .unwrap();
+        let Some(Ok(Message::Text(packet))) = socket.next().await else {
+            panic!("expected subscribe packet");
+        };
+        let subscriptions = serde_json::from_str(packet.strip_prefix('4').unwrap()).unwrap();
+        (socket, subscriptions)
+    }
+
+    fn notification(version: u64) -> Message {
+        Message::text(format!(r#"4{{"type":"notification","key":"versions/b1:block","value":{}}}"#, version))
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_and_resubscribes_from_last_version() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/primus-v8/", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, subscriptions) = accept(&listener).await;
+            assert_eq!(subscriptions[0]["key"], json!("versions/b1:block"));
+            assert_eq!(subscriptions[0]["version"], json!(-1));
+            socket.send(notification(5)).await.unwrap();
+            drop(socket);
+
+            let (mut socket, subscriptions) = accept(&listener).await;
+            assert_eq!(subscriptions[0]["version"], json!(5));
+            socket.send(notification(5)).await.unwrap();
+            socket.send(notification(6)).await.unwrap();
+            // Keep the socket open until the client has read everything
+            let _ = socket.next().await;
+        });
+
+        let client = RealtimeClient::from_token("secret".to_string())
+            .with_url(&url)
+            .with_reconnect_delay(Duration::from_millis(10), Duration::from_millis(50));
+        let mut subscription = client.subscribe(&[RecordPointer::block("b1")]);
+
+        let first = subscription.next().await.unwrap().unwrap();
+        let second = subscription.next().await.unwrap().unwrap();
+        assert_eq!((first.version, second.version), (5, 6));
+        drop(subscription);
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,410 @@
+//! Interactive endpoint explorer
+//!
+//! Each line is either a request, `<endpoint> [json body]`, or a `:command`.
+//! Endpoints can be written as `getSpaces`, `GET_SPACES` or a full path, and
+//! known ones tab-complete. `$name` in a body is replaced with the JSON of a
+//! variable, so `:let page results.0.id` followed by
+//! `loadPageChunk {"pageId": $page}` chains one response into the next request.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use anyhow::{bail, Context as _, Result};
+use regex::{Captures, Regex};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use serde_json::Value;
+
+use crate::api::endpoints::paths;
+use crate::api::{normalize_request, scrub_response, NotionClient};
+use crate::fixture::{endpoint_name, Fixture};
+use crate::models::RecordMap;
+use crate::output::colored_json;
+use crate::utils::to_uuid_format;
+
+/// A `$name` variable reference in a request body
+static VARIABLE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$([A-Za-z_][A-Za-z0-9_]*)").unwrap());
+
+const COMMANDS: &[(&str, &str)] = &[
+    (":help", "Show this help"),
+    (":set", "<name> <value>  Set a variable to JSON (or a bare string)"),
+    (":let", "<name> <path>  Set a variable from the last response, e.g. results.0.id"),
+    (":vars", "List variables"),
+    (":records", "Tables and record counts in the last recordMap"),
+    (":get", "<id>  A record from the last recordMap"),
+    (":history", "Requests made this session"),
+    (":show", "<n>  Print response n again"),
+    (":save", "[n]  Save exchange n (default: last) as a fixture"),
+    (":quit", "Leave"),
+];
+
+/// A request made during the session
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub path: String,
+    pub status: u16,
+    pub request: Value,
+    pub response: Value,
+}
+
+/// What the caller should do after a line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Print(String),
+    Quit,
+}
+
+pub struct Repl {
+    client: NotionClient,
+    exchanges: Vec<Exchange>,
+    vars: BTreeMap<String, Value>,
+    fixture_dir: PathBuf,
+    color: bool,
+}
+
+impl Repl {
+    pub fn new(client: NotionClient) -> Self {
+        Self {
+            client,
+            exchanges: Vec::new(),
+            vars: BTreeMap::new(),
+            fixture_dir: PathBuf::from("fixtures"),
+            color: false,
+        }
+    }
+
+    /// Where `:save` writes fixtures (default: `fixtures`)
+    pub fn with_fixture_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.fixture_dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Print responses with ANSI colors
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn exchanges(&self) -> &[Exchange] {
+        &self.exchanges
+    }
+
+    pub fn var(&self, name: &str) -> Option<&Value> {
+        self.vars.get(name)
+    }
+
+    /// Read lines until `:quit` or end of input, keeping history in `history_file`
+    pub async fn run(mut self, history_file: Option<PathBuf>) -> Result<()> {
+        let mut editor: Editor<ReplHelper, _> = Editor::new()?;
+        editor.set_helper(Some(ReplHelper));
+        if let Some(path) = &history_file {
+            let _ = editor.load_history(path);
+        }
+        println!("Type :help for commands, Tab to complete endpoints");
+
+        loop {
+            let line = match tokio::task::block_in_place(|| editor.readline("notion> ")) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            editor.add_history_entry(line.as_str())?;
+            match self.execute(&line).await {
+                Ok(Reply::Print(text)) => println!("{}", text),
+                Ok(Reply::Quit) => break,
+                Err(e) => eprintln!("error: {:#}", e),
+            }
+        }
+
+        if let Some(path) = &history_file {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            editor.save_history(path)?;
+        }
+        Ok(())
+    }
+
+    /// Handle one line of input
+    pub async fn execute(&mut self, line: &str) -> Result<Reply> {
+        let line = line.trim();
+        let (head, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        if !head.starts_with(':') {
+            return self.request(head, rest).await;
+        }
+
+        let (first, second) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let second = second.trim();
+        let text = match head {
+            ":help" => COMMANDS
+                .iter()
+                .map(|(name, help)| format!("{:<9} {}", name, help))
+                .chain(["<endpoint> [json]  Call an endpoint; $name inserts a variable".to_string()])
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ":quit" | ":exit" => return Ok(Reply::Quit),
+            ":set" => {
+                if first.is_empty() || second.is_empty() {
+                    bail!("Usage: :set <name> <value>");
+                }
+                let value = serde_json::from_str(second).unwrap_or_else(|_| Value::String(second.to_string()));
+                self.vars.insert(first.to_string(), value);
+                String::new()
+            }
+            ":let" => {
+                if first.is_empty() || second.is_empty() {
+                    bail!("Usage: :let <name> <path>");
+                }
+                let last = self.last()?;
+                let pointer = if second.starts_with('/') { second.to_string() } else { format!("/{}", second.replace('.', "/")) };
+                let value = last
+                    .response
+                    .pointer(&pointer)
+                    .with_context(|| format!("Nothing at {} in the last response", second))?
+                    .clone();
+                self.vars.insert(first.to_string(), value.clone());
+                self.show(&value)
+            }
+            ":vars" => self
+                .vars
+                .iter()
+                .map(|(name, value)| format!("${} = {}", name, value))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ":records" => {
+                let records = self.last_records()?;
+                let mut lines: Vec<String> = records
+                    .tables()
+                    .filter(|(_, records)| !records.is_empty())
+                    .map(|(table, records)| format!("{:<16} {}", table, records.len()))
+                    .collect();
+                lines.sort();
+                lines.join("\n")
+            }
+            ":get" => {
+                if first.is_empty() {
+                    bail!("Usage: :get <id>");
+                }
+                let records = self.last_records()?;
+                let id = to_uuid_format(first).unwrap_or_else(|| first.to_string());
+                let value = records
+                    .tables()
+                    .find_map(|(_, records)| records.get(&id).or_else(|| records.get(first)))
+                    .with_context(|| format!("{} is not in the last recordMap", first))?
+                    .value
+                    .clone();
+                self.show(&value)
+            }
+            ":history" => self
+                .exchanges
+                .iter()
+                .enumerate()
+                .map(|(i, e)| format!("{:>3}  {}  {}", i + 1, e.path, e.request))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ":show" => {
+                let response = self.exchange(first)?.response.clone();
+                self.show(&response)
+            }
+            ":save" => {
+                let exchange = self.exchange(first)?;
+                let mut response = exchange.response.clone();
+                scrub_response(&mut response, &self.client.headers().token);
+                let endpoint = endpoint_name(&exchange.path)
+                    .with_context(|| format!("Cannot save non-v3 path {}", exchange.path))?;
+                let fixture = Fixture {
+                    endpoint: endpoint.to_string(),
+                    status: exchange.status,
+                    request: normalize_request(&exchange.request),
+                    response,
+                };
+                format!("Saved {}", fixture.save(&self.fixture_dir)?.display())
+            }
+            other => bail!("Unknown command {}; try :help", other),
+        };
+        Ok(Reply::Print(text))
+    }
+
+    async fn request(&mut self, endpoint: &str, body: &str) -> Result<Reply> {
+        let body = if body.is_empty() { "{}".to_string() } else { self.substitute(body)? };
+        let request: Value = serde_json::from_str(&body).context("Body is not valid JSON")?;
+        let path = paths::resolve(endpoint);
+
+        let (status, response) = self.client.post_raw(&path, &request).await?;
+        let response = serde_json::from_str(&response).unwrap_or(Value::String(response));
+        let text = if status.is_success() {
+            self.show(&response)
+        } else {
+            format!("{}\n{}", status, self.show(&response))
+        };
+        let status = status.as_u16();
+        self.exchanges.push(Exchange { path, status, request, response });
+        Ok(Reply::Print(text))
+    }
+
+    /// Replace every `$name` with the JSON of the variable
+    fn substitute(&self, body: &str) -> Result<String> {
+        let mut missing = None;
+        let text = VARIABLE.replace_all(body, |caps: &Captures| match self.vars.get(&caps[1]) {
+            Some(value) => value.to_string(),
+            None => {
+                missing.get_or_insert_with(|| caps[1].to_string());
+                String::new()
+            }
+        });
+        if let Some(name) = missing {
+            bail!("Unknown variable ${}", name);
+        }
+        Ok(text.into_owned())
+    }
+
+    fn show(&self, value: &Value) -> String {
+        if self.color {
+            colored_json(value)
+        } else {
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        }
+    }
+
+    fn last(&self) -> Result<&Exchange> {
+        self.exchanges.last().context("No request made yet")
+    }
+
+    /// Exchange by 1-based number, or the last one
+    fn exchange(&self, number: &str) -> Result<&Exchange> {
+        if number.is_empty() {
+            return self.last();
+        }
+        let n: usize = number.parse().with_context(|| format!("'{}' is not a number", number))?;
+        n.checked_sub(1)
+            .and_then(|i| self.exchanges.get(i))
+            .with_context(|| format!("No request {}", n))
+    }
+
+    fn last_records(&self) -> Result<RecordMap> {
+        let records = self.last()?.response.get("recordMap").context("The last response has no recordMap")?;
+        serde_json::from_value(records.clone()).context("Unexpected recordMap shape")
+    }
+}
+
+/// Tab completion of commands and endpoint names
+struct ReplHelper;
+
+impl ReplHelper {
+    fn candidates(word: &str) -> Vec<String> {
+        let endpoints = paths::ALL
+            .iter()
+            .filter_map(|(_, path)| path.strip_prefix("/v3/"))
+            .map(str::to_string);
+        let constants = paths::ALL.iter().map(|(name, _)| name.to_string());
+        let commands = COMMANDS.iter().map(|(name, _)| name.to_string());
+        let mut matches: Vec<String> = commands.chain(endpoints).chain(constants).filter(|c| c.starts_with(word)).collect();
+        matches.sort();
+        matches.dedup();
+        matches
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        // Only the first word names an endpoint or command
+        if before.contains(char::is_whitespace) {
+            return Ok((pos, Vec::new()));
+        }
+        let pairs = Self::candidates(before)
+            .into_iter()
+            .map(|c| Pair {
+                display: c.clone(),
+                replacement: c,
+            })
+            .collect();
+        Ok((0, pairs))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::models::Record;
+    use serde_json::json;
+
+    #[test]
+    fn test_completion_candidates() {
+        assert_eq!(ReplHelper::candidates("getSnap"), vec!["getSnapshotContents", "getSnapshotsList"]);
+        assert_eq!(ReplHelper::candidates(":sa"), vec![":save"]);
+    }
+
+    #[tokio::test]
+    async fn test_variables_navigation_and_fixtures() {
+        let mut records = RecordMap::default();
+        records.block.insert("b1".into(), Record::new(json!({ "id": "b1", "type": "text" })));
+        let emulator = Emulator::new("secret").with_records(records).start().await.unwrap();
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let mut repl = Repl::new(emulator.client()).with_fixture_dir(dir);
+
+        repl.execute(":set id \"b1\"").await.unwrap();
+        repl.execute(r#"getRecordValues {"requests": [{"table": "block", "id": $id}]}"#)
+            .await
+            .unwrap();
+        repl.execute(":let kind results.0.value.type").await.unwrap();
+        assert_eq!(repl.var("kind"), Some(&json!("text")));
+        assert!(repl.execute("getRecordValues {\"requests\": $nope}").await.is_err());
+
+        repl.execute(r#"syncRecordValues {"requests": [{"pointer": {"table": "block", "id": "b1"}, "version": -1}]}"#)
+            .await
+            .unwrap();
+        let Reply::Print(block) = repl.execute(":get b1").await.unwrap() else {
+            panic!("expected output");
+        };
+        assert!(block.contains("\"type\": \"text\""));
+
+        let Reply::Print(saved) = repl.execute(":save 1").await.unwrap() else {
+            panic!("expected output");
+        };
+        assert!(saved.contains("getRecordValues"));
+        assert_eq!(crate::fixture::load_dir(dir).unwrap().len(), 1);
+        assert_eq!(repl.execute(":quit").await.unwrap(), Reply::Quit);
+    }
+
+    #[tokio::test]
+    async fn test_error_responses_are_kept() {
+        let emulator = Emulator::new("secret").start().await.unwrap();
+        let tmp = tempfile::tempdir().unwrap();
+        let mut repl = Repl::new(emulator.client()).with_fixture_dir(tmp.path());
+
+        let Reply::Print(text) = repl.execute(r#"getRecordValues {"requests": 5}"#).await.unwrap() else {
+            panic!("expected output");
+        };
+        assert!(text.starts_with("400"));
+        assert_eq!(repl.exchanges()[0].status, 400);
+
+        repl.execute(":save").await.unwrap();
+        let fixtures = crate::fixture::load_dir(tmp.path()).unwrap();
+        assert_eq!(fixtures[0].status, 400);
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+
+use super::{ObjectSchema, Schema};
+
+/// Strict and reserved keywords of the 2021 and 2024 editions
+const KEYWORDS: [&str; 51] = [
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate", "do", "dyn", "else",
+    "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "macro", "match", "mod",
+    "move", "mut", "override", "priv", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+    "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while",
+];
+
+/// Keywords that cannot be raw identifiers, so get a trailing `_` instead
+const NOT_RAW: [&str; 4] = ["crate", "self", "Self", "super"];
+
+struct Generator {
+    /// Generated structs in order, with the schema each name stands for
+    structs: Vec<(String, ObjectSchema, String)>,
+}
+
+/// Emit serde structs for `schema`, the root struct named `root`
+///
+/// Objects become structs, id-keyed maps become `HashMap<String, _>`, and
+/// fields missing from some samples or seen as null become `Option`. Unions
+/// have no faithful struct form and fall back to `serde_json::Value`.
+pub fn generate_rust(root: &str, schema: &Schema) -> String {
+    let mut gen = Generator { structs: Vec::new() };
+    let root_type = gen.rust_type(schema, &pascal_case(root));
+
+    let mut out = String::from("// Generated from captured responses; edit the generator, not this file\n\n");
+    out.push_str("use std::collections::HashMap;\n\nuse serde::{Deserialize, Serialize};\n");
+    if gen.structs.is_empty() {
+        let _ = write!(out, "\npub type {} = {};\n", pascal_case(root), root_type);
+    }
+    for (_, _, body) in &gen.structs {
+        out.push('\n');
+        out.push_str(body);
+    }
+    out
+}
+
+impl Generator {
+    fn rust_type(&mut self, schema: &Schema, hint: &str) -> String {
+        match schema {
+            Schema::Unknown | Schema::Null => "serde_json::Value".to_string(),
+            Schema::Union { .. } => "serde_json::Value".to_string(),
+            Schema::Bool => "bool".to_string(),
+            Schema::Integer => "i64".to_string(),
+            Schema::Number => "f64".to_string(),
+            Schema::String => "String".to_string(),
+            Schema::Nullable { inner } => format!("Option<{}>", self.rust_type(inner, hint)),
+            Schema::Array { items } => format!("Vec<{}>", self.rust_type(items, &format!("{}Item", hint))),
+            Schema::Map { values } => format!("HashMap<String, {}>", self.rust_type(values, &format!("{}Entry", hint))),
+            Schema::Object(obj) => self.object(obj, hint),
+        }
+    }
+
+    /// Name of the struct for `obj`, generating it unless an identical one exists
+    fn object(&mut self, obj: &ObjectSchema, hint: &str) -> String {
+        if let Some((name, _, _)) = self.structs.iter().find(|(n, o, _)| n.starts_with(hint) && same_shape(o, obj)) {
+            return name.clone();
+        }
+        let mut name = hint.to_string();
+        let mut n = 2;
+        while self.structs.iter().any(|(existing, _, _)| *existing == name) {
+            name = format!("{}{}", hint, n);
+            n += 1;
+        }
+
+        // Reserve the name before recursing so nested structs come after it
+        let index = self.structs.len();
+        self.structs.push((name.clone(), obj.clone(), String::new()));
+
+        let mut body = String::new();
+        let _ = writeln!(body, "#[derive(Debug, Clone, Serialize, Deserialize)]");
+        let _ = writeln!(body, "pub struct {} {{", name);
+        let mut idents = HashSet::new();
+        for (field, info) in &obj.fields {
+            // Keys like `spaceId` and `space_id` map to the same identifier
+            let base = field_ident(field);
+            let mut ident = base.clone();
+            let mut n = 2;
+            while !idents.insert(ident.clone()) {
+                ident = format!("{}_{}", base, n);
+                n += 1;
+            }
+            let mut ty = match &info.schema {
+                Schema::Nullable { inner } => self.rust_type(inner, &format!("{}{}", name, pascal_case(field))),
+                other => self.rust_type(other, &pascal_case(field)),
+            };
+            let optional = obj.is_optional(field);
+            if optional && !ty.starts_with("Option<") {
+                ty = format!("Option<{}>", ty);
+            }
+
+            let mut attrs = Vec::new();
+            if ident.trim_start_matches("r#") != field {
+                attrs.push(format!("rename = \"{}\"", field));
+            }
+            if optional {
+                attrs.push("default".to_string());
+            }
+            if let Schema::Union { variants } = &info.schema {
+                let kinds: Vec<String> = variants.iter().map(kind_name).collect();
+                let _ = writeln!(body, "    /// Seen as: {}", kinds.join(", "));
+            }
+            if !attrs.is_empty() {
+                let _ = writeln!(body, "    #[serde({})]", attrs.join(", "));
+            }
+            let _ = writeln!(body, "    pub {}: {},", ident, ty);
+        }
+        body.push_str("}\n");
+
+        self.structs[index].2 = body;
+        name
+    }
+}
+
+fn same_shape(a: &ObjectSchema, b: &ObjectSchema) -> bool {
+    let shape = |o: &ObjectSchema| -> BTreeMap<String, (Schema, bool)> {
+        o.fields
+            .iter()
+            .map(|(k, f)| (k.clone(), (f.schema.clone(), o.is_optional(k))))
+            .collect()
+    };
+    shape(a) == shape(b)
+}
+
+fn kind_name(schema: &Schema) -> String {
+    serde_json::to_value(schema)
+        .ok()
+        .and_then(|v| v.get("kind").and_then(|k| k.as_str()).map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn pascal_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut upper = true;
+    for c in name.chars() {
+        if !c.is_alphanumeric() {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    if out.chars().next().is_none_or(|c| c.is_ascii_digit()) {
+        out.insert(0, 'T');
+    }
+    if out == "Self" {
+        out.push('_');
+    }
+    out
+}
+
+fn field_ident(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 && !out.ends_with('_') {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else if c.is_alphanumeric() || c == '_' {
+            out.push(c);
+        } else if !out.ends_with('_') {
+            out.push('_');
+        }
+    }
+    if out.is_empty() || out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    if out == "_" || NOT_RAW.contains(&out.as_str()) {
+        out.push('_');
+    } else if KEYWORDS.contains(&out.as_str()) {
+        out.insert_str(0, "r#");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_generate_record_map_structs() {
+        let samples = [
+            json!({ "recordMap": { "block": { "11111111-1111-1111-1111-111111111111": { "role": "editor", "value": { "type": "page", "version": 3 } } } }, "cursor": { "stack": [] } }),
+            json!({ "recordMap": { "block": { "22222222-2222-2222-2222-222222222222": { "role": "reader", "value": { "type": "text", "version": 1, "content": ["a"] } } } } }),
+        ];
+        let schema = samples.iter().map(Schema::infer).fold(Schema::Unknown, Schema::merge);
+        let code = generate_rust("load_page_chunk_response", &schema);
+
+        assert!(code.contains("pub struct LoadPageChunkResponse {"));
+        assert!(code.contains("    #[serde(rename = \"recordMap\")]\n    pub record_map: RecordMap,"));
+        assert!(code.contains("    #[serde(default)]\n    pub cursor: Option<Cursor>,"));
+        assert!(code.contains("pub block: HashMap<String, BlockEntry>,"));
+        assert!(code.contains("pub r#type: String,"));
+        assert!(code.contains("    #[serde(default)]\n    pub content: Option<Vec<String>>,"));
+        assert!(code.contains("pub stack: Vec<serde_json::Value>,"));
+    }
+
+    #[test]
+    fn test_union_falls_back_to_value() {
+        let schema = [json!({ "v": 1 }), json!({ "v": "x" })]
+            .iter()
+            .map(Schema::infer)
+            .fold(Schema::Unknown, Schema::merge);
+        let code = generate_rust("Thing", &schema);
+        assert!(code.contains("    /// Seen as: integer, string\n    pub v: serde_json::Value,"));
+    }
+
+    #[test]
+    fn test_generated_code_parses() {
+        let mut sample = serde_json::Map::new();
+        for key in KEYWORDS.iter().chain(["spaceId", "space_id", "space-id", "_", "", "9"].iter()) {
+            sample.insert(key.to_string(), json!({ "self": 1, "Self": "x" }));
+        }
+        let code = generate_rust("crate", &Schema::infer(&Value::Object(sample)));
+        if let Err(e) = syn::parse_file(&code) {
+            panic!("{}\n{}", e, code);
+        }
+        assert!(code.contains("    #[serde(rename = \"crate\")]\n    pub crate_: "));
+        for ident in ["space_id", "space_id_2", "space_id_3"] {
+            assert!(code.contains(&format!("    pub {}: ", ident)), "{} missing", ident);
+        }
+        assert!(code.contains("    pub r#where: "));
+    }
+
+    #[test]
+    fn test_identifiers() {
+        assert_eq!(field_ident("spaceId"), "space_id");
+        assert_eq!(field_ident("type"), "r#type");
+        assert_eq!(field_ident("1st-place"), "_1st_place");
+        assert_eq!(field_ident("crate"), "crate_");
+        assert_eq!(field_ident("Self"), "self_");
+        assert_eq!(field_ident("-"), "__");
+        assert_eq!(pascal_case("notion_user"), "NotionUser");
+        assert_eq!(pascal_case("self"), "Self_");
+    }
+}
@@ -0,0 +1,232 @@
+//! JSON schema inference from captured responses
+//!
+//! Feed many responses of one endpoint through `Schema::infer` and `merge`
+//! to learn which fields are optional, where values disagree in type, and
+//! which objects are really maps keyed by record id (the `recordMap` tables
+//! are the classic case). `codegen` turns the merged schema into serde structs.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::fixture::Fixture;
+use crate::utils::to_uuid_format;
+
+mod codegen;
+
+pub use codegen::generate_rust;
+
+/// Inferred shape of a JSON value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Schema {
+    /// Nothing observed yet, e.g. the items of an always-empty array
+    Unknown,
+    Null,
+    Bool,
+    Integer,
+    Number,
+    String,
+    Array { items: Box<Schema> },
+    Object(ObjectSchema),
+    /// Object whose keys are record ids, all values sharing one schema
+    Map { values: Box<Schema> },
+    /// Values seen with different types; never contains `Null` or another union
+    Union { variants: Vec<Schema> },
+    /// Value that was sometimes null
+    Nullable { inner: Box<Schema> },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ObjectSchema {
+    /// How many objects were merged into this schema
+    pub samples: usize,
+    pub fields: BTreeMap<String, Field>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    /// How many of the merged objects had this field
+    pub present: usize,
+    pub schema: Schema,
+}
+
+impl ObjectSchema {
+    /// A field is optional if some samples lacked it or it was ever null
+    pub fn is_optional(&self, name: &str) -> bool {
+        self.fields
+            .get(name)
+            .is_some_and(|f| f.present < self.samples || matches!(f.schema, Schema::Nullable { .. } | Schema::Null))
+    }
+}
+
+impl Schema {
+    pub fn infer(value: &Value) -> Schema {
+        match value {
+            Value::Null => Schema::Null,
+            Value::Bool(_) => Schema::Bool,
+            Value::Number(n) if n.is_i64() || n.is_u64() => Schema::Integer,
+            Value::Number(_) => Schema::Number,
+            Value::String(_) => Schema::String,
+            Value::Array(items) => Schema::Array {
+                items: Box::new(items.iter().map(Schema::infer).fold(Schema::Unknown, Schema::merge)),
+            },
+            Value::Object(map) if !map.is_empty() && map.keys().all(|k| looks_like_id(k)) => Schema::Map {
+                values: Box::new(map.values().map(Schema::infer).fold(Schema::Unknown, Schema::merge)),
+            },
+            Value::Object(map) => Schema::Object(ObjectSchema {
+                samples: 1,
+                fields: map
+                    .iter()
+                    .map(|(k, v)| {
+                        (
+                            k.clone(),
+                            Field {
+                                present: 1,
+                                schema: Schema::infer(v),
+                            },
+                        )
+                    })
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Combine two observations of the same position
+    pub fn merge(self, other: Schema) -> Schema {
+        use Schema::*;
+
+        match (self, other) {
+            (Unknown, s) | (s, Unknown) => s,
+            (Null, Null) => Null,
+            (Null, Nullable { inner }) | (Nullable { inner }, Null) => Nullable { inner },
+            (Null, s) | (s, Null) => Nullable { inner: Box::new(s) },
+            (Nullable { inner: a }, Nullable { inner: b }) => Nullable { inner: Box::new(a.merge(*b)) },
+            (Nullable { inner }, s) | (s, Nullable { inner }) => Nullable { inner: Box::new(inner.merge(s)) },
+            (Integer, Number) | (Number, Integer) => Number,
+            (Array { items: a }, Array { items: b }) => Array { items: Box::new(a.merge(*b)) },
+            (Map { values: a }, Map { values: b }) => Map { values: Box::new(a.merge(*b)) },
+            // An empty object next to a map is just a map with no entries
+            (Map { values }, Object(o)) | (Object(o), Map { values }) if o.fields.is_empty() => Map { values },
+            (Object(a), Object(b)) => Object(merge_objects(a, b)),
+            (Union { variants }, s) | (s, Union { variants }) => {
+                let mut merged = variants;
+                add_variant(&mut merged, s);
+                Union { variants: merged }
+            }
+            (a, b) if a == b => a,
+            (a, b) => {
+                let mut variants = vec![a];
+                add_variant(&mut variants, b);
+                Union { variants }
+            }
+        }
+    }
+}
+
+fn merge_objects(mut a: ObjectSchema, b: ObjectSchema) -> ObjectSchema {
+    a.samples += b.samples;
+    for (name, field) in b.fields {
+        match a.fields.remove(&name) {
+            Some(existing) => {
+                a.fields.insert(
+                    name,
+                    Field {
+                        present: existing.present + field.present,
+                        schema: existing.schema.merge(field.schema),
+                    },
+                );
+            }
+            None => {
+                a.fields.insert(name, field);
+            }
+        }
+    }
+    a
+}
+
+/// Add `s` to a union, merging it into a variant of the same kind if there is one
+fn add_variant(variants: &mut Vec<Schema>, s: Schema) {
+    if let Schema::Union { variants: nested } = s {
+        for v in nested {
+            add_variant(variants, v);
+        }
+        return;
+    }
+    let same_kind = variants
+        .iter()
+        .position(|v| std::mem::discriminant(v) == std::mem::discriminant(&s) || matches!((v, &s), (Schema::Integer, Schema::Number) | (Schema::Number, Schema::Integer)));
+    match same_kind {
+        Some(i) => {
+            let existing = variants.remove(i);
+            variants.insert(i, existing.merge(s));
+        }
+        None => variants.push(s),
+    }
+}
+
+fn looks_like_id(key: &str) -> bool {
+    to_uuid_format(key).is_some()
+}
+
+/// Merged schema of every successful response captured for `endpoint`
+pub fn infer_endpoint(fixtures: &[Fixture], endpoint: &str) -> Schema {
+    fixtures
+        .iter()
+        .filter(|f| f.endpoint == endpoint && (200..300).contains(&f.status))
+        .map(|f| Schema::infer(&f.response))
+        .fold(Schema::Unknown, Schema::merge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ID_A: &str = "11111111-1111-1111-1111-111111111111";
+    const ID_B: &str = "22222222-2222-2222-2222-222222222222";
+
+    fn merged(values: &[Value]) -> Schema {
+        values.iter().map(Schema::infer).fold(Schema::Unknown, Schema::merge)
+    }
+
+    #[test]
+    fn test_optional_fields() {
+        let schema = merged(&[json!({ "id": "a", "name": "x" }), json!({ "id": "b", "icon": null })]);
+        let Schema::Object(obj) = schema else { panic!("expected object") };
+        assert_eq!(obj.samples, 2);
+        assert!(!obj.is_optional("id"));
+        assert!(obj.is_optional("name"));
+        assert!(obj.is_optional("icon"));
+    }
+
+    #[test]
+    fn test_maps_with_id_keys() {
+        let schema = merged(&[
+            json!({ "block": { ID_A: { "value": { "version": 1 } } } }),
+            json!({ "block": { ID_B: { "value": { "version": 2, "alive": true } } } }),
+        ]);
+        let Schema::Object(obj) = schema else { panic!("expected object") };
+        let Schema::Map { values } = &obj.fields["block"].schema else { panic!("expected map") };
+        let Schema::Object(entry) = values.as_ref() else { panic!("expected object values") };
+        let Schema::Object(value) = &entry.fields["value"].schema else { panic!() };
+        assert!(value.is_optional("alive"));
+        assert!(!value.is_optional("version"));
+    }
+
+    #[test]
+    fn test_unions_and_numbers() {
+        assert_eq!(merged(&[json!(1), json!(2.5)]), Schema::Number);
+        assert_eq!(
+            merged(&[json!("a"), json!(1), json!(null), json!(2.0)]),
+            Schema::Nullable {
+                inner: Box::new(Schema::Union { variants: vec![Schema::String, Schema::Number] })
+            }
+        );
+        assert_eq!(
+            merged(&[json!([]), json!([1])]),
+            Schema::Array { items: Box::new(Schema::Integer) }
+        );
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::debug;
+
+use crate::models::{plain_text, RecordMap, SearchResult};
+
+/// Title matches count this much more than body matches
+const TITLE_BOOST: f64 = 3.0;
+const SNIPPET_CHARS: usize = 80;
+/// Positions skipped between title and body, so phrases never span the two
+const BODY_GAP: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Document {
+    version: u64,
+    title: String,
+    text: String,
+    parent_id: Option<String>,
+    /// Number of leading tokens that come from the title
+    title_tokens: u32,
+    tokens: u32,
+    /// Distinct terms, so removal only touches this document's postings
+    terms: Vec<String>,
+}
+
+/// A ranked hit from the local index
+#[derive(Debug, Clone)]
+pub struct IndexHit {
+    pub result: SearchResult,
+    pub score: f64,
+    /// Titles of the ancestors, outermost first
+    pub breadcrumbs: Vec<String>,
+}
+
+/// On-disk inverted index over block titles, text and database properties
+///
+/// Blocks are re-indexed only when their `version` changes, so feeding every
+/// freshly loaded `RecordMap` through `update` is cheap.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    documents: HashMap<String, Document>,
+    /// term -> block id -> token positions
+    postings: BTreeMap<String, HashMap<String, Vec<u32>>>,
+}
+
+enum Clause {
+    Term(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+}
+
+impl SearchIndex {
+    /// Load the index stored at `path`, or start an empty one
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut index: SearchIndex = if path.exists() {
+            let data = fs::read(&path).with_context(|| format!("Failed to read index {}", path.display()))?;
+            serde_json::from_slice(&data).context("Corrupt search index")?
+        } else {
+            SearchIndex::default()
+        };
+        index.path = Some(path);
+        Ok(index)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = self.path.as_ref().context("Index was not opened from a file")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Index new or changed blocks and drop archived ones; returns how many changed
+    pub fn update(&mut self, records: &RecordMap) -> usize {
+        let mut changed = 0;
+        for (id, record) in &records.block {
+            let value = &record.value;
+            let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+            let alive = value.get("alive").and_then(Value::as_bool).unwrap_or(true);
+
+            if !alive || value.is_null() {
+                if self.documents.contains_key(id) {
+                    self.remove(id);
+                    changed += 1;
+                }
+                continue;
+            }
+            if self.documents.get(id).is_some_and(|d| d.version == version) {
+                continue;
+            }
+
+            self.remove(id);
+            self.insert(id, value, version);
+            changed += 1;
+        }
+        debug!("Search index updated {} block(s)", changed);
+        changed
+    }
+
+    fn insert(&mut self, id: &str, value: &Value, version: u64) {
+        let properties = value.get("properties").and_then(Value::as_object);
+        let title = properties.and_then(|p| p.get("title")).map(plain_text).unwrap_or_default();
+        let body: Vec<String> = properties
+            .into_iter()
+            .flatten()
+            .filter(|(key, _)| key.as_str() != "title")
+            .map(|(_, v)| plain_text(v))
+            .filter(|t| !t.is_empty())
+            .collect();
+        let text = body.join(" ");
+
+        let title_tokens = tokenize(&title);
+        let body_tokens = tokenize(&text);
+        let body_start = title_tokens.len() as u32 + BODY_GAP;
+        let positions = (0..).zip(&title_tokens).chain((body_start..).zip(&body_tokens));
+        let mut terms = HashSet::new();
+        for (pos, term) in positions {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .entry(id.to_string())
+                .or_default()
+                .push(pos);
+            terms.insert(term.clone());
+        }
+
+        self.documents.insert(
+            id.to_string(),
+            Document {
+                version,
+                title,
+                text,
+                parent_id: value.get("parent_id").and_then(Value::as_str).map(str::to_string),
+                title_tokens: title_tokens.len() as u32,
+                tokens: (title_tokens.len() + body_tokens.len()) as u32,
+                terms: terms.into_iter().collect(),
+            },
+        );
+    }
+
+    fn remove(&mut self, id: &str) {
+        let Some(doc) = self.documents.remove(id) else {
+            return;
+        };
+        for term in &doc.terms {
+            if let Some(docs) = self.postings.get_mut(term) {
+                docs.remove(id);
+                if docs.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+    }
+
+    /// Search with plain terms (all must match), `"exact phrases"` and `prefix*`
+    pub fn search(&self, query: &str, limit: usize) -> Vec<IndexHit> {
+        let clauses = parse_query(query);
+        if clauses.is_empty() {
+            return Vec::new();
+        }
+
+        let mut scores: Option<HashMap<&str, f64>> = None;
+        for clause in &clauses {
+            let matches = self.match_clause(clause);
+            scores = Some(match scores {
+                None => matches,
+                Some(prev) => prev
+                    .into_iter()
+                    .filter_map(|(id, score)| matches.get(id).map(|s| (id, score + s)))
+                    .collect(),
+            });
+        }
+
+        let mut ranked: Vec<(&str, f64)> = scores.unwrap_or_default().into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        ranked
+            .into_iter()
+            .take(limit)
+            .map(|(id, score)| self.hit(id, score, &clauses))
+            .collect()
+    }
+
+    /// Score every document matching a clause
+    fn match_clause(&self, clause: &Clause) -> HashMap<&str, f64> {
+        let mut scores: HashMap<&str, f64> = HashMap::new();
+        match clause {
+            Clause::Term(term) => self.score_term(term, &mut scores),
+            Clause::Prefix(prefix) => {
+                for term in self.postings.range(prefix.clone()..).map(|(t, _)| t).take_while(|t| t.starts_with(prefix.as_str())) {
+                    self.score_term(term, &mut scores);
+                }
+            }
+            Clause::Phrase(words) => {
+                let Some(first) = words.first().and_then(|w| self.postings.get(w)) else {
+                    return scores;
+                };
+                for (id, starts) in first {
+                    let doc = &self.documents[id];
+                    let mut hits = 0.0;
+                    for &start in starts {
+                        let whole = words.iter().enumerate().skip(1).all(|(offset, word)| {
+                            self.postings
+                                .get(word)
+                                .and_then(|docs| docs.get(id))
+                                .is_some_and(|pos| pos.contains(&(start + offset as u32)))
+                        });
+                        if whole {
+                            hits += if start < doc.title_tokens { TITLE_BOOST } else { 1.0 };
+                        }
+                    }
+                    if hits > 0.0 {
+                        let idf = self.idf(first.len());
+                        scores.insert(id.as_str(), hits * idf * words.len() as f64 / doc.tokens.max(1) as f64);
+                    }
+                }
+            }
+        }
+        scores
+    }
+
+    fn score_term<'a>(&'a self, term: &str, scores: &mut HashMap<&'a str, f64>) {
+        let Some(docs) = self.postings.get(term) else {
+            return;
+        };
+        let idf = self.idf(docs.len());
+        for (id, positions) in docs {
+            let doc = &self.documents[id];
+            let weighted: f64 = positions
+                .iter()
+                .map(|&p| if p < doc.title_tokens { TITLE_BOOST } else { 1.0 })
+                .sum();
+            *scores.entry(id.as_str()).or_default() += weighted * idf / doc.tokens.max(1) as f64;
+        }
+    }
+
+    fn idf(&self, doc_freq: usize) -> f64 {
+        (1.0 + self.documents.len() as f64 / doc_freq.max(1) as f64).ln()
+    }
+
+    fn hit(&self, id: &str, score: f64, clauses: &[Clause]) -> IndexHit {
+        let doc = &self.documents[id];
+        IndexHit {
+            result: SearchResult {
+                id: id.to_string(),
+                title: doc.title.clone(),
+                highlight: snippet(&doc.text, clauses),
+                snapshot: None,
+                block_id: Some(id.to_string()),
+                parent_id: doc.parent_id.clone(),
+            },
+            score,
+            breadcrumbs: self.breadcrumbs(id),
+        }
+    }
+
+    /// Titles of indexed ancestors, outermost first
+    pub fn breadcrumbs(&self, id: &str) -> Vec<String> {
+        let mut crumbs = Vec::new();
+        let mut visited = HashSet::from([id.to_string()]);
+        let mut current = self.documents.get(id).and_then(|d| d.parent_id.clone());
+        while let Some(parent) = current {
+            if !visited.insert(parent.clone()) {
+                break;
+            }
+            let Some(doc) = self.documents.get(&parent) else {
+                break;
+            };
+            if !doc.title.is_empty() {
+                crumbs.push(doc.title.clone());
+            }
+            current = doc.parent_id.clone();
+        }
+        crumbs.reverse();
+        crumbs
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn parse_query(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    for (i, part) in query.split('"').enumerate() {
+        if i % 2 == 1 {
+            let words = tokenize(part);
+            match words.len() {
+                0 => {}
+                1 => clauses.push(Clause::Term(words[0].clone())),
+                _ => clauses.push(Clause::Phrase(words)),
+            }
+            continue;
+        }
+        for word in part.split_whitespace() {
+            let prefix = word.ends_with('*');
+            for token in tokenize(word) {
+                clauses.push(if prefix { Clause::Prefix(token) } else { Clause::Term(token) });
+            }
+        }
+    }
+    clauses
+}
+
+/// Short excerpt of `text` around the first matching word
+fn snippet(text: &str, clauses: &[Clause]) -> Option<String> {
+    if text.is_empty() {
+        return None;
+    }
+    let lower = text.to_lowercase();
+    let needle = clauses.iter().find_map(|c| match c {
+        Clause::Term(t) | Clause::Prefix(t) => lower.find(t.as_str()),
+        Clause::Phrase(words) => lower.find(words[0].as_str()),
+    });
+
+    let start = needle.unwrap_or(0);
+    let from = lower[..start].char_indices().rev().nth(SNIPPET_CHARS / 4).map(|(i, _)| i).unwrap_or(0);
+    // Byte offsets come from the lowercased copy, which can differ in length
+    let excerpt: String = text.get(from..).unwrap_or(text).chars().take(SNIPPET_CHARS).collect();
+    Some(excerpt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Record;
+    use serde_json::json;
+
+    fn block(records: &mut RecordMap, id: &str, parent: &str, version: u64, title: &str, extra: Value) {
+        let mut properties = json!({ "title": [[title]] });
+        if let Some(obj) = extra.as_object() {
+            for (k, v) in obj {
+                properties[k] = v.clone();
+            }
+        }
+        records.block.insert(
+            id.to_string(),
+            Record::new(json!({ "id": id, "version": version, "alive": true, "parent_id": parent, "properties": properties })),
+        );
+    }
+
+    fn records() -> RecordMap {
+        let mut records = RecordMap::default();
+        block(&mut records, "root", "space", 1, "Engineering", json!({}));
+        block(&mut records, "rfc", "root", 1, "Release process", json!({ "abc": [["Owner: platform team"]] }));
+        block(&mut records, "notes", "rfc", 1, "Meeting notes", json!({ "def": [["we discussed the release freeze"]] }));
+        records
+    }
+
+    #[test]
+    fn test_terms_rank_titles_first_with_breadcrumbs() {
+        let mut index = SearchIndex::default();
+        assert_eq!(index.update(&records()), 3);
+
+        let hits = index.search("release", 10);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].result.id, "rfc");
+        assert_eq!(hits[1].breadcrumbs, vec!["Engineering", "Release process"]);
+        assert!(hits[1].result.highlight.as_deref().unwrap().contains("release freeze"));
+
+        assert_eq!(index.search("platform team", 10).len(), 1);
+        assert!(index.search("platform freeze", 10).is_empty());
+    }
+
+    #[test]
+    fn test_phrase_and_prefix_queries() {
+        let mut index = SearchIndex::default();
+        index.update(&records());
+
+        let phrase = index.search("\"release freeze\"", 10);
+        assert_eq!(phrase.len(), 1);
+        assert_eq!(phrase[0].result.id, "notes");
+        assert!(index.search("\"freeze release\"", 10).is_empty());
+        // Title "Meeting notes" ends where body "we discussed..." starts
+        assert!(index.search("\"notes we\"", 10).is_empty());
+
+        let prefix = index.search("engin*", 10);
+        assert_eq!(prefix[0].result.title, "Engineering");
+    }
+
+    #[test]
+    fn test_incremental_update_by_version() {
+        let mut index = SearchIndex::default();
+        let mut records = records();
+        index.update(&records);
+        assert_eq!(index.update(&records), 0);
+
+        block(&mut records, "rfc", "root", 2, "Deploy process", json!({}));
+        records.block.get_mut("notes").unwrap().value["alive"] = json!(false);
+        assert_eq!(index.update(&records), 2);
+        assert!(index.search("release", 10).is_empty());
+        assert_eq!(index.search("deploy", 10)[0].result.id, "rfc");
+        assert_eq!(index.len(), 2);
+        assert!(!index.postings.contains_key("meeting"));
+        assert!(!index.postings.contains_key("release"));
+    }
+
+    #[test]
+    fn test_save_and_open() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("index.json");
+        let mut index = SearchIndex::open(&path).unwrap();
+        index.update(&records());
+        index.save().unwrap();
+
+        let reopened = SearchIndex::open(&path).unwrap();
+        assert_eq!(reopened.len(), 3);
+        assert_eq!(reopened.search("meeting", 1)[0].result.id, "notes");
+    }
+}
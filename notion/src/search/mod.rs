@@ -0,0 +1,156 @@
+//! Search helpers: a paging stream over the `search` endpoint and a local index
+
+use std::collections::{HashSet, VecDeque};
+
+use anyhow::Result;
+use futures::stream::{self, Stream};
+use serde_json::Value;
+use tracing::debug;
+
+use crate::api::endpoints::{paths, SearchRequest};
+use crate::api::NotionClient;
+use crate::models::{plain_text, RecordMap, SearchHit, SearchResponse, SearchResult};
+
+mod index;
+
+pub use index::{IndexHit, SearchIndex};
+
+const DEFAULT_PAGE_SIZE: u32 = 20;
+
+/// Turn a raw hit into a `SearchResult`, using the record map for title and parent
+pub fn to_search_result(hit: &SearchHit, records: &RecordMap) -> SearchResult {
+    let block = records.get("block", &hit.id);
+    let title = block
+        .and_then(|b| b.get("properties"))
+        .and_then(|p| p.get("title"))
+        .map(plain_text)
+        .or_else(|| hit.highlight.as_ref().and_then(|h| h.title.clone()))
+        .unwrap_or_default();
+
+    SearchResult {
+        id: hit.id.clone(),
+        title,
+        highlight: hit.highlight.as_ref().and_then(|h| h.text.clone()),
+        snapshot: None,
+        block_id: Some(hit.id.clone()),
+        parent_id: block
+            .and_then(|b| b.get("parent_id"))
+            .and_then(Value::as_str)
+            .map(str::to_string),
+    }
+}
+
+struct Pager {
+    client: NotionClient,
+    request: SearchRequest,
+    page_size: u32,
+    seen: HashSet<String>,
+    buffer: VecDeque<SearchResult>,
+    done: bool,
+}
+
+impl Pager {
+    /// Ask again with a larger limit and buffer the hits not seen yet
+    ///
+    /// Like the web app, search has no cursor; each page re-requests the
+    /// results so far plus one more page.
+    async fn fetch(&mut self) -> Result<()> {
+        let limit = self.request.limit.unwrap_or(0) + self.page_size;
+        self.request.limit = Some(limit);
+        let response: SearchResponse = self.client.post_json(paths::SEARCH, &self.request).await?;
+
+        let fresh: Vec<&SearchHit> = response.results.iter().filter(|h| self.seen.insert(h.id.clone())).collect();
+        debug!("search returned {} hits, {} new", response.results.len(), fresh.len());
+
+        let reached_total = response.total.is_some_and(|total| self.seen.len() as u32 >= total);
+        self.done = fresh.is_empty() || (response.results.len() as u32) < limit || reached_total;
+        self.buffer
+            .extend(fresh.into_iter().map(|hit| to_search_result(hit, &response.record_map)));
+        Ok(())
+    }
+}
+
+/// Stream every result of `request`, fetching pages as the stream is polled
+///
+/// `request.limit` is used as the page size. A failed page yields its error
+/// and ends the stream.
+pub fn search_stream(client: &NotionClient, request: SearchRequest) -> impl Stream<Item = Result<SearchResult>> {
+    let pager = Pager {
+        client: client.clone(),
+        page_size: request.limit.unwrap_or(DEFAULT_PAGE_SIZE).max(1),
+        request: SearchRequest { limit: None, ..request },
+        seen: HashSet::new(),
+        buffer: VecDeque::new(),
+        done: false,
+    };
+
+    stream::unfold(pager, |mut pager| async move {
+        loop {
+            if let Some(result) = pager.buffer.pop_front() {
+                return Some((Ok(result), pager));
+            }
+            if pager.done {
+                return None;
+            }
+            if let Err(e) = pager.fetch().await {
+                pager.done = true;
+                return Some((Err(e), pager));
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Json, Router};
+    use futures::StreamExt;
+    use serde_json::json;
+
+    const TOTAL: usize = 7;
+
+    async fn search(Json(body): Json<Value>) -> Json<Value> {
+        let limit = body["limit"].as_u64().unwrap() as usize;
+        let ids: Vec<String> = (0..TOTAL.min(limit)).map(|i| format!("page{}", i)).collect();
+        let results: Vec<Value> = ids.iter().map(|id| json!({ "id": id, "highlight": { "text": "hit" } })).collect();
+        let blocks: serde_json::Map<String, Value> = ids
+            .iter()
+            .map(|id| (id.clone(), json!({ "value": { "parent_id": "root", "properties": { "title": [[id]] } } })))
+            .collect();
+        Json(json!({ "results": results, "total": TOTAL, "recordMap": { "block": blocks } }))
+    }
+
+    #[tokio::test]
+    async fn test_search_stream_pages_transparently() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/api", listener.local_addr().unwrap());
+        let app = Router::new().route("/api/v3/search", post(search));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = NotionClient::from_token("token".to_string()).with_base_url(&base);
+        let request = SearchRequest {
+            limit: Some(3),
+            ..SearchRequest::in_space("space", "hit")
+        };
+        let results: Vec<SearchResult> = search_stream(&client, request)
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(results.len(), TOTAL);
+        assert_eq!(results[6].title, "page6");
+        assert_eq!(results[0].parent_id.as_deref(), Some("root"));
+        assert_eq!(results[0].highlight.as_deref(), Some("hit"));
+    }
+
+    #[test]
+    fn test_request_serializes_like_web_app() {
+        let body = serde_json::to_value(SearchRequest::in_ancestor("page", "q")).unwrap();
+        assert_eq!(body["type"], json!("BlocksInAncestor"));
+        assert_eq!(body["ancestorId"], json!("page"));
+        assert_eq!(body["filters"]["navigableBlockContentOnly"], json!(true));
+        assert_eq!(body["filters"]["lastEditedTime"], json!({}));
+        assert_eq!(body["sort"], json!({ "field": "relevance" }));
+        assert!(body.get("spaceId").is_none());
+    }
+}
@@ -0,0 +1,862 @@
+//! Terminal workspace browser
+//!
+//! A sidebar lists spaces and their pages as a tree; the selected page is
+//! rendered as styled text beside it. `/` searches, `i` shows the raw JSON
+//! of the selected record and `o` opens the page in a web browser.
+//!
+//! Every page loaded over the network is written to the [`PageCache`]. With
+//! no client (or when a load fails) the browser falls back to the cache, so
+//! pages seen before can still be read offline.
+
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use futures::StreamExt;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::widgets::ListState;
+use ratatui::DefaultTerminal;
+use serde_json::Value;
+use tracing::debug;
+
+use crate::api::endpoints::{RecordPointer, SearchRequest};
+use crate::api::NotionClient;
+use crate::cache::PageCache;
+use crate::models::{plain_text, RecordMap, SearchResult};
+use crate::search::{search_stream, SearchIndex};
+use crate::utils::page_url;
+
+mod view;
+
+/// Block types shown as pages in the sidebar
+const PAGE_TYPES: &[&str] = &["page", "collection_view_page"];
+const SEARCH_LIMIT: usize = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Sidebar,
+    Page,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodeKind {
+    Space,
+    Page,
+}
+
+/// A visible row of the sidebar tree
+#[derive(Debug, Clone)]
+struct Node {
+    id: String,
+    title: String,
+    kind: NodeKind,
+    depth: usize,
+    expanded: bool,
+}
+
+struct Search {
+    query: String,
+    /// Query the current results are for
+    ran: Option<String>,
+    results: Vec<SearchResult>,
+    selected: ListState,
+}
+
+pub struct App {
+    client: Option<NotionClient>,
+    cache: PageCache,
+    nodes: Vec<Node>,
+    sidebar: ListState,
+    /// Space records, by id
+    spaces: RecordMap,
+    /// Top-level pages of each space as (id, title)
+    space_pages: HashMap<String, Vec<(String, String)>>,
+    pages: HashMap<String, RecordMap>,
+    current: Option<String>,
+    /// Blocks of the current page in reading order, with depth
+    blocks: Vec<(String, usize)>,
+    cursor: usize,
+    back: Vec<String>,
+    focus: Focus,
+    inspector: bool,
+    inspector_scroll: u16,
+    search: Option<Search>,
+    index: Option<SearchIndex>,
+    status: String,
+}
+
+impl App {
+    /// Browse the pages in `cache`; add a client with [`App::with_client`] to go online
+    pub fn new(cache: PageCache) -> Self {
+        Self {
+            client: None,
+            cache,
+            nodes: Vec::new(),
+            sidebar: ListState::default(),
+            spaces: RecordMap::default(),
+            space_pages: HashMap::new(),
+            pages: HashMap::new(),
+            current: None,
+            blocks: Vec::new(),
+            cursor: 0,
+            back: Vec::new(),
+            focus: Focus::Sidebar,
+            inspector: false,
+            inspector_scroll: 0,
+            search: None,
+            index: None,
+            status: String::new(),
+        }
+    }
+
+    pub fn with_client(mut self, client: NotionClient) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.client.is_none()
+    }
+
+    /// Take over the terminal until the user quits
+    pub async fn run(mut self) -> Result<()> {
+        self.load_roots().await?;
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal).await;
+        ratatui::restore();
+        result
+    }
+
+    async fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| view::draw(frame, self))?;
+            // Reading blocks, so keep it off the runtime's worker
+            if let Event::Key(key) = tokio::task::block_in_place(event::read)? {
+                if key.kind == KeyEventKind::Press && !self.handle_key(key).await {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Fill the sidebar with spaces and their top-level pages
+    ///
+    /// Online this asks `getSpaces`; offline (or if that fails) the cached
+    /// pages whose parent is not cached become the roots.
+    pub async fn load_roots(&mut self) -> Result<()> {
+        if let Some(client) = self.client.clone() {
+            match self.online_roots(&client).await {
+                Ok(()) => return self.rebuild_sidebar(),
+                Err(e) => self.status = format!("Showing cached pages: {:#}", e),
+            }
+        }
+        self.cached_roots()?;
+        if self.space_pages.is_empty() && self.status.is_empty() {
+            self.status = "No cached pages yet".to_string();
+        }
+        self.rebuild_sidebar()
+    }
+
+    async fn online_roots(&mut self, client: &NotionClient) -> Result<()> {
+        let records = client.get_spaces().await?;
+        for (space_id, space) in &records.space {
+            let pointers: Vec<RecordPointer> = space
+                .value
+                .get("pages")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(RecordPointer::block)
+                .collect();
+            let values = client.get_record_values(&pointers).await?;
+            let pages = pointers
+                .iter()
+                .zip(values)
+                .map(|(pointer, record)| (pointer.id.clone(), title(&record.value)))
+                .collect();
+            self.space_pages.insert(space_id.clone(), pages);
+        }
+        self.spaces = records;
+        Ok(())
+    }
+
+    fn cached_roots(&mut self) -> Result<()> {
+        let mut cached = HashMap::new();
+        for entry in self.cache.list()? {
+            if let Some(page) = self.cache.get(&entry.page_id)? {
+                cached.insert(entry.page_id, page.record_map);
+            }
+        }
+        for (page_id, records) in &cached {
+            let Some(block) = records.get("block", page_id) else {
+                continue;
+            };
+            let parent = block["parent_id"].as_str().unwrap_or_default();
+            if cached.contains_key(parent) {
+                continue;
+            }
+            let space_id = block["space_id"].as_str().unwrap_or_default().to_string();
+            if let Some(space) = records.space.get(&space_id) {
+                self.spaces.space.insert(space_id.clone(), space.clone());
+            }
+            self.space_pages
+                .entry(space_id)
+                .or_default()
+                .push((page_id.clone(), title(block)));
+        }
+        for pages in self.space_pages.values_mut() {
+            pages.sort_by(|a, b| a.1.cmp(&b.1));
+        }
+        Ok(())
+    }
+
+    /// Spaces by name, each expanded to its top-level pages
+    fn rebuild_sidebar(&mut self) -> Result<()> {
+        let mut spaces: Vec<(String, String)> = self
+            .space_pages
+            .keys()
+            .map(|id| {
+                let name = self.spaces.get("space", id).and_then(|s| s["name"].as_str());
+                (id.clone(), name.unwrap_or("Cached pages").to_string())
+            })
+            .collect();
+        spaces.sort_by(|a, b| a.1.cmp(&b.1));
+
+        self.nodes.clear();
+        for (id, name) in spaces {
+            self.nodes.push(Node {
+                id: id.clone(),
+                title: name,
+                kind: NodeKind::Space,
+                depth: 0,
+                expanded: true,
+            });
+            self.nodes.extend(self.space_children(&id, 1));
+        }
+        self.sidebar.select((!self.nodes.is_empty()).then_some(0));
+        Ok(())
+    }
+
+    fn space_children(&self, space_id: &str, depth: usize) -> Vec<Node> {
+        self.space_pages
+            .get(space_id)
+            .into_iter()
+            .flatten()
+            .map(|(id, title)| page_node(id, title, depth))
+            .collect()
+    }
+
+    /// Handle one key press; returns false when the browser should close
+    pub async fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+        let result = if self.search.is_some() {
+            self.search_key(key).await
+        } else {
+            match key.code {
+                KeyCode::Char('q') => return false,
+                KeyCode::Tab | KeyCode::BackTab => {
+                    self.focus = match self.focus {
+                        Focus::Sidebar => Focus::Page,
+                        Focus::Page => Focus::Sidebar,
+                    };
+                    Ok(())
+                }
+                KeyCode::Char('/') => {
+                    self.search = Some(Search {
+                        query: String::new(),
+                        ran: None,
+                        results: Vec::new(),
+                        selected: ListState::default(),
+                    });
+                    Ok(())
+                }
+                KeyCode::Char('i') => {
+                    self.inspector = !self.inspector;
+                    self.inspector_scroll = 0;
+                    Ok(())
+                }
+                KeyCode::PageDown => {
+                    self.inspector_scroll = self.inspector_scroll.saturating_add(10);
+                    Ok(())
+                }
+                KeyCode::PageUp => {
+                    self.inspector_scroll = self.inspector_scroll.saturating_sub(10);
+                    Ok(())
+                }
+                KeyCode::Char('o') => self.open_in_browser(),
+                KeyCode::Char('r') => self.reload().await,
+                KeyCode::Backspace => self.go_back().await,
+                KeyCode::Esc => {
+                    self.inspector = false;
+                    Ok(())
+                }
+                _ => match self.focus {
+                    Focus::Sidebar => self.sidebar_key(key).await,
+                    Focus::Page => self.page_key(key).await,
+                },
+            }
+        };
+        if let Err(e) = result {
+            self.status = format!("{:#}", e);
+        }
+        true
+    }
+
+    async fn sidebar_key(&mut self, key: KeyEvent) -> Result<()> {
+        let Some(selected) = self.sidebar.selected() else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.sidebar.select(Some((selected + 1).min(self.nodes.len().saturating_sub(1))));
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.sidebar.select(Some(selected.saturating_sub(1))),
+            KeyCode::Right | KeyCode::Char('l') => self.expand(selected).await?,
+            KeyCode::Left | KeyCode::Char('h') => {
+                if self.nodes[selected].expanded {
+                    self.collapse(selected);
+                } else if let Some(parent) = self.parent_node(selected) {
+                    self.sidebar.select(Some(parent));
+                }
+            }
+            KeyCode::Enter => {
+                let node = self.nodes[selected].clone();
+                match node.kind {
+                    NodeKind::Space if node.expanded => self.collapse(selected),
+                    NodeKind::Space => self.expand(selected).await?,
+                    NodeKind::Page => {
+                        self.open_page(&node.id).await?;
+                        self.expand(selected).await?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn page_key(&mut self, key: KeyEvent) -> Result<()> {
+        match key.code {
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.cursor = (self.cursor + 1).min(self.blocks.len().saturating_sub(1));
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Home | KeyCode::Char('g') => self.cursor = 0,
+            KeyCode::End | KeyCode::Char('G') => self.cursor = self.blocks.len().saturating_sub(1),
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                let Some((id, _)) = self.blocks.get(self.cursor).cloned() else {
+                    return Ok(());
+                };
+                let is_page = self
+                    .current_records()
+                    .and_then(|r| r.get("block", &id))
+                    .and_then(|b| b["type"].as_str())
+                    .is_some_and(|t| PAGE_TYPES.contains(&t));
+                if is_page {
+                    self.open_page(&id).await?;
+                }
+            }
+            KeyCode::Left | KeyCode::Char('h') => self.focus = Focus::Sidebar,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn search_key(&mut self, key: KeyEvent) -> Result<()> {
+        let Some(search) = self.search.as_mut() else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Esc => self.search = None,
+            KeyCode::Char(c) => search.query.push(c),
+            KeyCode::Backspace => {
+                search.query.pop();
+            }
+            KeyCode::Down => {
+                let next = search.selected.selected().map_or(0, |i| i + 1);
+                search.selected.select(Some(next.min(search.results.len().saturating_sub(1))));
+            }
+            KeyCode::Up => {
+                let prev = search.selected.selected().unwrap_or(0).saturating_sub(1);
+                search.selected.select(Some(prev));
+            }
+            KeyCode::Enter if search.ran.as_deref() == Some(search.query.as_str()) => {
+                let hit = search.selected.selected().and_then(|i| search.results.get(i)).cloned();
+                if let Some(hit) = hit {
+                    self.search = None;
+                    self.open_block(&hit.id).await?;
+                }
+            }
+            KeyCode::Enter => {
+                let query = search.query.clone();
+                let results = self.run_search(&query).await?;
+                self.status = format!("{} results for \"{}\"", results.len(), query);
+                if let Some(search) = self.search.as_mut() {
+                    search.selected.select((!results.is_empty()).then_some(0));
+                    search.results = results;
+                    search.ran = Some(query);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Search the workspace online, or the cached pages offline
+    async fn run_search(&mut self, query: &str) -> Result<Vec<SearchResult>> {
+        if let Some(client) = &self.client {
+            let request = match self.current_space() {
+                Some(space) => SearchRequest::in_space(&space, query),
+                None => bail!("No workspace to search"),
+            };
+            let request = SearchRequest {
+                limit: Some(SEARCH_LIMIT as u32),
+                ..request
+            };
+            let results: Vec<_> = search_stream(client, request).take(SEARCH_LIMIT).collect().await;
+            return results.into_iter().collect();
+        }
+        if self.index.is_none() {
+            let mut index = SearchIndex::default();
+            for entry in self.cache.list()? {
+                if let Some(page) = self.cache.get(&entry.page_id)? {
+                    index.update(&page.record_map);
+                }
+            }
+            self.index = Some(index);
+        }
+        let index = self.index.as_ref().expect("index built above");
+        Ok(index.search(query, SEARCH_LIMIT).into_iter().map(|hit| hit.result).collect())
+    }
+
+    /// Space of the current page, else of the selected sidebar node, else the first one
+    fn current_space(&self) -> Option<String> {
+        let from_page = self
+            .current_records()
+            .zip(self.current.as_deref())
+            .and_then(|(r, id)| r.get("block", id))
+            .and_then(|b| b["space_id"].as_str());
+        let from_sidebar = self
+            .sidebar
+            .selected()
+            .and_then(|i| (0..=i).rev().find(|j| self.nodes[*j].kind == NodeKind::Space))
+            .map(|i| self.nodes[i].id.as_str());
+        from_page
+            .or(from_sidebar)
+            .or_else(|| self.spaces.space.keys().next().map(String::as_str))
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    }
+
+    /// Open the page holding `block_id` with that block selected
+    async fn open_block(&mut self, block_id: &str) -> Result<()> {
+        let page_id = match self.containing_page(block_id)? {
+            Some(page_id) => page_id,
+            None => self.remote_page_of(block_id).await?,
+        };
+        self.open_page(&page_id).await?;
+        if let Some(i) = self.blocks.iter().position(|(id, _)| id == block_id) {
+            self.cursor = i;
+            self.focus = Focus::Page;
+        }
+        Ok(())
+    }
+
+    /// A loaded or cached page whose record map has `block_id`
+    fn containing_page(&self, block_id: &str) -> Result<Option<String>> {
+        let is_page = |records: &RecordMap| {
+            records
+                .get("block", block_id)
+                .and_then(|b| b["type"].as_str())
+                .is_some_and(|t| PAGE_TYPES.contains(&t))
+        };
+        for (page_id, records) in &self.pages {
+            if page_id == block_id || (records.block.contains_key(block_id) && !is_page(records)) {
+                return Ok(Some(page_id.clone()));
+            }
+        }
+        for entry in self.cache.list()? {
+            if let Some(page) = self.cache.get(&entry.page_id)? {
+                let contains = page.record_map.block.contains_key(block_id) && !is_page(&page.record_map);
+                if page.page_id == block_id || contains {
+                    return Ok(Some(page.page_id));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Walk `parent_id` up from `block_id` to the nearest page
+    async fn remote_page_of(&self, block_id: &str) -> Result<String> {
+        let client = self.client.as_ref().with_context(|| format!("{} is not cached", block_id))?;
+        let mut id = block_id.to_string();
+        for _ in 0..32 {
+            let record = client.get_record_values(&[RecordPointer::block(&id)]).await?;
+            let value = record.into_iter().next().map(|r| r.value).unwrap_or_default();
+            if value["type"].as_str().is_some_and(|t| PAGE_TYPES.contains(&t)) {
+                return Ok(id);
+            }
+            match value["parent_id"].as_str() {
+                Some(parent) if value["parent_table"] == "block" => id = parent.to_string(),
+                _ => break,
+            }
+        }
+        bail!("No page found above {}", block_id)
+    }
+
+    async fn open_page(&mut self, page_id: &str) -> Result<()> {
+        self.ensure_loaded(page_id, false).await?;
+        if let Some(current) = self.current.replace(page_id.to_string()) {
+            if current != page_id {
+                self.back.push(current);
+            }
+        }
+        self.show_current();
+        Ok(())
+    }
+
+    fn show_current(&mut self) {
+        self.blocks = match (&self.current, self.current_records()) {
+            (Some(id), Some(records)) => view::page_blocks(records, id),
+            _ => Vec::new(),
+        };
+        self.cursor = 0;
+        self.inspector_scroll = 0;
+    }
+
+    async fn go_back(&mut self) -> Result<()> {
+        if let Some(previous) = self.back.pop() {
+            self.ensure_loaded(&previous, false).await?;
+            self.current = Some(previous);
+            self.show_current();
+        }
+        Ok(())
+    }
+
+    async fn reload(&mut self) -> Result<()> {
+        let Some(page_id) = self.current.clone() else {
+            return Ok(());
+        };
+        self.ensure_loaded(&page_id, true).await?;
+        let cursor = self.cursor;
+        self.show_current();
+        self.cursor = cursor.min(self.blocks.len().saturating_sub(1));
+        Ok(())
+    }
+
+    /// Load a page over the network (caching it) or from the cache
+    async fn ensure_loaded(&mut self, page_id: &str, refresh: bool) -> Result<()> {
+        if self.pages.contains_key(page_id) && !refresh {
+            return Ok(());
+        }
+        if let Some(client) = &self.client {
+            match client.load_page(page_id).await {
+                Ok(records) => {
+                    if let Err(e) = self.cache.put(page_id, &records) {
+                        debug!("Could not cache {}: {:#}", page_id, e);
+                    }
+                    self.status = format!("Loaded {} blocks", records.block.len());
+                    self.pages.insert(page_id.to_string(), records);
+                    return Ok(());
+                }
+                Err(e) => self.status = format!("Showing cached copy: {:#}", e),
+            }
+        }
+        let page = self
+            .cache
+            .get(page_id)?
+            .with_context(|| format!("Page {} is not cached", page_id))?;
+        if self.client.is_none() {
+            self.status = format!("Cached {}", page.cached_at.format("%Y-%m-%d %H:%M"));
+        }
+        self.pages.insert(page_id.to_string(), page.record_map);
+        Ok(())
+    }
+
+    async fn expand(&mut self, index: usize) -> Result<()> {
+        let node = self.nodes[index].clone();
+        if node.expanded {
+            return Ok(());
+        }
+        let children = match node.kind {
+            NodeKind::Space => self.space_children(&node.id, node.depth + 1),
+            NodeKind::Page => {
+                self.ensure_loaded(&node.id, false).await?;
+                let records = &self.pages[&node.id];
+                sub_pages(records, &node.id)
+                    .into_iter()
+                    .map(|(id, title)| page_node(&id, &title, node.depth + 1))
+                    .collect()
+            }
+        };
+        self.nodes[index].expanded = true;
+        self.nodes.splice(index + 1..index + 1, children);
+        Ok(())
+    }
+
+    fn collapse(&mut self, index: usize) {
+        let depth = self.nodes[index].depth;
+        let end = (index + 1..self.nodes.len())
+            .find(|i| self.nodes[*i].depth <= depth)
+            .unwrap_or(self.nodes.len());
+        self.nodes.drain(index + 1..end);
+        self.nodes[index].expanded = false;
+    }
+
+    fn parent_node(&self, index: usize) -> Option<usize> {
+        let depth = self.nodes[index].depth;
+        (0..index).rev().find(|i| self.nodes[*i].depth < depth)
+    }
+
+    fn open_in_browser(&mut self) -> Result<()> {
+        let page_id = match self.focus {
+            Focus::Page => self.current.clone(),
+            Focus::Sidebar => self
+                .sidebar
+                .selected()
+                .map(|i| &self.nodes[i])
+                .filter(|n| n.kind == NodeKind::Page)
+                .map(|n| n.id.clone()),
+        };
+        let Some(page_id) = page_id else {
+            bail!("Select a page to open");
+        };
+        let mut url = page_url(&page_id);
+        if let (Focus::Page, Some((block_id, _))) = (self.focus, self.blocks.get(self.cursor)) {
+            url = format!("{}#{}", url, block_id.replace('-', ""));
+        }
+        open_url(&url)?;
+        self.status = format!("Opened {}", url);
+        Ok(())
+    }
+
+    fn current_records(&self) -> Option<&RecordMap> {
+        self.current.as_ref().and_then(|id| self.pages.get(id))
+    }
+
+    /// Table, id and value of the record under the cursor
+    fn selected_record(&self) -> Option<(&'static str, &str, &Value)> {
+        match self.focus {
+            Focus::Page => {
+                let (id, _) = self.blocks.get(self.cursor)?;
+                Some(("block", id, self.current_records()?.get("block", id)?))
+            }
+            Focus::Sidebar => {
+                let node = &self.nodes[self.sidebar.selected()?];
+                match node.kind {
+                    NodeKind::Space => Some(("space", &node.id, self.spaces.get("space", &node.id)?)),
+                    NodeKind::Page => {
+                        let records = self.pages.get(&node.id)?;
+                        Some(("block", &node.id, records.get("block", &node.id)?))
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn page_node(id: &str, title: &str, depth: usize) -> Node {
+    Node {
+        id: id.to_string(),
+        title: title.to_string(),
+        kind: NodeKind::Page,
+        depth,
+        expanded: false,
+    }
+}
+
+fn title(block: &Value) -> String {
+    block.pointer("/properties/title").map(plain_text).unwrap_or_default()
+}
+
+/// Pages nested in a page's content, in order, as (id, title)
+fn sub_pages(records: &RecordMap, page_id: &str) -> Vec<(String, String)> {
+    view::page_blocks(records, page_id)
+        .into_iter()
+        .filter_map(|(id, _)| {
+            let block = records.get("block", &id)?;
+            match block["type"].as_str()? {
+                "page" => Some((id, title(block))),
+                "collection_view_page" => {
+                    let name = block["collection_id"]
+                        .as_str()
+                        .and_then(|c| records.get("collection", c))
+                        .and_then(|c| c.get("name"))
+                        .map(plain_text)
+                        .unwrap_or_default();
+                    Some((id, name))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Hand a URL to the platform's opener
+fn open_url(url: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    command
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to launch a browser")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::fixture::Fixture;
+    use crate::models::Record;
+    use ratatui::backend::TestBackend;
+    use ratatui::style::Modifier;
+    use ratatui::Terminal;
+    use serde_json::json;
+
+    const ROOT: &str = "11111111-1111-1111-1111-111111111111";
+    const CHILD: &str = "22222222-2222-2222-2222-222222222222";
+
+    fn workspace() -> RecordMap {
+        let mut records = RecordMap::default();
+        let mut block = |id: &str, value: Value| {
+            records.block.insert(id.to_string(), Record::new(value));
+        };
+        block(ROOT, json!({ "id": ROOT, "type": "page", "space_id": "sp", "parent_id": "sp", "parent_table": "space",
+            "properties": { "title": [["Handbook"]] }, "content": ["h", "list", CHILD] }));
+        block("h", json!({ "id": "h", "type": "header", "space_id": "sp", "parent_id": ROOT, "parent_table": "block",
+            "properties": { "title": [["Welcome"]] } }));
+        block("list", json!({ "id": "list", "type": "to_do", "space_id": "sp", "parent_id": ROOT, "parent_table": "block",
+            "properties": { "title": [["Read the ", []], ["rules", [["b"]]]], "checked": [["Yes"]] } }));
+        block(CHILD, json!({ "id": CHILD, "type": "page", "space_id": "sp", "parent_id": ROOT, "parent_table": "block",
+            "properties": { "title": [["Onboarding"]] }, "content": ["laptop"] }));
+        block("laptop", json!({ "id": "laptop", "type": "text", "space_id": "sp", "parent_id": CHILD, "parent_table": "block",
+            "properties": { "title": [["Collect your laptop"]] } }));
+        records
+            .space
+            .insert("sp".to_string(), Record::new(json!({ "id": "sp", "name": "Acme", "pages": [ROOT] })));
+        records
+    }
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn screen(app: &mut App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| view::draw(frame, app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|c| c.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_block_styles() {
+        let records = workspace();
+        let todo = records.get("block", "list").unwrap();
+        let line = &view::block_lines(&records, todo, 1, 0)[0];
+        assert_eq!(line.to_string(), "  [x] Read the rules");
+        let rules = line.spans.last().unwrap();
+        assert!(rules.style.add_modifier.contains(Modifier::BOLD | Modifier::CROSSED_OUT));
+
+        let blocks = view::page_blocks(&records, ROOT);
+        let ids: Vec<&str> = blocks.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["h", "list", CHILD]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_browse_search_and_inspect_online() {
+        let tmp = tempfile::tempdir().unwrap();
+        let fixtures = tmp.path().join("fixtures");
+        Fixture {
+            endpoint: "getSpaces".to_string(),
+            status: 200,
+            request: json!({}),
+            response: json!({ "u1": { "space": { "sp": { "value": { "id": "sp", "name": "Acme", "pages": [ROOT] } } } } }),
+        }
+        .save(&fixtures)
+        .unwrap();
+        let emulator = Emulator::new("secret")
+            .with_records(workspace())
+            .with_fixtures(&fixtures)
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let cache = PageCache::new(tmp.path().join("cache"));
+        let mut app = App::new(cache.clone()).with_client(emulator.client());
+        app.load_roots().await.unwrap();
+        assert!(screen(&mut app).contains("▾ Acme"));
+
+        // Open the root page and expand it to its sub-page
+        app.handle_key(press(KeyCode::Down)).await;
+        app.handle_key(press(KeyCode::Enter)).await;
+        let shown = screen(&mut app);
+        assert!(shown.contains("# Welcome"), "{}", shown);
+        assert!(shown.contains("→ Onboarding"));
+        assert!(shown.contains("▸ Onboarding"));
+        assert!(cache.get(ROOT).unwrap().is_some());
+
+        // Inspect the to-do block
+        app.handle_key(press(KeyCode::Tab)).await;
+        app.handle_key(press(KeyCode::Down)).await;
+        app.handle_key(press(KeyCode::Char('i'))).await;
+        assert!(screen(&mut app).contains("\"checked\""));
+
+        // Search lands on the matching block inside the sub-page
+        app.handle_key(press(KeyCode::Char('/'))).await;
+        for c in "laptop".chars() {
+            app.handle_key(press(KeyCode::Char(c))).await;
+        }
+        app.handle_key(press(KeyCode::Enter)).await;
+        assert!(screen(&mut app).contains("Collect your laptop"));
+        app.handle_key(press(KeyCode::Enter)).await;
+        assert_eq!(app.current.as_deref(), Some(CHILD));
+        assert_eq!(app.blocks[app.cursor].0, "laptop");
+
+        app.handle_key(press(KeyCode::Backspace)).await;
+        assert_eq!(app.current.as_deref(), Some(ROOT));
+        assert!(!app.handle_key(press(KeyCode::Char('q'))).await);
+    }
+
+    #[tokio::test]
+    async fn test_offline_reads_cache() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cache = PageCache::new(tmp.path());
+        let records = workspace();
+        cache.put(ROOT, &records).unwrap();
+
+        let mut app = App::new(cache.clone());
+        app.load_roots().await.unwrap();
+        let names: Vec<&str> = app.nodes.iter().map(|n| n.title.as_str()).collect();
+        assert_eq!(names, vec!["Acme", "Handbook"]);
+
+        app.handle_key(press(KeyCode::Down)).await;
+        app.handle_key(press(KeyCode::Enter)).await;
+        assert!(screen(&mut app).contains("offline"));
+        assert_eq!(app.blocks.len(), 3);
+
+        // The sub-page was never loaded, so opening it reports that
+        app.handle_key(press(KeyCode::Tab)).await;
+        app.handle_key(press(KeyCode::End)).await;
+        app.handle_key(press(KeyCode::Enter)).await;
+        assert!(app.status.contains("not cached"), "{}", app.status);
+    }
+}
@@ -0,0 +1,350 @@
+//! Drawing the browser: sidebar, page pane, inspector, search popup and status bar
+
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Clear, List, ListItem, Paragraph, Wrap};
+use ratatui::Frame;
+use serde_json::Value;
+
+use super::{App, Focus, NodeKind, PAGE_TYPES};
+use crate::models::{plain_text, RecordMap};
+
+const HINTS: &str = "q quit · Tab focus · ↵ open · ⌫ back · / search · i inspect · o browser · r reload";
+
+pub(super) fn draw(frame: &mut Frame, app: &mut App) {
+    let [body, status] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+    let [side, main] = Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(body);
+
+    draw_sidebar(frame, app, side);
+    if app.inspector {
+        let [page, json] = Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(main);
+        draw_page(frame, app, page);
+        draw_inspector(frame, app, json);
+    } else {
+        draw_page(frame, app, main);
+    }
+
+    let mode = if app.is_offline() { " offline " } else { " online " };
+    let status_line = Line::from(vec![
+        Span::styled(mode, Style::new().reversed()),
+        Span::raw(" "),
+        Span::raw(app.status.clone()),
+        Span::styled(format!("  {}", HINTS), Style::new().dark_gray()),
+    ]);
+    frame.render_widget(Paragraph::new(status_line), status);
+
+    if app.search.is_some() {
+        draw_search(frame, app, popup(frame.area()));
+    }
+}
+
+fn pane(title: &str, focused: bool) -> Block<'static> {
+    let block = Block::bordered().title(format!(" {} ", title));
+    if focused {
+        block.border_style(Style::new().cyan())
+    } else {
+        block
+    }
+}
+
+fn draw_sidebar(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .nodes
+        .iter()
+        .map(|node| {
+            let marker = if node.expanded { "▾ " } else { "▸ " };
+            let title = if node.title.is_empty() { "Untitled" } else { &node.title };
+            let style = match node.kind {
+                NodeKind::Space => Style::new().bold(),
+                NodeKind::Page => Style::new(),
+            };
+            ListItem::new(Line::from(vec![
+                Span::raw("  ".repeat(node.depth)),
+                Span::raw(marker),
+                Span::styled(title.to_string(), style),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(pane("Workspace", app.focus == Focus::Sidebar))
+        .highlight_style(Style::new().reversed());
+    frame.render_stateful_widget(list, area, &mut app.sidebar);
+}
+
+fn draw_page(frame: &mut Frame, app: &App, area: Rect) {
+    let focused = app.focus == Focus::Page;
+    let Some(records) = app.current_records() else {
+        let hint = Paragraph::new("Select a page in the sidebar and press Enter").dark_gray();
+        frame.render_widget(hint.block(pane("Page", focused)), area);
+        return;
+    };
+    let page_id = app.current.as_deref().unwrap_or_default();
+    let title = records
+        .get("block", page_id)
+        .and_then(|b| b.pointer("/properties/title"))
+        .map(plain_text)
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| "Untitled".to_string());
+
+    let (lines, selected) = page_lines(records, &app.blocks, Some(app.cursor));
+    // Count wrapped rows above the selection so it stays in view
+    let width = area.width.saturating_sub(2).max(1) as usize;
+    let rows_above: usize = lines[..selected].iter().map(|l| l.width().max(1).div_ceil(width)).sum();
+    let offset = rows_above.saturating_sub(area.height as usize / 3);
+
+    let paragraph = Paragraph::new(lines)
+        .block(pane(&title, focused))
+        .wrap(Wrap { trim: false })
+        .scroll((offset as u16, 0));
+    frame.render_widget(paragraph, area);
+}
+
+fn draw_inspector(frame: &mut Frame, app: &App, area: Rect) {
+    let (title, text) = match app.selected_record() {
+        Some((table, id, value)) => (
+            format!("{} {}", table, id),
+            serde_json::to_string_pretty(value).unwrap_or_default(),
+        ),
+        None => ("Inspector".to_string(), "Nothing selected".to_string()),
+    };
+    let paragraph = Paragraph::new(text)
+        .block(pane(&title, false))
+        .scroll((app.inspector_scroll, 0));
+    frame.render_widget(paragraph, area);
+}
+
+fn draw_search(frame: &mut Frame, app: &mut App, area: Rect) {
+    let Some(search) = app.search.as_mut() else {
+        return;
+    };
+    frame.render_widget(Clear, area);
+    let outer = pane("Search · ↵ run/open · Esc close", true);
+    let inner = outer.inner(area);
+    frame.render_widget(outer, area);
+
+    let [input, results] = Layout::vertical([Constraint::Length(2), Constraint::Min(1)]).areas(inner);
+    frame.render_widget(Paragraph::new(format!("/ {}▏", search.query)), input);
+
+    let items: Vec<ListItem> = search
+        .results
+        .iter()
+        .map(|r| {
+            let title = if r.title.is_empty() { "Untitled" } else { &r.title };
+            let mut spans = vec![Span::raw(title.to_string())];
+            if let Some(highlight) = r.highlight.as_deref().filter(|h| !h.is_empty()) {
+                spans.push(Span::styled(format!("  {}", highlight), Style::new().dark_gray()));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let list = List::new(items).highlight_style(Style::new().reversed());
+    frame.render_stateful_widget(list, results, &mut search.selected);
+}
+
+/// A box in the middle of `area`, 60% of its size
+fn popup(area: Rect) -> Rect {
+    let [_, middle, _] =
+        Layout::vertical([Constraint::Percentage(20), Constraint::Percentage(60), Constraint::Percentage(20)]).areas(area);
+    let [_, center, _] =
+        Layout::horizontal([Constraint::Percentage(20), Constraint::Percentage(60), Constraint::Percentage(20)])
+            .areas(middle);
+    center
+}
+
+/// Content blocks of a page in reading order with their nesting depth
+///
+/// Sub-pages are listed but not descended into.
+pub(super) fn page_blocks(records: &RecordMap, page_id: &str) -> Vec<(String, usize)> {
+    let mut out = Vec::new();
+    let mut stack: Vec<(String, usize)> = children(records, page_id).into_iter().rev().map(|id| (id, 0)).collect();
+    while let Some((id, depth)) = stack.pop() {
+        let Some(block) = records.get("block", &id) else {
+            continue;
+        };
+        if block.get("alive").and_then(Value::as_bool) == Some(false) {
+            continue;
+        }
+        let is_page = block["type"].as_str().is_some_and(|t| PAGE_TYPES.contains(&t));
+        if !is_page {
+            stack.extend(children(records, &id).into_iter().rev().map(|c| (c, depth + 1)));
+        }
+        out.push((id, depth));
+    }
+    out
+}
+
+fn children(records: &RecordMap, id: &str) -> Vec<String> {
+    records
+        .get("block", id)
+        .and_then(|b| b["content"].as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect()
+}
+
+/// Styled lines for `blocks`, and the index of the first line of the selected block
+pub(super) fn page_lines(
+    records: &RecordMap,
+    blocks: &[(String, usize)],
+    selected: Option<usize>,
+) -> (Vec<Line<'static>>, usize) {
+    let mut lines = Vec::new();
+    let mut selected_line = 0;
+    // Running number of each numbered list, by depth
+    let mut numbers: Vec<usize> = Vec::new();
+    for (i, (id, depth)) in blocks.iter().enumerate() {
+        let Some(block) = records.get("block", id) else {
+            continue;
+        };
+        numbers.resize(depth + 1, 0);
+        numbers[*depth] = if block["type"] == "numbered_list" { numbers[*depth] + 1 } else { 0 };
+
+        let mut block_lines = block_lines(records, block, *depth, numbers[*depth]);
+        if selected == Some(i) {
+            selected_line = lines.len();
+            block_lines = block_lines
+                .into_iter()
+                .map(|l| l.patch_style(Style::new().bg(Color::DarkGray)))
+                .collect();
+        }
+        lines.extend(block_lines);
+    }
+    (lines, selected_line)
+}
+
+/// One block as lines, with a type marker and its rich text styled
+pub(super) fn block_lines(records: &RecordMap, block: &Value, depth: usize, number: usize) -> Vec<Line<'static>> {
+    let indent = "  ".repeat(depth);
+    let title = block.pointer("/properties/title").unwrap_or(&Value::Null);
+    let block_type = block["type"].as_str().unwrap_or_default();
+
+    let (marker, base) = match block_type {
+        "header" => ("# ".to_string(), Style::new().bold().underlined()),
+        "sub_header" => ("## ".to_string(), Style::new().bold()),
+        "sub_sub_header" => ("### ".to_string(), Style::new().bold()),
+        "bulleted_list" => ("• ".to_string(), Style::new()),
+        "numbered_list" => (format!("{}. ", number), Style::new()),
+        "to_do" => {
+            let checked = block.pointer("/properties/checked").map(plain_text).as_deref() == Some("Yes");
+            if checked {
+                ("[x] ".to_string(), Style::new().dark_gray().crossed_out())
+            } else {
+                ("[ ] ".to_string(), Style::new())
+            }
+        }
+        "toggle" => ("▸ ".to_string(), Style::new()),
+        "quote" => ("│ ".to_string(), Style::new().italic()),
+        "callout" => {
+            let icon = block.pointer("/format/page_icon").and_then(Value::as_str).unwrap_or("ⓘ");
+            (format!("{} ", icon), Style::new())
+        }
+        "divider" => return vec![Line::styled(format!("{}{}", indent, "─".repeat(40)), Style::new().dark_gray())],
+        "code" => {
+            let language = block.pointer("/properties/language").map(plain_text).unwrap_or_default();
+            let code = plain_text(title);
+            let style = Style::new().yellow();
+            let mut lines = vec![Line::styled(format!("{}```{}", indent, language), Style::new().dark_gray())];
+            lines.extend(code.lines().map(|l| Line::styled(format!("{}  {}", indent, l), style)));
+            return lines;
+        }
+        "page" => {
+            let name = Some(plain_text(title)).filter(|t| !t.is_empty()).unwrap_or_else(|| "Untitled".into());
+            return vec![Line::styled(format!("{}→ {}", indent, name), Style::new().cyan().underlined())];
+        }
+        "collection_view" | "collection_view_page" => {
+            let name = block["collection_id"]
+                .as_str()
+                .and_then(|c| records.get("collection", c))
+                .and_then(|c| c.get("name"))
+                .map(plain_text)
+                .unwrap_or_else(|| "Database".into());
+            return vec![Line::styled(format!("{}▦ {}", indent, name), Style::new().cyan().underlined())];
+        }
+        "image" | "video" | "audio" | "file" | "pdf" | "bookmark" | "embed" => {
+            let source = block.pointer("/properties/source").map(plain_text).unwrap_or_default();
+            let caption = Some(plain_text(title)).filter(|t| !t.is_empty()).unwrap_or(source);
+            return vec![Line::styled(format!("{}[{}] {}", indent, block_type, caption), Style::new().dark_gray())];
+        }
+        _ => (String::new(), Style::new()),
+    };
+
+    // Rich text may hold newlines; continuation lines line up under the text
+    let hang = " ".repeat(marker.chars().count());
+    let mut lines = vec![vec![Span::raw(indent.clone()), Span::raw(marker)]];
+    for span in rich_text(title, base) {
+        let mut parts = span.content.split('\n');
+        if let Some(first) = parts.next() {
+            if !first.is_empty() {
+                lines.last_mut().unwrap().push(Span::styled(first.to_string(), span.style));
+            }
+        }
+        for part in parts {
+            lines.push(vec![Span::raw(format!("{}{}", indent, hang)), Span::styled(part.to_string(), span.style)]);
+        }
+    }
+    lines.into_iter().map(Line::from).collect()
+}
+
+/// Spans for a rich-text property, applying bold, italic, strikethrough,
+/// underline, code, link and color annotations on top of `base`
+pub(super) fn rich_text(raw: &Value, base: Style) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    for segment in raw.as_array().into_iter().flatten() {
+        let text = segment.get(0).and_then(Value::as_str).unwrap_or_default();
+        let annotations = segment.get(1).and_then(Value::as_array).cloned().unwrap_or_default();
+
+        let mut style = base;
+        let mut content = text.to_string();
+        for annotation in &annotations {
+            let code = annotation.get(0).and_then(Value::as_str).unwrap_or_default();
+            let arg = annotation.get(1);
+            style = match code {
+                "b" => style.add_modifier(Modifier::BOLD),
+                "i" => style.add_modifier(Modifier::ITALIC),
+                "s" => style.add_modifier(Modifier::CROSSED_OUT),
+                "_" => style.add_modifier(Modifier::UNDERLINED),
+                "c" => style.fg(Color::LightRed),
+                "a" => style.fg(Color::Blue).add_modifier(Modifier::UNDERLINED),
+                "h" => match arg.and_then(Value::as_str) {
+                    Some(name) if name.ends_with("_background") => {
+                        color(name.trim_end_matches("_background")).map_or(style, |c| style.bg(c))
+                    }
+                    Some(name) => color(name).map_or(style, |c| style.fg(c)),
+                    None => style,
+                },
+                _ => style,
+            };
+            if text == "‣" {
+                content = match code {
+                    "p" => "@page".to_string(),
+                    "u" => "@user".to_string(),
+                    "d" => arg
+                        .and_then(|d| d.get("start_date"))
+                        .and_then(Value::as_str)
+                        .map(|d| format!("@{}", d))
+                        .unwrap_or(content),
+                    _ => content,
+                };
+            }
+        }
+        spans.push(Span::styled(content, style));
+    }
+    spans
+}
+
+fn color(name: &str) -> Option<Color> {
+    Some(match name {
+        "gray" => Color::DarkGray,
+        "brown" | "orange" => Color::LightYellow,
+        "yellow" => Color::Yellow,
+        "green" => Color::Green,
+        "blue" => Color::Blue,
+        "purple" => Color::Magenta,
+        "pink" => Color::LightMagenta,
+        "red" => Color::Red,
+        _ => return None,
+    })
+}
@@ -0,0 +1,328 @@
+//! File uploads
+//!
+//! Uploading is a three step dance: ask `getUploadFileUrl` for a signed S3 URL,
+//! PUT the bytes there, then point a block's `source` property at the
+//! permanent URL returned in the first step.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use reqwest::Body;
+use serde_json::{json, Value};
+use tokio_util::io::ReaderStream;
+use tracing::info;
+
+use crate::api::endpoints::{paths, GetUploadFileUrlRequest, Operation, RecordPointer, UploadRecord};
+use crate::api::NotionClient;
+use crate::models::UploadFileUrlResponse;
+
+/// Where the uploaded bytes come from
+#[derive(Debug, Clone)]
+pub enum UploadSource {
+    /// A file on disk, streamed rather than read into memory
+    Path(PathBuf),
+    Bytes { name: String, data: Vec<u8> },
+}
+
+impl UploadSource {
+    fn name(&self) -> String {
+        match self {
+            UploadSource::Path(path) => path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "upload".to_string()),
+            UploadSource::Bytes { name, .. } => name.clone(),
+        }
+    }
+
+    async fn into_body(self) -> Result<(Body, u64)> {
+        match self {
+            UploadSource::Path(path) => {
+                let file = tokio::fs::File::open(&path)
+                    .await
+                    .with_context(|| format!("Failed to open {}", path.display()))?;
+                let len = file.metadata().await?.len();
+                Ok((Body::wrap_stream(ReaderStream::new(file)), len))
+            }
+            UploadSource::Bytes { data, .. } => {
+                let len = data.len() as u64;
+                Ok((Body::from(data), len))
+            }
+        }
+    }
+}
+
+/// Outcome of an upload
+#[derive(Debug, Clone)]
+pub struct UploadedFile {
+    pub block_id: String,
+    /// Permanent URL stored in the block
+    pub url: String,
+    pub operations: Vec<Operation>,
+}
+
+/// Block type Notion uses to display a file of this MIME type
+pub fn block_type_for_mime(mime: &str) -> &'static str {
+    match mime {
+        m if m.starts_with("image/") => "image",
+        m if m.starts_with("video/") => "video",
+        m if m.starts_with("audio/") => "audio",
+        "application/pdf" => "pdf",
+        _ => "file",
+    }
+}
+
+/// Upload a file and append a new image/file/pdf block to `parent_block`
+pub async fn upload_file(
+    client: &NotionClient,
+    parent_block: &str,
+    source: UploadSource,
+    mime: &str,
+) -> Result<UploadedFile> {
+    let space_id = space_of(client, parent_block).await?;
+    let block_id = uuid::Uuid::new_v4().to_string();
+    let name = source.name();
+    let (url, size) = put_file(client, &block_id, space_id.as_deref(), source, mime).await?;
+
+    let mut block = json!({
+        "id": block_id,
+        "type": block_type_for_mime(mime),
+        "version": 1,
+        "alive": true,
+        "parent_id": parent_block,
+        "parent_table": "block",
+        "properties": file_properties(&name, &url, size),
+        "format": { "display_source": url },
+    });
+    if let Some(space_id) = &space_id {
+        block["space_id"] = json!(space_id);
+    }
+
+    let operations = vec![
+        Operation::set("block", &block_id, &[], block),
+        Operation::list_after("block", parent_block, &["content"], &block_id, None),
+    ];
+    client.submit_transaction(operations.clone()).await?;
+    info!("Uploaded {} as block {}", name, block_id);
+
+    Ok(UploadedFile { block_id, url, operations })
+}
+
+/// Upload a file and make an existing block point at it
+pub async fn upload_to_block(
+    client: &NotionClient,
+    block_id: &str,
+    source: UploadSource,
+    mime: &str,
+) -> Result<UploadedFile> {
+    let space_id = space_of(client, block_id).await?;
+    let name = source.name();
+    let (url, size) = put_file(client, block_id, space_id.as_deref(), source, mime).await?;
+
+    let operations = vec![
+        Operation::update(
+            "block",
+            block_id,
+            &[],
+            json!({ "type": block_type_for_mime(mime), "properties": file_properties(&name, &url, size) }),
+        ),
+        Operation::update("block", block_id, &["format"], json!({ "display_source": url })),
+    ];
+    client.submit_transaction(operations.clone()).await?;
+
+    Ok(UploadedFile {
+        block_id: block_id.to_string(),
+        url,
+        operations,
+    })
+}
+
+async fn space_of(client: &NotionClient, block_id: &str) -> Result<Option<String>> {
+    let records = client.get_record_values(&[RecordPointer::block(block_id)]).await?;
+    let value = records.into_iter().next().map(|r| r.value).unwrap_or(Value::Null);
+    if value.is_null() {
+        bail!("Block {} not found or not accessible", block_id);
+    }
+    Ok(value.get("space_id").and_then(Value::as_str).map(str::to_string))
+}
+
+/// Request a signed URL and PUT the bytes, returning the permanent URL and size
+async fn put_file(
+    client: &NotionClient,
+    block_id: &str,
+    space_id: Option<&str>,
+    source: UploadSource,
+    mime: &str,
+) -> Result<(String, u64)> {
+    let request = GetUploadFileUrlRequest {
+        bucket: "secure".to_string(),
+        name: source.name(),
+        content_type: mime.to_string(),
+        record: Some(UploadRecord {
+            table: "block".to_string(),
+            id: block_id.to_string(),
+            space_id: space_id.map(str::to_string),
+        }),
+    };
+    let signed: UploadFileUrlResponse = client.post_json(paths::GET_UPLOAD_FILE_URL, &request).await?;
+
+    let (body, len) = source.into_body().await?;
+    client.put_signed(&signed.signed_put_url, mime, body, len).await?;
+
+    Ok((signed.url, len))
+}
+
+fn file_properties(name: &str, url: &str, size: u64) -> Value {
+    json!({
+        "source": [[url]],
+        "title": [[name]],
+        "size": [[format_size(size)]],
+    })
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    use axum::body::Bytes;
+    use axum::extract::State;
+    use axum::routing::{post, put};
+    use axum::{Json, Router};
+
+    #[derive(Clone, Default)]
+    struct StandIn {
+        base: String,
+        stored: Arc<Mutex<Vec<u8>>>,
+        submitted: Arc<Mutex<Vec<Value>>>,
+    }
+
+    async fn serve() -> StandIn {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let state = StandIn {
+            base: format!("http://{}", listener.local_addr().unwrap()),
+            ..Default::default()
+        };
+
+        let app = Router::new()
+            .route(
+                "/api/v3/getRecordValues",
+                post(|| async { Json(json!({ "results": [{ "value": { "id": "parent", "space_id": "space" } }] })) }),
+            )
+            .route(
+                "/api/v3/getUploadFileUrl",
+                post(|State(s): State<StandIn>| async move {
+                    Json(json!({
+                        "url": "https://s3.example/secure/file.png",
+                        "signedPutUrl": format!("{}/storage/file.png", s.base),
+                    }))
+                }),
+            )
+            .route(
+                "/storage/file.png",
+                put(|State(s): State<StandIn>, body: Bytes| async move {
+                    s.stored.lock().unwrap().extend_from_slice(&body);
+                }),
+            )
+            .route(
+                "/api/v3/submitTransaction",
+                post(|State(s): State<StandIn>, Json(body): Json<Value>| async move {
+                    s.submitted.lock().unwrap().push(body);
+                    Json(json!({}))
+                }),
+            )
+            .with_state(state.clone());
+
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        state
+    }
+
+    fn client(standin: &StandIn) -> NotionClient {
+        NotionClient::from_token("token".to_string()).with_base_url(&format!("{}/api", standin.base))
+    }
+
+    #[tokio::test]
+    async fn test_upload_bytes_creates_block() {
+        let standin = serve().await;
+        let source = UploadSource::Bytes {
+            name: "file.png".to_string(),
+            data: b"not really a png".to_vec(),
+        };
+
+        let uploaded = upload_file(&client(&standin), "parent", source, "image/png").await.unwrap();
+
+        assert_eq!(&*standin.stored.lock().unwrap(), b"not really a png");
+        assert_eq!(uploaded.url, "https://s3.example/secure/file.png");
+        let block = &uploaded.operations[0].args;
+        assert_eq!(block["type"], json!("image"));
+        assert_eq!(block["space_id"], json!("space"));
+        assert_eq!(block["properties"]["source"], json!([[uploaded.url]]));
+        assert_eq!(uploaded.operations[1].id, "parent");
+
+        let submitted = standin.submitted.lock().unwrap();
+        assert_eq!(submitted[0]["transactions"][0]["operations"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_upload_streams_file_to_existing_block() {
+        let standin = serve().await;
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("upload.pdf");
+        let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let uploaded = upload_to_block(&client(&standin), "parent", UploadSource::Path(path.clone()), "application/pdf")
+            .await
+            .unwrap();
+
+        assert_eq!(*standin.stored.lock().unwrap(), data);
+        assert_eq!(uploaded.operations[0].args["type"], json!("pdf"));
+        assert_eq!(uploaded.operations[0].args["properties"]["size"], json!([["195.3 KB"]]));
+    }
+
+    #[tokio::test]
+    async fn test_upload_replays_without_network() {
+        use crate::api::Transport;
+
+        let standin = serve().await;
+        let tmp = tempfile::tempdir().unwrap();
+        let source = || UploadSource::Bytes {
+            name: "file.png".to_string(),
+            data: b"recorded".to_vec(),
+        };
+        let recorder = client(&standin).with_transport(Transport::Record(tmp.path().to_path_buf()));
+        upload_to_block(&recorder, "parent", source(), "image/png").await.unwrap();
+        assert_eq!(&*standin.stored.lock().unwrap(), b"recorded");
+
+        // The signed URL points at the stand-in, which must not see a second PUT
+        let replayer = NotionClient::from_token("token".to_string())
+            .with_base_url("http://127.0.0.1:1/api")
+            .with_transport(Transport::Replay(tmp.path().to_path_buf()));
+        let uploaded = upload_to_block(&replayer, "parent", source(), "image/png").await.unwrap();
+        assert_eq!(uploaded.url, "https://s3.example/secure/file.png");
+        assert_eq!(&*standin.stored.lock().unwrap(), b"recorded");
+        assert_eq!(standin.submitted.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_block_type_for_mime() {
+        assert_eq!(block_type_for_mime("image/jpeg"), "image");
+        assert_eq!(block_type_for_mime("application/pdf"), "pdf");
+        assert_eq!(block_type_for_mime("application/zip"), "file");
+    }
+}
@@ -51,6 +51,30 @@ pub fn is_valid_notion_id(id: &str) -> bool {
     id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit())
 }
 
+/// Format a Notion ID as a dashed UUID, the form used inside record maps
+///
+/// Accepts both compact and already dashed IDs; returns `None` for anything else.
+pub fn to_uuid_format(id: &str) -> Option<String> {
+    let compact: String = id.chars().filter(|c| *c != '-').collect::<String>().to_lowercase();
+    if !is_valid_notion_id(&compact) {
+        return None;
+    }
+    Some(format!(
+        "{}-{}-{}-{}-{}",
+        &compact[..8],
+        &compact[8..12],
+        &compact[12..16],
+        &compact[16..20],
+        &compact[20..]
+    ))
+}
+
+/// Web URL of a page, for opening it in a browser
+pub fn page_url(id: &str) -> String {
+    let compact: String = id.chars().filter(|c| *c != '-').collect();
+    format!("{}/{}", crate::api::NOTION_WWW_BASE, compact)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -107,6 +131,22 @@ mod tests {
         assert!(!is_valid_notion_id("g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g-g")); // invalid chars
     }
 
+    #[test]
+    fn test_to_uuid_format() {
+        let dashed = "12345678-90ab-cdef-1234-567890abcdef";
+        assert_eq!(to_uuid_format("1234567890ABCDEF1234567890abcdef").as_deref(), Some(dashed));
+        assert_eq!(to_uuid_format(dashed).as_deref(), Some(dashed));
+        assert_eq!(to_uuid_format("not-an-id"), None);
+    }
+
+    #[test]
+    fn test_page_url() {
+        assert_eq!(
+            page_url("12345678-90ab-cdef-1234-567890abcdef"),
+            "https://www.notion.so/1234567890abcdef1234567890abcdef"
+        );
+    }
+
     #[test]
     fn test_pretty_print_json() {
         let json = r#"{"name":"test","value":123}"#;
@@ -0,0 +1,402 @@
+//! Change detection by polling
+//!
+//! Each round asks `syncRecordValues` for the watched records newer than the
+//! versions we hold, so unchanged records cost nothing to transfer. Changed
+//! records are diffed against their previous value and reported as events.
+//! The last seen values can be kept in a state file so that a restarted
+//! watcher picks up where it left off instead of replaying history.
+
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::debug;
+
+use crate::api::endpoints::{RecordPointer, SyncRecordRequest};
+use crate::api::NotionClient;
+use crate::models::{Record, RecordMap};
+
+/// Keys that change on every edit and are not worth an event
+const BOOKKEEPING_KEYS: [&str; 5] = ["version", "last_edited_time", "last_edited_by", "last_edited_by_id", "last_edited_by_table"];
+
+/// Keys reported by dedicated events rather than as field changes
+const STRUCTURE_KEYS: [&str; 4] = ["alive", "content", "parent_id", "parent_table"];
+
+/// Objects whose entries are diffed one by one
+const NESTED_KEYS: [&str; 2] = ["properties", "format"];
+
+/// A field that differs between two versions; `path` is dotted, e.g. `properties.title`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub path: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ChangeEvent {
+    Created { pointer: RecordPointer, value: Value },
+    Updated { pointer: RecordPointer, fields: Vec<FieldChange> },
+    Moved { pointer: RecordPointer, from: Option<String>, to: Option<String> },
+    Archived { pointer: RecordPointer },
+    Restored { pointer: RecordPointer },
+    ChildAdded { pointer: RecordPointer, child_id: String, index: usize },
+    ChildRemoved { pointer: RecordPointer, child_id: String },
+}
+
+/// Events describing how `old` became `new`; `None` means the record is new
+pub fn diff(pointer: &RecordPointer, old: Option<&Value>, new: &Value) -> Vec<ChangeEvent> {
+    let Some(old) = old else {
+        return vec![ChangeEvent::Created {
+            pointer: pointer.clone(),
+            value: new.clone(),
+        }];
+    };
+
+    let mut events = Vec::new();
+    let alive = |v: &Value| v.get("alive").and_then(Value::as_bool).unwrap_or(true);
+    match (alive(old), alive(new)) {
+        (true, false) => events.push(ChangeEvent::Archived { pointer: pointer.clone() }),
+        (false, true) => events.push(ChangeEvent::Restored { pointer: pointer.clone() }),
+        _ => {}
+    }
+
+    let parent = |v: &Value| v.get("parent_id").and_then(Value::as_str).map(str::to_string);
+    if parent(old) != parent(new) {
+        events.push(ChangeEvent::Moved {
+            pointer: pointer.clone(),
+            from: parent(old),
+            to: parent(new),
+        });
+    }
+
+    let children = |v: &Value| -> Vec<String> {
+        v.get("content")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|c| c.as_str().map(str::to_string))
+            .collect()
+    };
+    let (before, after) = (children(old), children(new));
+    let before_set: HashSet<&String> = before.iter().collect();
+    let after_set: HashSet<&String> = after.iter().collect();
+    for child in before.iter().filter(|c| !after_set.contains(c)) {
+        events.push(ChangeEvent::ChildRemoved {
+            pointer: pointer.clone(),
+            child_id: child.clone(),
+        });
+    }
+    for (index, child) in after.iter().enumerate().filter(|(_, c)| !before_set.contains(c)) {
+        events.push(ChangeEvent::ChildAdded {
+            pointer: pointer.clone(),
+            child_id: child.clone(),
+            index,
+        });
+    }
+
+    let fields = changed_fields(old, new);
+    if !fields.is_empty() {
+        events.push(ChangeEvent::Updated {
+            pointer: pointer.clone(),
+            fields,
+        });
+    }
+    events
+}
+
+pub(crate) fn changed_fields(old: &Value, new: &Value) -> Vec<FieldChange> {
+    let keys: BTreeSet<&String> = [old, new].iter().filter_map(|v| v.as_object()).flat_map(|o| o.keys()).collect();
+    let mut fields = Vec::new();
+    for key in keys {
+        if BOOKKEEPING_KEYS.contains(&key.as_str()) || STRUCTURE_KEYS.contains(&key.as_str()) {
+            continue;
+        }
+        let (a, b) = (&old[key.as_str()], &new[key.as_str()]);
+        if a == b {
+            continue;
+        }
+        if NESTED_KEYS.contains(&key.as_str()) && (a.is_object() || b.is_object()) {
+            let inner: BTreeSet<&String> = [a, b].iter().filter_map(|v| v.as_object()).flat_map(|o| o.keys()).collect();
+            for name in inner {
+                let (x, y) = (&a[name.as_str()], &b[name.as_str()]);
+                if x != y {
+                    fields.push(FieldChange {
+                        path: format!("{}.{}", key, name),
+                        old: x.clone(),
+                        new: y.clone(),
+                    });
+                }
+            }
+        } else {
+            fields.push(FieldChange {
+                path: key.clone(),
+                old: a.clone(),
+                new: b.clone(),
+            });
+        }
+    }
+    fields
+}
+
+/// What a watcher has seen so far
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct WatchState {
+    records: RecordMap,
+    /// Pointers (`table/id`) checked at least once
+    polled: BTreeSet<String>,
+}
+
+/// Polls a fixed set of records and reports how they change
+pub struct Watcher {
+    client: NotionClient,
+    pointers: Vec<RecordPointer>,
+    state: WatchState,
+    state_path: Option<PathBuf>,
+}
+
+impl Watcher {
+    pub fn new(client: NotionClient, pointers: &[RecordPointer]) -> Self {
+        Self {
+            client,
+            pointers: pointers.to_vec(),
+            state: WatchState::default(),
+            state_path: None,
+        }
+    }
+
+    /// Load last seen values from `path` (if present) and save them there after each poll
+    pub fn with_state_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if path.exists() {
+            let data = fs::read(&path).with_context(|| format!("Failed to read watch state {}", path.display()))?;
+            self.state = serde_json::from_slice(&data).context("Corrupt watch state")?;
+        }
+        self.state_path = Some(path);
+        Ok(self)
+    }
+
+    /// Start watching another record
+    ///
+    /// Once the watcher has polled, a newly added record is reported as
+    /// `Created` when it is first seen rather than taken as a baseline.
+    pub fn add(&mut self, pointer: RecordPointer) {
+        if self.pointers.contains(&pointer) {
+            return;
+        }
+        if !self.state.polled.is_empty() {
+            self.state.polled.insert(pointer.to_string());
+        }
+        self.pointers.push(pointer);
+    }
+
+    pub fn pointers(&self) -> &[RecordPointer] {
+        &self.pointers
+    }
+
+    /// Last seen value of a watched record
+    pub fn value(&self, pointer: &RecordPointer) -> Option<&Value> {
+        self.known(pointer).map(|r| &r.value)
+    }
+
+    /// Check once for changes
+    ///
+    /// The first check of a record only records its current value; events
+    /// start with the next change.
+    pub async fn poll(&mut self) -> Result<Vec<ChangeEvent>> {
+        let requests: Vec<SyncRecordRequest> = self
+            .pointers
+            .iter()
+            .map(|p| SyncRecordRequest::new(p.clone(), self.known(p).and_then(Record::version)))
+            .collect();
+        let changed = self.client.sync_record_values(&requests).await?;
+
+        let mut events = Vec::new();
+        for pointer in &self.pointers {
+            let first = self.state.polled.insert(pointer.to_string());
+            let Some(record) = changed.table(&pointer.table).and_then(|t| t.get(&pointer.id)) else {
+                continue;
+            };
+            if record.value.is_null() {
+                continue;
+            }
+            let old = self.known(pointer);
+            if old.is_some_and(|old| old.version() >= record.version()) {
+                continue;
+            }
+            if !first {
+                events.extend(diff(pointer, old.map(|r| &r.value), &record.value));
+            }
+            self.state
+                .records
+                .table_mut(&pointer.table)
+                .insert(pointer.id.clone(), record.clone());
+        }
+
+        debug!("Watch poll found {} event(s)", events.len());
+        self.save()?;
+        Ok(events)
+    }
+
+    /// Poll every `interval` forever, yielding events as they are found
+    ///
+    /// A failed poll yields its error and the next tick tries again.
+    pub fn into_stream(self, interval: Duration) -> impl Stream<Item = Result<ChangeEvent>> {
+        let ticker = tokio::time::interval(interval);
+        stream::unfold((self, ticker, VecDeque::new()), |(mut watcher, mut ticker, mut pending)| async move {
+            loop {
+                if let Some(event) = pending.pop_front() {
+                    return Some((Ok(event), (watcher, ticker, pending)));
+                }
+                ticker.tick().await;
+                match watcher.poll().await {
+                    Ok(events) => pending.extend(events),
+                    Err(e) => return Some((Err(e), (watcher, ticker, pending))),
+                }
+            }
+        })
+    }
+
+    fn known(&self, pointer: &RecordPointer) -> Option<&Record> {
+        self.state.records.table(&pointer.table)?.get(&pointer.id)
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.state_path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Write beside the target and rename, so a crash never leaves half a file
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&self.state)?)
+            .with_context(|| format!("Failed to write watch state {}", tmp.display()))?;
+        fs::rename(&tmp, path).with_context(|| format!("Failed to replace watch state {}", path.display()))
+    }
+}
+
+/// Stream change events for `pointers`, polling every `interval`
+pub fn watch(client: &NotionClient, pointers: &[RecordPointer], interval: Duration) -> impl Stream<Item = Result<ChangeEvent>> {
+    Watcher::new(client.clone(), pointers).into_stream(interval)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::endpoints::Operation;
+    use crate::emulator::Emulator;
+    use futures::StreamExt;
+    use serde_json::json;
+
+    fn page(content: &[&str]) -> Value {
+        json!({
+            "id": "p", "type": "page", "version": 1, "alive": true, "parent_id": "root",
+            "properties": { "title": [["Plan"]] }, "content": content,
+        })
+    }
+
+    #[test]
+    fn test_diff_events() {
+        let pointer = RecordPointer::block("p");
+        let old = page(&["a", "b"]);
+        let mut new = page(&["b", "c"]);
+        new["version"] = json!(2);
+        new["parent_id"] = json!("elsewhere");
+        new["alive"] = json!(false);
+        new["properties"]["title"] = json!([["Plan v2"]]);
+
+        let events = diff(&pointer, Some(&old), &new);
+        assert_eq!(
+            events,
+            vec![
+                ChangeEvent::Archived { pointer: pointer.clone() },
+                ChangeEvent::Moved {
+                    pointer: pointer.clone(),
+                    from: Some("root".into()),
+                    to: Some("elsewhere".into())
+                },
+                ChangeEvent::ChildRemoved {
+                    pointer: pointer.clone(),
+                    child_id: "a".into()
+                },
+                ChangeEvent::ChildAdded {
+                    pointer: pointer.clone(),
+                    child_id: "c".into(),
+                    index: 1
+                },
+                ChangeEvent::Updated {
+                    pointer: pointer.clone(),
+                    fields: vec![FieldChange {
+                        path: "properties.title".into(),
+                        old: json!([["Plan"]]),
+                        new: json!([["Plan v2"]]),
+                    }],
+                },
+            ]
+        );
+    }
+
+    async fn emulated_page() -> (crate::emulator::EmulatorHandle, NotionClient) {
+        let mut records = RecordMap::default();
+        records.block.insert("p".into(), Record::new(page(&["a"])));
+        let emulator = Emulator::new("t").with_records(records).start().await.unwrap();
+        let client = emulator.client();
+        (emulator, client)
+    }
+
+    #[tokio::test]
+    async fn test_restart_resumes_from_state_file() {
+        let (_emulator, client) = emulated_page().await;
+        let pointers = [RecordPointer::block("p")];
+        let tmp = tempfile::tempdir().unwrap();
+        let state = tmp.path().join("watch.json");
+
+        let mut watcher = Watcher::new(client.clone(), &pointers).with_state_file(&state).unwrap();
+        assert!(watcher.poll().await.unwrap().is_empty());
+        drop(watcher);
+        assert!(state.exists() && !state.with_extension("tmp").exists());
+
+        client
+            .submit_transaction(vec![Operation::list_after("block", "p", &["content"], "b", Some("a"))])
+            .await
+            .unwrap();
+
+        let mut restarted = Watcher::new(client, &pointers).with_state_file(&state).unwrap();
+        assert_eq!(
+            restarted.poll().await.unwrap(),
+            vec![ChangeEvent::ChildAdded {
+                pointer: RecordPointer::block("p"),
+                child_id: "b".into(),
+                index: 1
+            }]
+        );
+        assert!(restarted.poll().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watch_stream() {
+        let (_emulator, client) = emulated_page().await;
+        let mut events = Box::pin(watch(&client, &[RecordPointer::block("p")], Duration::from_millis(10)));
+
+        let writer = client.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let op = Operation::set("block", "p", &["properties", "title"], json!([["Done"]]));
+            writer.submit_transaction(vec![op]).await.unwrap();
+        });
+
+        let event = events.next().await.unwrap().unwrap();
+        let ChangeEvent::Updated { fields, .. } = event else {
+            panic!("expected an update, got {:?}", event);
+        };
+        assert_eq!(fields[0].path, "properties.title");
+        assert_eq!(fields[0].new, json!([["Done"]]));
+    }
+}
@@ -0,0 +1,608 @@
+//! Webhooks driven by workspace changes
+//!
+//! Notion's internal API has no webhooks, so `Dispatcher` polls the configured
+//! pages and databases with a `Watcher` per hook. Each change event that
+//! passes the hook's filter is POSTed as JSON, signed with HMAC-SHA256 over
+//! the body (`X-Signature-256: sha256=<hex>`, as GitHub does). Failed
+//! deliveries are retried with backoff and finally appended to a dead-letter
+//! file.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use futures::StreamExt;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use tracing::{debug, info, warn};
+
+use crate::api::endpoints::RecordPointer;
+use crate::api::NotionClient;
+use crate::collection::{Collection, QueryBuilder};
+use crate::models::RecordMap;
+use crate::watch::{ChangeEvent, Watcher};
+
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+/// Deliveries in flight at once, across all hooks
+const MAX_CONCURRENT_DELIVERIES: usize = 16;
+
+fn default_interval() -> u64 {
+    30
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_retry_delay() -> u64 {
+    1000
+}
+
+/// Daemon configuration, usually read from a JSON file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+    /// Where each hook keeps its watch state between restarts
+    #[serde(default)]
+    pub state_dir: Option<PathBuf>,
+    pub dead_letter_file: PathBuf,
+    pub hooks: Vec<HookConfig>,
+}
+
+impl WebhookConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let config: Self =
+            serde_json::from_slice(&data).with_context(|| format!("Invalid webhook config {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reject a zero interval, duplicate hook names and any invalid hook
+    pub fn validate(&self) -> Result<()> {
+        if self.interval_secs == 0 {
+            bail!("interval_secs must be at least 1");
+        }
+        let mut names = BTreeSet::new();
+        for hook in &self.hooks {
+            hook.validate()?;
+            if !names.insert(hook.name.as_str()) {
+                bail!("Hook name '{}' is used more than once", hook.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookConfig {
+    /// Used in logs and for the state file name
+    pub name: String,
+    pub url: String,
+    pub secret: String,
+    /// Page block ids; the page and its direct children are watched
+    #[serde(default)]
+    pub pages: Vec<String>,
+    /// Collection ids; every row is watched
+    #[serde(default)]
+    pub databases: Vec<String>,
+    #[serde(default)]
+    pub filter: EventFilter,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each further one
+    #[serde(default = "default_retry_delay")]
+    pub retry_delay_ms: u64,
+}
+
+impl HookConfig {
+    /// Reject hooks that could never deliver anything
+    pub fn validate(&self) -> Result<()> {
+        // The name becomes a file name in `state_dir`
+        if self.name.is_empty() || !self.name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            bail!("Hook name '{}' may only contain letters, digits, '-' and '_'", self.name);
+        }
+        if self.pages.is_empty() && self.databases.is_empty() {
+            bail!("Hook '{}' watches no pages or databases", self.name);
+        }
+        if self.max_attempts == 0 {
+            bail!("Hook '{}' needs at least one delivery attempt", self.name);
+        }
+        Ok(())
+    }
+}
+
+/// Which events a hook receives; empty lists match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventFilter {
+    #[serde(default)]
+    pub block_types: Vec<String>,
+    /// Property names or schema ids; only updates touching one of them pass
+    #[serde(default)]
+    pub properties: Vec<String>,
+    /// User ids of the last editor
+    #[serde(default)]
+    pub authors: Vec<String>,
+}
+
+/// Body POSTed to the hook URL
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub id: String,
+    pub hook: String,
+    pub timestamp: DateTime<Utc>,
+    pub block_type: Option<String>,
+    pub author: Option<String>,
+    pub event: ChangeEvent,
+}
+
+/// A delivery that failed every attempt, one JSON line in the dead-letter file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub url: String,
+    pub attempts: u32,
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
+    pub payload: WebhookPayload,
+}
+
+/// `sha256=<hex>` HMAC of `body` under `secret`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Check a signature header against `body`, in constant time
+pub fn verify(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(hex_digest) = signature.strip_prefix("sha256=") else {
+        return false;
+    };
+    let Ok(digest) = hex::decode(hex_digest) else {
+        return false;
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&digest).is_ok()
+}
+
+struct Hook {
+    config: HookConfig,
+    watcher: Watcher,
+    /// Schema id -> property name, for every watched database
+    property_names: HashMap<String, String>,
+}
+
+/// Polls the configured targets and delivers webhooks
+pub struct Dispatcher {
+    client: NotionClient,
+    http: reqwest::Client,
+    config: WebhookConfig,
+    hooks: Vec<Hook>,
+}
+
+impl Dispatcher {
+    pub fn new(client: NotionClient, config: WebhookConfig) -> Result<Self> {
+        config.validate()?;
+        let mut hooks = Vec::new();
+        for hook in &config.hooks {
+            let pointers: Vec<RecordPointer> = hook.pages.iter().map(|id| RecordPointer::block(id)).collect();
+            let mut watcher = Watcher::new(client.clone(), &pointers);
+            if let Some(dir) = &config.state_dir {
+                watcher = watcher.with_state_file(dir.join(format!("{}.json", hook.name)))?;
+            }
+            hooks.push(Hook {
+                config: hook.clone(),
+                watcher,
+                property_names: HashMap::new(),
+            });
+        }
+
+        Ok(Self {
+            client,
+            http: reqwest::Client::new(),
+            config,
+            hooks,
+        })
+    }
+
+    /// Poll and deliver forever
+    pub async fn run(mut self) -> Result<()> {
+        let mut ticker = tokio::time::interval(Duration::from_secs(self.config.interval_secs));
+        info!("Dispatching {} hook(s) every {}s", self.hooks.len(), self.config.interval_secs);
+        loop {
+            ticker.tick().await;
+            self.tick().await;
+        }
+    }
+
+    /// One round: refresh targets, poll, deliver; returns how many deliveries succeeded
+    ///
+    /// A hook whose targets cannot be read is skipped until the next round.
+    /// Deliveries of every hook run concurrently.
+    pub async fn tick(&mut self) -> usize {
+        let mut pending = Vec::new();
+        for index in 0..self.hooks.len() {
+            match self.poll_hook(index).await {
+                Ok(payloads) => pending.extend(payloads.into_iter().map(|p| (index, p))),
+                Err(e) => warn!("Skipping hook '{}' this round: {:#}", self.hooks[index].config.name, e),
+            }
+        }
+
+        let this = &*self;
+        let results: Vec<bool> = futures::stream::iter(pending)
+            .map(|(index, payload)| async move {
+                let hook = &this.hooks[index].config;
+                match this.deliver(hook, &payload).await {
+                    Ok(()) => true,
+                    Err(e) => {
+                        if let Err(e) = this.dead_letter(hook, payload, e) {
+                            warn!("Failed to write dead letter for hook '{}': {:#}", hook.name, e);
+                        }
+                        false
+                    }
+                }
+            })
+            .buffer_unordered(MAX_CONCURRENT_DELIVERIES)
+            .collect()
+            .await;
+        results.into_iter().filter(|ok| *ok).count()
+    }
+
+    /// Refresh a hook's targets and turn its new events into payloads
+    async fn poll_hook(&mut self, index: usize) -> Result<Vec<WebhookPayload>> {
+        self.expand_targets(index).await?;
+        let events = self.hooks[index].watcher.poll().await?;
+        let hook = &self.hooks[index];
+        Ok(events.into_iter().filter_map(|event| payload_for(hook, event)).collect())
+    }
+
+    /// Add page children and database rows not watched yet
+    ///
+    /// Children added since the last round are picked up here, so they are
+    /// watched from the next poll on.
+    async fn expand_targets(&mut self, index: usize) -> Result<()> {
+        let hook = &self.hooks[index];
+        let mut found = BTreeSet::new();
+
+        for page in &hook.config.pages {
+            let pointer = RecordPointer::block(page);
+            let children = match hook.watcher.value(&pointer) {
+                Some(value) => value.get("content").cloned(),
+                None => self
+                    .client
+                    .get_record_values(&[pointer])
+                    .await?
+                    .into_iter()
+                    .next()
+                    .and_then(|r| r.value.get("content").cloned()),
+            };
+            for child in children.as_ref().and_then(Value::as_array).into_iter().flatten() {
+                if let Some(id) = child.as_str() {
+                    found.insert(RecordPointer::block(id));
+                }
+            }
+        }
+
+        let mut property_names = HashMap::new();
+        for collection_id in &hook.config.databases {
+            let records = self
+                .client
+                .get_record_values(&[RecordPointer::new("collection", collection_id)])
+                .await?;
+            let mut record_map = RecordMap::default();
+            record_map
+                .collection
+                .insert(collection_id.clone(), records.into_iter().next().unwrap_or_default());
+            let collection = Collection::from_record_map(&record_map, collection_id)?;
+            property_names.extend(collection.schema.iter().map(|(id, p)| (id.clone(), p.name.clone())));
+
+            let rows = QueryBuilder::new(&collection).run(&self.client).await?.rows;
+            found.extend(rows.iter().map(|row| RecordPointer::block(&row.id)));
+        }
+
+        let hook = &mut self.hooks[index];
+        hook.property_names.extend(property_names);
+        for pointer in found {
+            hook.watcher.add(pointer);
+        }
+        Ok(())
+    }
+
+    /// POST with retries; the error of the last attempt is returned
+    async fn deliver(&self, hook: &HookConfig, payload: &WebhookPayload) -> Result<()> {
+        let body = serde_json::to_vec(payload)?;
+        let signature = sign(&hook.secret, &body);
+        let mut delay = Duration::from_millis(hook.retry_delay_ms);
+
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .http
+                .post(&hook.url)
+                .header("Content-Type", "application/json")
+                .header(SIGNATURE_HEADER, &signature)
+                .header(DELIVERY_HEADER, &payload.id)
+                .body(body.clone())
+                .send()
+                .await;
+            let error = match result {
+                Ok(resp) if resp.status().is_success() => {
+                    debug!("Delivered {} to {} (attempt {})", payload.id, hook.name, attempt);
+                    return Ok(());
+                }
+                Ok(resp) => anyhow::anyhow!("Receiver answered {}", resp.status()),
+                Err(e) => anyhow::Error::new(e).context("Request failed"),
+            };
+            if attempt >= hook.max_attempts {
+                return Err(error);
+            }
+            warn!("Delivery {} to {} failed ({:#}), retrying in {:?}", payload.id, hook.name, error, delay);
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+
+    fn dead_letter(&self, hook: &HookConfig, payload: WebhookPayload, error: anyhow::Error) -> Result<()> {
+        warn!("Giving up on delivery {} to {}: {:#}", payload.id, hook.name, error);
+        let letter = DeadLetter {
+            url: hook.url.clone(),
+            attempts: hook.max_attempts,
+            error: format!("{:#}", error),
+            failed_at: Utc::now(),
+            payload,
+        };
+        let path = &self.config.dead_letter_file;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open dead-letter file {}", path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(&letter)?)?;
+        Ok(())
+    }
+}
+
+/// Read back a dead-letter file, e.g. to redeliver by hand
+pub fn load_dead_letters<P: AsRef<Path>>(path: P) -> Result<Vec<DeadLetter>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| serde_json::from_str(l).context("Invalid dead-letter line"))
+        .collect()
+}
+
+/// Build the payload for an event, or `None` if the hook's filter drops it
+fn payload_for(hook: &Hook, event: ChangeEvent) -> Option<WebhookPayload> {
+    let pointer = event_pointer(&event);
+    let value = hook.watcher.value(pointer);
+    let str_field = |key: &str| value.and_then(|v| v.get(key)).and_then(Value::as_str).map(str::to_string);
+    let block_type = str_field("type");
+    let author = str_field("last_edited_by_id")
+        .or_else(|| str_field("last_edited_by"))
+        .or_else(|| str_field("created_by_id"));
+
+    let filter = &hook.config.filter;
+    if !filter.block_types.is_empty() && !block_type.as_ref().is_some_and(|t| filter.block_types.contains(t)) {
+        return None;
+    }
+    if !filter.authors.is_empty() && !author.as_ref().is_some_and(|a| filter.authors.contains(a)) {
+        return None;
+    }
+    if !filter.properties.is_empty() {
+        let ChangeEvent::Updated { fields, .. } = &event else {
+            return None;
+        };
+        let touched = fields.iter().filter_map(|f| f.path.strip_prefix("properties.")).any(|id| {
+            let name = hook.property_names.get(id);
+            filter.properties.iter().any(|p| p == id || Some(p) == name)
+        });
+        if !touched {
+            return None;
+        }
+    }
+
+    Some(WebhookPayload {
+        id: uuid::Uuid::new_v4().to_string(),
+        hook: hook.config.name.clone(),
+        timestamp: Utc::now(),
+        block_type,
+        author,
+        event,
+    })
+}
+
+fn event_pointer(event: &ChangeEvent) -> &RecordPointer {
+    match event {
+        ChangeEvent::Created { pointer, .. }
+        | ChangeEvent::Updated { pointer, .. }
+        | ChangeEvent::Moved { pointer, .. }
+        | ChangeEvent::Archived { pointer }
+        | ChangeEvent::Restored { pointer }
+        | ChangeEvent::ChildAdded { pointer, .. }
+        | ChangeEvent::ChildRemoved { pointer, .. } => pointer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::endpoints::Operation;
+    use crate::emulator::Emulator;
+    use crate::models::Record;
+    use axum::body::Bytes;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, Bytes)>>>;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signature = sign("key", b"{}");
+        assert!(verify("key", b"{}", &signature));
+        assert!(!verify("other", b"{}", &signature));
+        assert!(!verify("key", b"{ }", &signature));
+    }
+
+    fn workspace() -> RecordMap {
+        let mut records = RecordMap::default();
+        records.block.insert(
+            "page".into(),
+            Record::new(json!({ "id": "page", "type": "page", "version": 1, "content": ["a"] })),
+        );
+        records.block.insert(
+            "a".into(),
+            Record::new(json!({ "id": "a", "type": "text", "version": 1, "parent_id": "page" })),
+        );
+        records.collection.insert(
+            "coll".into(),
+            Record::new(json!({
+                "id": "coll",
+                "schema": {
+                    "title": { "name": "Name", "type": "title" },
+                    "st": { "name": "Status", "type": "select" },
+                },
+            })),
+        );
+        records.block.insert(
+            "row".into(),
+            Record::new(json!({
+                "id": "row", "type": "page", "version": 1, "parent_id": "coll", "parent_table": "collection",
+                "properties": { "title": [["Task"]], "st": [["Todo"]] },
+            })),
+        );
+        records
+    }
+
+    async fn receiver() -> (String, Received) {
+        async fn accept(State(received): State<Received>, headers: HeaderMap, body: Bytes) -> StatusCode {
+            let mut received = received.lock().unwrap();
+            received.push((headers, body));
+            // Fail the very first delivery to exercise the retry
+            if received.len() == 1 {
+                StatusCode::INTERNAL_SERVER_ERROR
+            } else {
+                StatusCode::OK
+            }
+        }
+        let received = Received::default();
+        let app = Router::new()
+            .route("/hook", post(accept))
+            .route("/broken", post(|| async { StatusCode::BAD_GATEWAY }))
+            .with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (base, received)
+    }
+
+    fn hook(name: &str, url: String, filter: EventFilter) -> HookConfig {
+        HookConfig {
+            name: name.to_string(),
+            url,
+            secret: "s3cret".to_string(),
+            pages: vec!["page".to_string()],
+            databases: vec!["coll".to_string()],
+            filter,
+            max_attempts: 2,
+            retry_delay_ms: 10,
+        }
+    }
+
+    #[test]
+    fn test_hook_names_validated() {
+        let config = |names: &[&str]| WebhookConfig {
+            interval_secs: 1,
+            state_dir: None,
+            dead_letter_file: PathBuf::from("dead.jsonl"),
+            hooks: names.iter().map(|n| hook(n, "http://localhost/hook".into(), EventFilter::default())).collect(),
+        };
+        assert!(config(&["status", "status-2"]).validate().is_ok());
+        for bad in ["", "../escape", "a/b", ".."] {
+            assert!(config(&[bad]).validate().is_err(), "{:?} accepted", bad);
+        }
+        assert!(config(&["status", "status"]).validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_filtered_signed_delivery_with_dead_letters() {
+        let emulator = Emulator::new("t").with_records(workspace()).start().await.unwrap();
+        let client = emulator.client();
+        let (base, received) = receiver().await;
+        let tmp = tempfile::tempdir().unwrap();
+        let dead_letters = tmp.path().join("dead.jsonl");
+
+        let status_only = EventFilter {
+            properties: vec!["Status".to_string()],
+            ..Default::default()
+        };
+        let missing = HookConfig {
+            pages: Vec::new(),
+            databases: vec!["missing".to_string()],
+            ..hook("missing", format!("{}/hook", base), EventFilter::default())
+        };
+        let mut config = WebhookConfig {
+            interval_secs: 0,
+            state_dir: None,
+            dead_letter_file: dead_letters.clone(),
+            hooks: vec![
+                missing,
+                hook("status", format!("{}/hook", base), status_only),
+                hook("broken", format!("{}/broken", base), EventFilter::default()),
+            ],
+        };
+        assert!(Dispatcher::new(client.clone(), config.clone()).is_err());
+        config.interval_secs = 1;
+        let mut dispatcher = Dispatcher::new(client.clone(), config).unwrap();
+        assert_eq!(dispatcher.tick().await, 0);
+
+        client
+            .submit_transaction(vec![
+                Operation::set("block", "row", &["properties", "st"], json!([["Done"]])),
+                Operation::set("block", "row", &["properties", "title"], json!([["Task!"]])),
+                Operation::list_after("block", "page", &["content"], "b", Some("a")),
+            ])
+            .await
+            .unwrap();
+        // The missing database does not keep the other hooks from delivering
+        assert_eq!(dispatcher.tick().await, 1);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2, "one failed attempt and one retry");
+        let (headers, body) = &received[1];
+        assert!(verify("s3cret", body, headers[SIGNATURE_HEADER].to_str().unwrap()));
+        let payload: WebhookPayload = serde_json::from_slice(body).unwrap();
+        assert_eq!(payload.block_type.as_deref(), Some("page"));
+        let ChangeEvent::Updated { fields, .. } = payload.event else {
+            panic!("expected an update");
+        };
+        assert!(fields.iter().any(|f| f.path == "properties.st"));
+
+        // The unfiltered hook saw the row update and the new child, and failed both
+        let letters = load_dead_letters(&dead_letters).unwrap();
+        assert_eq!(letters.len(), 2);
+        assert!(letters.iter().all(|l| l.attempts == 2 && l.error.contains("502")));
+    }
+}